@@ -0,0 +1,6 @@
+ALTER TABLE submission
+  DROP COLUMN compilation_exit_code,
+  DROP COLUMN compilation_time_ms,
+  DROP COLUMN compilation_memory_kib,
+  DROP COLUMN compilation_stdout,
+  DROP COLUMN compilation_stderr
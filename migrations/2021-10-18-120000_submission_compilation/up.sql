@@ -0,0 +1,6 @@
+ALTER TABLE submission
+  ADD COLUMN compilation_exit_code integer null,
+  ADD COLUMN compilation_time_ms integer null,
+  ADD COLUMN compilation_memory_kib integer null,
+  ADD COLUMN compilation_stdout text null,
+  ADD COLUMN compilation_stderr text null
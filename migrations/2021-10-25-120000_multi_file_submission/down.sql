@@ -0,0 +1,4 @@
+DROP TABLE submission_file;
+
+ALTER TABLE problem
+  DROP COLUMN grader_paths
@@ -0,0 +1,9 @@
+ALTER TABLE problem
+  ADD COLUMN grader_paths text[] not null default '{}';
+
+CREATE TABLE submission_file (
+  submission_uuid text references submission(uuid) not null,
+  name text not null,
+  text text not null,
+  primary key (submission_uuid, name)
+)
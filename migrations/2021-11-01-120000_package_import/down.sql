@@ -0,0 +1,3 @@
+DROP TABLE package_import_test;
+DROP TABLE package_import_problem;
+DROP TABLE package_import
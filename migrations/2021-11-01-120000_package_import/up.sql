@@ -0,0 +1,26 @@
+CREATE TABLE package_import (
+  uuid text primary key not null,
+  status text not null,
+  contest_id integer references contest(id) null,
+  creation_user_id integer references "user"(id) not null,
+  creation_instant timestamp not null,
+  end_instant timestamp null
+);
+
+CREATE TABLE package_import_problem (
+  id serial primary key,
+  package_import_uuid text references package_import(uuid) not null,
+  name text not null,
+  label text null,
+  problem_id text null,
+  status text not null,
+  message text null
+);
+
+CREATE TABLE package_import_test (
+  package_import_problem_id integer references package_import_problem(id) not null,
+  test integer not null,
+  status text not null,
+  message text null,
+  primary key (package_import_problem_id, test)
+)
@@ -0,0 +1,8 @@
+CREATE TABLE problem_test (
+  problem_id text references problem(id) not null,
+  test integer not null,
+  validation_status text not null,
+  validation_message text null,
+  validation_instant timestamp not null,
+  primary key (problem_id, test)
+)
@@ -0,0 +1,9 @@
+CREATE TABLE package_import_solution (
+  package_import_problem_id integer references package_import_problem(id) not null,
+  path text not null,
+  tag text not null,
+  status text not null,
+  verdict text null,
+  message text null,
+  primary key (package_import_problem_id, path)
+)
@@ -0,0 +1 @@
+DROP TABLE package_import_self_test
@@ -0,0 +1,10 @@
+CREATE TABLE package_import_self_test (
+  package_import_problem_id integer references package_import_problem(id) not null,
+  program text not null,
+  test integer not null,
+  expected text not null,
+  verdict text null,
+  status text not null,
+  message text null,
+  primary key (package_import_problem_id, program, test)
+)
@@ -0,0 +1,17 @@
+ALTER TABLE contest
+  DROP COLUMN contest_testset,
+  DROP COLUMN system_testset;
+
+DELETE FROM package_import_test WHERE testset <> 'tests';
+ALTER TABLE package_import_test
+  DROP CONSTRAINT package_import_test_pkey,
+  DROP COLUMN testset,
+  ADD PRIMARY KEY (package_import_problem_id, test);
+
+DELETE FROM problem_test WHERE testset <> 'tests';
+ALTER TABLE problem_test
+  DROP CONSTRAINT problem_test_pkey,
+  DROP COLUMN testset,
+  ADD PRIMARY KEY (problem_id, test);
+
+DROP TABLE problem_testset
@@ -0,0 +1,26 @@
+CREATE TABLE problem_testset (
+  problem_id text references problem(id) not null,
+  name text not null,
+  time_limit_ms integer not null,
+  memory_limit_bytes integer not null,
+  test_count integer not null,
+  test_pattern text not null,
+  primary key (problem_id, name)
+);
+
+INSERT INTO problem_testset
+  SELECT id, 'tests', time_limit_ms, memory_limit_bytes, test_count, test_pattern FROM problem;
+
+ALTER TABLE problem_test
+  ADD COLUMN testset text not null default 'tests',
+  DROP CONSTRAINT problem_test_pkey,
+  ADD PRIMARY KEY (problem_id, testset, test);
+
+ALTER TABLE package_import_test
+  ADD COLUMN testset text not null default 'tests',
+  DROP CONSTRAINT package_import_test_pkey,
+  ADD PRIMARY KEY (package_import_problem_id, testset, test);
+
+ALTER TABLE contest
+  ADD COLUMN contest_testset text not null default 'tests',
+  ADD COLUMN system_testset text not null default 'tests'
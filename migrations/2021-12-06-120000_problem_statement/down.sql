@@ -0,0 +1,4 @@
+ALTER TABLE "user"
+  DROP COLUMN statement_language;
+
+DROP TABLE problem_statement
@@ -0,0 +1,15 @@
+CREATE TABLE problem_statement (
+  problem_id text references problem(id) not null,
+  language text not null,
+  mime_type text not null,
+  path text not null,
+  charset text null,
+  mathjax boolean not null,
+  primary key (problem_id, language, mime_type)
+);
+
+INSERT INTO problem_statement
+  SELECT id, 'portuguese', 'text/html', 'statements/.html/portuguese/problem.html', 'UTF-8', true FROM problem;
+
+ALTER TABLE "user"
+  ADD COLUMN statement_language text null
@@ -0,0 +1,2 @@
+ALTER TABLE problem_test
+  DROP COLUMN sample
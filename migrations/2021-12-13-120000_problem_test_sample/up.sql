@@ -0,0 +1,2 @@
+ALTER TABLE problem_test
+  ADD COLUMN sample boolean not null default false
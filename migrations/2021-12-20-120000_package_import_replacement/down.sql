@@ -0,0 +1,3 @@
+ALTER TABLE package_import_problem
+  DROP COLUMN contest_problem_id,
+  DROP COLUMN previous_problem_id
@@ -0,0 +1,3 @@
+ALTER TABLE package_import_problem
+  ADD COLUMN contest_problem_id integer references contest_problems(id) null,
+  ADD COLUMN previous_problem_id text references problem(id) null
@@ -0,0 +1,3 @@
+ALTER TABLE problem
+  DROP COLUMN package_format,
+  DROP COLUMN checker_arguments
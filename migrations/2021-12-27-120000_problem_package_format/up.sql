@@ -0,0 +1,3 @@
+ALTER TABLE problem
+  ADD COLUMN package_format text not null default 'polygon',
+  ADD COLUMN checker_arguments text[] not null default '{}'
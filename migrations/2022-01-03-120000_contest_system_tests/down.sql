@@ -0,0 +1,2 @@
+ALTER TABLE contest
+  DROP COLUMN system_tests_instant
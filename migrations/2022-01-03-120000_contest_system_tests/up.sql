@@ -0,0 +1,2 @@
+ALTER TABLE contest
+  ADD COLUMN system_tests_instant timestamp
@@ -2,46 +2,40 @@ use actix_web::rt::time::{interval_at, Instant};
 use actix_web::web::{Bytes, Data};
 use actix_web::Error;
 use futures::Stream;
+use std::collections::HashMap;
 use std::pin::Pin;
 use std::sync::Mutex;
 use std::task::{Context, Poll};
 use std::time::Duration;
 use tokio::sync::mpsc::{channel, Receiver, Sender};
-use tokio::sync::broadcast;
-use crate::queue::job_protocol::{JobResult, job_result};
+
+struct BroadcastClient {
+    sender: Sender<Bytes>,
+    user_id: Option<i32>,
+    is_admin: bool,
+}
 
 pub struct Broadcaster {
-    clients: Vec<Sender<Bytes>>,
+    clients: Vec<BroadcastClient>,
+    /// Who submitted each job being judged, the only one besides the admins
+    /// to see its progress.
+    job_owners: HashMap<String, i32>,
 }
 
 impl Broadcaster {
-    pub fn create(job_result_receiver: broadcast::Receiver<JobResult>) -> Data<Mutex<Self>> {
+    pub fn create() -> Data<Mutex<Self>> {
         let me = Data::new(Mutex::new(Broadcaster::new()));
         Broadcaster::spawn_ping(me.clone());
-        Broadcaster::spawn_receiver(me.clone(), job_result_receiver);
         me
     }
 
     pub fn new() -> Self {
         Broadcaster {
             clients: Vec::new(),
+            job_owners: HashMap::new(),
         }
     }
 
-    fn spawn_receiver(me: Data<Mutex<Self>>, mut job_result_receiver: broadcast::Receiver<JobResult>) {
-        actix_web::rt::spawn(async move {
-            loop {
-                let job_result = job_result_receiver.recv().await.unwrap();
-                if let JobResult {
-                    which: Some(job_result::Which::Judgement(_judgement)),
-                    ..
-                } = job_result {
-                    me.lock().unwrap().send("update_submission", "");
-                }
-            }
-        });
-    }
-
     fn spawn_ping(me: Data<Mutex<Self>>) {
         actix_web::rt::spawn(async move {
             let mut task = interval_at(Instant::now(), Duration::from_secs(3));
@@ -54,24 +48,28 @@ impl Broadcaster {
 
     fn remove_stale_clients(&mut self) {
         let mut ok_clients = Vec::new();
-        for client in self.clients.iter() {
-            let result = client.clone().try_send(Bytes::from("data: ping\n\n"));
+        for client in self.clients.drain(..) {
+            let result = client.sender.clone().try_send(Bytes::from("data: ping\n\n"));
 
             if let Ok(()) = result {
-                ok_clients.push(client.clone());
+                ok_clients.push(client);
             }
         }
         self.clients = ok_clients;
     }
 
-    pub fn new_client(&mut self) -> Client {
+    pub fn new_client(&mut self, user_id: Option<i32>, is_admin: bool) -> Client {
         let (tx, rx) = channel(100);
 
         tx.clone()
             .try_send(Bytes::from("data: connected\n\n"))
             .unwrap();
 
-        self.clients.push(tx);
+        self.clients.push(BroadcastClient {
+            sender: tx,
+            user_id,
+            is_admin,
+        });
         Client(rx)
     }
 
@@ -79,7 +77,29 @@ impl Broadcaster {
         let msg = Bytes::from(["event:", event, "\n", "data: ", msg, "\n\n"].concat());
 
         for client in self.clients.iter() {
-            client.clone().try_send(msg.clone()).unwrap_or(());
+            client.sender.clone().try_send(msg.clone()).unwrap_or(());
+        }
+    }
+
+    pub fn watch_job(&mut self, uuid: &str, user_id: i32) {
+        self.job_owners.insert(uuid.into(), user_id);
+    }
+
+    pub fn forget_job(&mut self, uuid: &str) {
+        self.job_owners.remove(uuid);
+    }
+
+    /// Sends the progress of a watched job to its owner and the admins,
+    /// dropping that of jobs nobody shows.
+    pub fn send_job_progress(&self, uuid: &str, msg: &str) {
+        let owner = match self.job_owners.get(uuid) {
+            Some(owner) => *owner,
+            None => return,
+        };
+        let msg = Bytes::from(["event:progress_", uuid, "\n", "data: ", msg, "\n\n"].concat());
+
+        for client in self.clients.iter().filter(|client| client.is_admin || client.user_id == Some(owner)) {
+            client.sender.clone().try_send(msg.clone()).unwrap_or(());
         }
     }
 }
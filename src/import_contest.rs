@@ -4,6 +4,11 @@ use std::io::{Read, Seek};
 use zip::ZipArchive;
 use regex::Captures;
 
+pub mod clics;
+pub mod generator_script;
+pub mod kattis;
+pub mod statement_sections;
+
 mod error {
     use quick_xml::de::DeError;
     use std::io;
@@ -18,6 +23,14 @@ mod error {
         XmlDecode(#[from] DeError),
         #[error(transparent)]
         Io(#[from] io::Error),
+        #[error(transparent)]
+        YamlDecode(#[from] serde_yaml::Error),
+        #[error("test {0} has no answer")]
+        MissingAnswer(String),
+        #[error("no problem.xml or problem.yaml in the package")]
+        UnknownFormat,
+        #[error("problem {0} isn't in the contest package")]
+        MissingProblem(String),
     }
 }
 
@@ -152,6 +165,13 @@ mod xml {
             #[serde(rename = "answer-path-pattern")]
             pub answer_path_pattern: AnswerPathPattern,
             pub tests: Tests,
+            pub script: Option<Script>,
+        }
+
+        #[derive(Deserialize, Debug)]
+        pub struct Script {
+            #[serde(rename = "$value")]
+            pub value: String,
         }
 
         #[derive(Deserialize, Debug)]
@@ -195,6 +215,8 @@ mod xml {
             pub sample: Option<bool>,
             pub description: Option<String>,
             pub cmd: Option<String>,
+            #[serde(rename = "from-file")]
+            pub from_file: Option<String>,
         }
 
         #[derive(Deserialize, Debug)]
@@ -211,6 +233,27 @@ mod xml {
         #[derive(Deserialize, Debug)]
         pub struct File {
             pub path: String,
+            pub r#type: Option<String>,
+            pub assets: Option<FileAssets>,
+        }
+
+        #[derive(Deserialize, Debug)]
+        pub struct FileAssets {
+            pub asset: Vec<FileAsset>,
+        }
+
+        #[derive(Deserialize, Debug)]
+        pub struct FileAsset {
+            pub name: String,
+        }
+
+        impl File {
+            /// Resources compiled together with every solution, as graders are
+            pub fn is_grader(&self) -> bool {
+                self.assets
+                    .as_ref()
+                    .is_some_and(|assets| assets.asset.iter().any(|asset| asset.name == "solution"))
+            }
         }
 
         #[derive(Deserialize, Debug)]
@@ -259,11 +302,19 @@ mod xml {
             pub test_count: TestCount,
             #[serde(rename = "input-path-pattern")]
             pub input_path_pattern: InputPathPattern,
+            #[serde(rename = "output-path-pattern")]
+            pub output_path_pattern: Option<OutputPathPattern>,
             #[serde(rename = "answer-path-pattern")]
             pub answer_path_pattern: AnswerPathPattern,
             pub tests: VerdictTests,
         }
 
+        #[derive(Deserialize, Debug)]
+        pub struct OutputPathPattern {
+            #[serde(rename = "$value")]
+            pub value: String,
+        }
+
         #[derive(Deserialize, Debug)]
         pub struct Copy {
             pub path: String,
@@ -368,15 +419,10 @@ pub use xml::problem::Problem;
 
 pub fn import_file<R: Read + Seek>(
     reader: R,
-) -> Result<(Contest, Vec<(String, Problem)>, ZipArchive<R>, String), ImportContestError> {
-    let mut report = String::new();
-
+) -> Result<(Contest, Vec<(String, Problem)>, ZipArchive<R>), ImportContestError> {
     let mut zip = ZipArchive::new(reader)?;
     let contest = xml::contest::get_from_zip(&mut zip)?;
 
-    report.push_str(&format!("{:#?}", contest));
-    report.push_str("\n---\n");
-
     let mut problems: Vec<(String, Problem)> = Vec::new();
 
     lazy_static! {
@@ -390,15 +436,7 @@ pub fn import_file<R: Read + Seek>(
         .map(|s| s.into())
         .collect::<Vec<String>>()
     {
-        report.push_str(&name);
         let problem = xml::problem::get_from_zip(&mut zip, &name)?;
-        report.push_str("\n---\n");
-        report.push_str(&format!("{:#?}", problem));
-        report.push_str("\n---\n");
-        report.push_str(&read_string_from_zip_by_name(&mut zip, &name)?);
-        report.push_str("---\n");
-        report.push_str("\n");
-
         problems.push((
             PROBLEM_XML_PATH_REGEX
                 .captures(&name)
@@ -411,7 +449,78 @@ pub fn import_file<R: Read + Seek>(
         ));
     }
 
-    Ok((contest, problems, zip, report))
+    Ok((contest, problems, zip))
+}
+
+/// A problem package in one of the formats we import.
+pub enum ProblemPackage {
+    Polygon(Box<Problem>),
+    Kattis(Box<kattis::Problem>),
+}
+
+impl ProblemPackage {
+    pub fn name(&self) -> String {
+        match self {
+            ProblemPackage::Polygon(problem) => problem
+                .names
+                .name
+                .first()
+                .map(|name| name.value.clone())
+                .unwrap_or(problem.short_name.clone()),
+            ProblemPackage::Kattis(problem) => problem.name.clone(),
+        }
+    }
+}
+
+/// Where the `problem.yaml` of a Kattis package is, at the root of the zip
+/// or in its only directory.
+fn kattis_prefix<R: Read + Seek>(zip: &mut ZipArchive<R>) -> Option<String> {
+    if zip.by_name("problem.yaml").is_ok() {
+        return Some("".into());
+    }
+    let mut prefixes = zip
+        .file_names()
+        .filter_map(|name| name.strip_suffix("problem.yaml"))
+        .filter(|prefix| prefix.matches('/').count() == 1 && prefix.ends_with('/'))
+        .map(String::from)
+        .collect::<Vec<_>>();
+    if prefixes.len() != 1 {
+        return None;
+    }
+    Some(prefixes.remove(0))
+}
+
+/// Kattis names problems after their directory, or the zip when the package
+/// is at its root.
+fn kattis_short_name(name: &str) -> String {
+    name.trim_end_matches('/')
+        .trim_end_matches(".zip")
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '-')
+        .collect()
+}
+
+/// Reads a single problem package, either Polygon's with its `problem.xml`
+/// at the root or Kattis' with its `problem.yaml` at the root or in a
+/// directory, returning where in the zip the package is.
+pub fn import_problem_file<R: Read + Seek>(
+    reader: R,
+    file_name: &str,
+) -> Result<(String, ProblemPackage, ZipArchive<R>), ImportContestError> {
+    let mut zip = ZipArchive::new(reader)?;
+    if zip.by_name("problem.xml").is_ok() {
+        let problem = xml::problem::get_from_zip(&mut zip, "problem.xml")?;
+        return Ok(("".into(), ProblemPackage::Polygon(Box::new(problem)), zip));
+    }
+
+    let prefix = kattis_prefix(&mut zip).ok_or(ImportContestError::UnknownFormat)?;
+    let short_name = kattis_short_name(if prefix.is_empty() { file_name } else { prefix.as_str() });
+    let problem = kattis::read_problem(&mut zip, &prefix, &short_name)?;
+    Ok((prefix, ProblemPackage::Kattis(Box::new(problem)), zip))
 }
 
 pub fn format_width(pattern_path: &String, i: usize) -> String {
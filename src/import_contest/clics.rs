@@ -0,0 +1,299 @@
+//! CLICS contest packages, as DOMjudge exports them: `contest.yaml`, the
+//! problems and their labels in `problemset.yaml` (or `problems.yaml`) and
+//! each problem as a Kattis package, in a directory or in a zip of its own.
+
+use super::{kattis, kattis_prefix, ImportContestError, ProblemPackage};
+use chrono::Duration;
+use serde::Deserialize;
+use std::io::{Cursor, Read, Seek, Write};
+use zip::write::{FileOptions, ZipWriter};
+use zip::ZipArchive;
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Contest {
+    pub name: Option<String>,
+    #[serde(alias = "formal-name")]
+    pub formal_name: Option<String>,
+    /// As `2021-10-30T13:00:00-03:00`
+    #[serde(alias = "start-time")]
+    pub start_time: Option<String>,
+    /// As `5:00:00`
+    pub duration: Option<String>,
+}
+
+impl Contest {
+    pub fn duration(&self) -> Option<Duration> {
+        let mut parts = self.duration.as_deref()?.trim().split(':');
+        let hours: i64 = parts.next()?.parse().ok()?;
+        let minutes: i64 = parts.next()?.parse().ok()?;
+        let seconds: f64 = parts.next().unwrap_or("0").parse().ok()?;
+        if parts.next().is_some() || minutes >= 60 || !(0.0..60.0).contains(&seconds) {
+            return None;
+        }
+        Some(Duration::hours(hours) + Duration::minutes(minutes) + Duration::seconds(seconds as i64))
+    }
+}
+
+/// Older exports write `letter` and `short-name`, newer ones `label` and `id`.
+#[derive(Deserialize, Debug)]
+pub struct ContestProblem {
+    pub letter: Option<String>,
+    pub label: Option<String>,
+    #[serde(rename = "short-name")]
+    pub short_name: Option<String>,
+    pub id: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum ProblemList {
+    Problemset { problems: Vec<ContestProblem> },
+    Problems(Vec<ContestProblem>),
+}
+
+fn read_yaml<T: for<'de> Deserialize<'de>, R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    name: &str,
+) -> Result<Option<T>, ImportContestError> {
+    let mut content = String::new();
+    match zip.by_name(name) {
+        Ok(mut file) => file.read_to_string(&mut content)?,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    // An empty file is as good as a missing one
+    if content.trim().is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(serde_yaml::from_str(&content)?))
+}
+
+/// Where the package of a problem is in the zip: its directory, or a zip
+/// of its own.
+enum ProblemSource {
+    Directory(String),
+    Zip(String),
+}
+
+fn find_problem<R: Read + Seek>(zip: &ZipArchive<R>, short_name: &str) -> Option<ProblemSource> {
+    let names: Vec<&str> = zip.file_names().collect();
+    for directory in &["", "problems/"] {
+        let prefix = format!("{}{}/", directory, short_name);
+        if names.contains(&format!("{}problem.yaml", prefix).as_str()) {
+            return Some(ProblemSource::Directory(prefix));
+        }
+        let name = format!("{}{}.zip", directory, short_name);
+        if names.contains(&name.as_str()) {
+            return Some(ProblemSource::Zip(name));
+        }
+    }
+    None
+}
+
+/// Copies the files of a problem under `{short_name}/` in the new zip, with
+/// the package at the root of its zip or in a directory of it.
+fn copy_problem<R: Read + Seek, W: Write + Seek>(
+    zip: &mut ZipArchive<R>,
+    prefix: &str,
+    writer: &mut ZipWriter<W>,
+    short_name: &str,
+) -> Result<(), ImportContestError> {
+    let names: Vec<String> = zip
+        .file_names()
+        .filter(|name| name.starts_with(prefix) && !name.ends_with('/'))
+        .map(String::from)
+        .collect();
+    for name in names {
+        writer.start_file(format!("{}/{}", short_name, &name[prefix.len()..]), FileOptions::default())?;
+        std::io::copy(&mut zip.by_name(&name)?, writer)?;
+    }
+    Ok(())
+}
+
+/// The prefix of a problem in the contest's zip, its package and its label.
+pub type LabelledPackage = (String, ProblemPackage, Option<String>);
+
+/// The contest, its problems and the zip holding them all.
+pub type ClicsContest = (Contest, Vec<LabelledPackage>, ZipArchive<Cursor<Vec<u8>>>);
+
+/// Reads a CLICS contest package, putting every problem under
+/// `{short_name}/` of a single zip as the Polygon contests have theirs.
+pub fn read_contest<R: Read + Seek>(
+    reader: R,
+) -> Result<ClicsContest, ImportContestError> {
+    let mut zip = ZipArchive::new(reader)?;
+    let contest: Contest = read_yaml(&mut zip, "contest.yaml")?.unwrap_or_default();
+    let problems = match read_yaml::<ProblemList, _>(&mut zip, "problemset.yaml")? {
+        Some(problems) => problems,
+        None => read_yaml(&mut zip, "problems.yaml")?.ok_or(ImportContestError::UnknownFormat)?,
+    };
+    let problems = match problems {
+        ProblemList::Problemset { problems } => problems,
+        ProblemList::Problems(problems) => problems,
+    };
+
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let mut labelled = Vec::new();
+    for (i, problem) in problems.iter().enumerate() {
+        let short_name = problem
+            .short_name
+            .clone()
+            .or(problem.id.clone())
+            .ok_or(ImportContestError::UnknownFormat)?;
+        // Unlabelled problems go in the order they're listed, as A, B, ...
+        let label = problem
+            .label
+            .clone()
+            .or(problem.letter.clone())
+            .unwrap_or_else(|| ((b'A' + (i % 26) as u8) as char).to_string())
+            .to_uppercase();
+
+        match find_problem(&zip, &short_name) {
+            Some(ProblemSource::Directory(prefix)) => {
+                copy_problem(&mut zip, &prefix, &mut writer, &short_name)?
+            }
+            Some(ProblemSource::Zip(name)) => {
+                let mut content = Vec::new();
+                zip.by_name(&name)?.read_to_end(&mut content)?;
+                let mut problem_zip = ZipArchive::new(Cursor::new(content))?;
+                let prefix = kattis_prefix(&mut problem_zip).ok_or(ImportContestError::UnknownFormat)?;
+                copy_problem(&mut problem_zip, &prefix, &mut writer, &short_name)?
+            }
+            None => return Err(ImportContestError::MissingProblem(short_name)),
+        }
+        labelled.push((short_name, label));
+    }
+
+    let mut zip = ZipArchive::new(writer.finish()?)?;
+    let mut packages = Vec::new();
+    for (short_name, label) in labelled {
+        let prefix = format!("{}/", short_name);
+        let problem = kattis::read_problem(&mut zip, &prefix, &short_name)?;
+        packages.push((prefix, ProblemPackage::Kattis(Box::new(problem)), Some(label)));
+    }
+    Ok((contest, packages, zip))
+}
+
+/// Whether the zip is a CLICS contest package instead of a Polygon one.
+pub fn is_clics_file<R: Read + Seek>(reader: R) -> bool {
+    let clics = match ZipArchive::new(reader) {
+        Ok(zip) => {
+            let mut names = zip.file_names();
+            names.any(|name| name == "problemset.yaml" || name == "problems.yaml")
+        }
+        Err(_) => false,
+    };
+    clics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn duration(duration: &str) -> Option<Duration> {
+        Contest {
+            duration: Some(duration.into()),
+            ..Default::default()
+        }
+        .duration()
+    }
+
+    fn package(files: &[(&str, &[u8])]) -> Cursor<Vec<u8>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in files {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(content).unwrap();
+        }
+        let mut reader = writer.finish().unwrap();
+        reader.set_position(0);
+        reader
+    }
+
+    fn labels(packages: &[LabelledPackage]) -> Vec<(&str, Option<&str>)> {
+        packages
+            .iter()
+            .map(|(prefix, _, label)| (prefix.as_str(), label.as_deref()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(duration("5:00:00"), Some(Duration::hours(5)));
+        assert_eq!(duration(" 2:30:15 "), Some(Duration::seconds(2 * 3600 + 30 * 60 + 15)));
+        assert_eq!(duration("1:30:00.000"), Some(Duration::minutes(90)));
+        assert_eq!(duration("0:45"), Some(Duration::minutes(45)));
+        assert_eq!(duration("27:00:00"), Some(Duration::hours(27)));
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        for invalid in ["", "5", "5h", "5:xx:00", "1:00:00:00", "1:60:00", "1:00:60", "-"] {
+            assert_eq!(duration(invalid), None, "{}", invalid);
+        }
+        assert_eq!(Contest::default().duration(), None);
+    }
+
+    #[test]
+    fn reads_contests_with_missing_fields() {
+        let reader = package(&[
+            ("contest.yaml", b"formal-name: Regional\n"),
+            ("problemset.yaml", b"problems:\n  - short-name: sum\n"),
+            ("sum/problem.yaml", b""),
+        ]);
+        let (contest, packages, _) = read_contest(reader).unwrap();
+        assert_eq!(contest.name, None);
+        assert_eq!(contest.formal_name.as_deref(), Some("Regional"));
+        assert_eq!(contest.start_time, None);
+        assert_eq!(contest.duration(), None);
+        assert_eq!(labels(&packages), vec![("sum/", Some("A"))]);
+    }
+
+    #[test]
+    fn reads_contests_without_contest_yaml() {
+        let reader = package(&[("problemset.yaml", b"")]);
+        assert!(matches!(read_contest(reader), Err(ImportContestError::UnknownFormat)));
+
+        let reader = package(&[
+            ("contest.yaml", b""),
+            ("problems.yaml", b"- id: a\n  label: x\n- id: b\n"),
+            ("problems/a/problem.yaml", b"name: A\n"),
+            ("problems/b/problem.yaml", b"name: B\n"),
+        ]);
+        let (contest, packages, _) = read_contest(reader).unwrap();
+        assert_eq!(contest.name, None);
+        assert_eq!(labels(&packages), vec![("a/", Some("X")), ("b/", Some("B"))]);
+    }
+
+    #[test]
+    fn reads_problems_in_zips_of_their_own() {
+        let problem = package(&[
+            ("pkg/problem.yaml", b"name: Sum\n"),
+            ("pkg/data/sample/1.in", b"1 2\n"),
+            ("pkg/data/sample/1.ans", b"3\n"),
+        ]);
+        let reader = package(&[
+            ("problemset.yaml", b"problems:\n  - short-name: sum\n    letter: C\n"),
+            ("sum.zip", &problem.into_inner()),
+        ]);
+        let (_, packages, mut zip) = read_contest(reader).unwrap();
+        assert_eq!(labels(&packages), vec![("sum/", Some("C"))]);
+        assert!(zip.by_name("sum/data/sample/1.in").is_ok());
+        match &packages[0].1 {
+            ProblemPackage::Kattis(problem) => assert_eq!(problem.name, "Sum"),
+            _ => panic!("Expected a Kattis package"),
+        }
+    }
+
+    #[test]
+    fn requires_the_problems() {
+        let reader = package(&[("problemset.yaml", b"problems:\n  - label: A\n")]);
+        assert!(matches!(read_contest(reader), Err(ImportContestError::UnknownFormat)));
+
+        let reader = package(&[("problemset.yaml", b"problems:\n  - short-name: gone\n")]);
+        assert!(matches!(read_contest(reader), Err(ImportContestError::MissingProblem(name)) if name == "gone"));
+
+        let reader = package(&[("contest.yaml", b"name: Nothing\n")]);
+        assert!(matches!(read_contest(reader), Err(ImportContestError::UnknownFormat)));
+        assert!(!is_clics_file(package(&[("contest.yaml", b"name: Nothing\n")])));
+    }
+}
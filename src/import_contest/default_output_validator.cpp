@@ -0,0 +1,146 @@
+// Kattis' default output validator: compares the output with the answer
+// token by token, numbers within a tolerance when asked to.
+//
+// Usage: validator input answer feedback_dir [case_sensitive]
+//     [space_change_sensitive] [float_tolerance e]
+//     [float_absolute_tolerance e] [float_relative_tolerance e] < output
+//
+// Exits with 42 when accepted and 43 otherwise, explaining why in
+// feedback_dir/judgemessage.txt.
+
+#include <cctype>
+#include <cmath>
+#include <cstdio>
+#include <cstdlib>
+#include <cstring>
+#include <fstream>
+#include <iostream>
+#include <sstream>
+#include <string>
+
+static std::ofstream judge_message;
+
+static void accept() {
+    std::exit(42);
+}
+
+static void reject(const std::string& message) {
+    judge_message << message << std::endl;
+    std::exit(43);
+}
+
+// A whitespace or non whitespace run, whitespace only being read when it matters
+static bool next_token(std::istream& in, bool space_change_sensitive, std::string& token) {
+    token.clear();
+    int c = in.peek();
+    if (c == EOF) {
+        return false;
+    }
+    if (std::isspace(c)) {
+        if (!space_change_sensitive) {
+            while (in.peek() != EOF && std::isspace(in.peek())) {
+                in.get();
+            }
+            return next_token(in, space_change_sensitive, token);
+        }
+        while (in.peek() != EOF && std::isspace(in.peek())) {
+            token += (char) in.get();
+        }
+        return true;
+    }
+    while (in.peek() != EOF && !std::isspace(in.peek())) {
+        token += (char) in.get();
+    }
+    return true;
+}
+
+static bool parse_number(const std::string& token, double& value) {
+    if (token.empty()) {
+        return false;
+    }
+    char* end;
+    value = std::strtod(token.c_str(), &end);
+    return *end == '\0' && !std::isnan(value) && !std::isinf(value);
+}
+
+static std::string lowercase(std::string text) {
+    for (char& c : text) {
+        c = std::tolower(c);
+    }
+    return text;
+}
+
+int main(int argc, char** argv) {
+    if (argc < 4) {
+        std::cerr << "Usage: " << argv[0] << " input answer feedback_dir [options] < output" << std::endl;
+        return 1;
+    }
+
+    judge_message.open(std::string(argv[3]) + "/judgemessage.txt");
+
+    bool case_sensitive = false;
+    bool space_change_sensitive = false;
+    bool float_tolerance = false;
+    double absolute_tolerance = 0;
+    double relative_tolerance = 0;
+    for (int i = 4; i < argc; i++) {
+        std::string option = argv[i];
+        if (option == "case_sensitive") {
+            case_sensitive = true;
+        } else if (option == "space_change_sensitive") {
+            space_change_sensitive = true;
+        } else if (i + 1 < argc && option == "float_tolerance") {
+            float_tolerance = true;
+            absolute_tolerance = relative_tolerance = std::atof(argv[++i]);
+        } else if (i + 1 < argc && option == "float_absolute_tolerance") {
+            float_tolerance = true;
+            absolute_tolerance = std::atof(argv[++i]);
+        } else if (i + 1 < argc && option == "float_relative_tolerance") {
+            float_tolerance = true;
+            relative_tolerance = std::atof(argv[++i]);
+        } else {
+            std::cerr << "Unknown option " << option << std::endl;
+            return 1;
+        }
+    }
+
+    std::ifstream answer(argv[2]);
+    if (!answer) {
+        std::cerr << "Couldn't open answer " << argv[2] << std::endl;
+        return 1;
+    }
+
+    std::string expected, found;
+    for (int token = 1;; token++) {
+        bool has_expected = next_token(answer, space_change_sensitive, expected);
+        bool has_found = next_token(std::cin, space_change_sensitive, found);
+        if (!has_expected && !has_found) {
+            accept();
+        }
+        std::ostringstream position;
+        position << "token " << token;
+        if (!has_expected) {
+            reject("Output is longer than the answer at " + position.str() + ": " + found);
+        }
+        if (!has_found) {
+            reject("Output is shorter than the answer at " + position.str() + ", expected " + expected);
+        }
+
+        double expected_value, found_value;
+        if (float_tolerance && parse_number(expected, expected_value)) {
+            if (!parse_number(found, found_value)) {
+                reject("Expected a number at " + position.str() + ", found " + found);
+            }
+            double difference = std::fabs(expected_value - found_value);
+            if (difference > absolute_tolerance && difference > relative_tolerance * std::fabs(expected_value)) {
+                reject("Number too far from the answer at " + position.str() + ": expected " + expected + ", found " + found);
+            }
+            continue;
+        }
+
+        bool same = case_sensitive ? expected == found : lowercase(expected) == lowercase(found);
+        if (!same) {
+            reject("Wrong answer at " + position.str() + ": expected " + expected + ", found " + found);
+        }
+    }
+}
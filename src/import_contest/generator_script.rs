@@ -0,0 +1,443 @@
+//! Polygon's test generation: the `cmd` of each generated test and the
+//! testset script, with lines such as `gen 10 "a b" > 3`, `gen 5 > $` or
+//! `multigen 8 > {4-6,9}`.
+
+use super::xml::problem::{Problem, Test};
+use std::collections::BTreeSet;
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum GeneratorScriptError {
+    #[error("unterminated quote in `{0}`")]
+    UnterminatedQuote(String),
+    #[error("unterminated comment in generator script")]
+    UnterminatedComment,
+    #[error("expected `generator arguments > tests`, got `{0}`")]
+    InvalidLine(String),
+    #[error("invalid tests `{0}`")]
+    InvalidTarget(String),
+    #[error("unsupported generator script syntax `{0}`")]
+    Unsupported(String),
+    #[error("test {0} is out of range")]
+    TestOutOfRange(usize),
+    #[error("test {0} is generated more than once")]
+    DuplicateTest(usize),
+    #[error("nothing generates test {0}")]
+    MissingCommand(usize),
+}
+
+use GeneratorScriptError::*;
+
+/// Where the input of a test comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TestSource {
+    /// Stored in the package, under the testset's input pattern
+    Manual,
+    /// Written by running `command`, the generator name followed by its
+    /// arguments, either to stdout or to the file `output` in its working
+    /// directory, as multi-test generators do
+    Generated {
+        command: Vec<String>,
+        output: Option<String>,
+    },
+}
+
+enum Token {
+    Word(String),
+    Redirect,
+}
+
+fn tokenize(line: &str) -> Result<Vec<Token>, GeneratorScriptError> {
+    let mut tokens = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if let Some(word) = word.take() {
+                    tokens.push(Token::Word(word));
+                }
+            }
+            '>' => {
+                if let Some(word) = word.take() {
+                    tokens.push(Token::Word(word));
+                }
+                tokens.push(Token::Redirect);
+            }
+            '"' | '\'' => {
+                // Quotes may be empty or glued to the rest of the word, as in a shell
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        None => return Err(UnterminatedQuote(line.into())),
+                        Some(quote) if quote == c => break,
+                        Some('\\') if c == '"' => match chars.next() {
+                            Some(escaped) => word.push(escaped),
+                            None => return Err(UnterminatedQuote(line.into())),
+                        },
+                        Some(other) => word.push(other),
+                    }
+                }
+            }
+            '\\' => {
+                let word = word.get_or_insert_with(String::new);
+                if let Some(escaped) = chars.next() {
+                    word.push(escaped);
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if let Some(word) = word {
+        tokens.push(Token::Word(word));
+    }
+    Ok(tokens)
+}
+
+fn words(tokens: Vec<Token>, line: &str) -> Result<Vec<String>, GeneratorScriptError> {
+    let words = tokens
+        .into_iter()
+        .map(|token| match token {
+            Token::Word(word) => Ok(word),
+            Token::Redirect => Err(InvalidLine(line.into())),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if words.is_empty() {
+        return Err(InvalidLine(line.into()));
+    }
+    Ok(words)
+}
+
+/// Splits a test's `cmd` into the generator name and its arguments.
+pub fn split_command(cmd: &str) -> Result<Vec<String>, GeneratorScriptError> {
+    words(tokenize(cmd)?, cmd)
+}
+
+enum Target {
+    /// `> 3`
+    Single(usize),
+    /// `> $`, the first test not otherwise taken
+    Next,
+    /// `> {4-6,9}`, the generator writing the files `1`, `2`, ... in order
+    Multi(Vec<usize>),
+}
+
+struct ScriptLine {
+    command: Vec<String>,
+    target: Target,
+}
+
+fn parse_index(index: &str, target: &str) -> Result<usize, GeneratorScriptError> {
+    index
+        .trim()
+        .parse()
+        .ok()
+        .filter(|&index| index > 0)
+        .ok_or_else(|| InvalidTarget(target.into()))
+}
+
+fn parse_target(target: &str) -> Result<Target, GeneratorScriptError> {
+    if target == "$" {
+        return Ok(Target::Next);
+    }
+    if let Some(list) = target.strip_prefix('{').and_then(|list| list.strip_suffix('}')) {
+        let mut tests = Vec::new();
+        for part in list.split(',') {
+            let (first, last) = match part.split_once('-') {
+                Some((first, last)) => (parse_index(first, target)?, parse_index(last, target)?),
+                None => (parse_index(part, target)?, parse_index(part, target)?),
+            };
+            if first > last {
+                return Err(InvalidTarget(target.into()));
+            }
+            tests.extend(first..=last);
+        }
+        return Ok(Target::Multi(tests));
+    }
+    Ok(Target::Single(parse_index(target, target)?))
+}
+
+fn strip_comments(script: &str) -> Result<String, GeneratorScriptError> {
+    let mut stripped = String::new();
+    let mut rest = script;
+    while let Some(start) = rest.find("<#--") {
+        stripped.push_str(&rest[..start]);
+        let end = rest[start..].find("-->").ok_or(UnterminatedComment)?;
+        rest = &rest[start + end + "-->".len()..];
+    }
+    stripped.push_str(rest);
+    Ok(stripped)
+}
+
+fn parse_script(script: &str) -> Result<Vec<ScriptLine>, GeneratorScriptError> {
+    let mut lines = Vec::new();
+    for line in strip_comments(script)?.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        // Freemarker loops and variables would need a template engine
+        if line.starts_with("<#") || line.starts_with("</#") || line.contains("${") {
+            return Err(Unsupported(line.into()));
+        }
+        let mut tokens = tokenize(line)?;
+        let target = match (tokens.pop(), tokens.pop()) {
+            (Some(Token::Word(target)), Some(Token::Redirect)) => parse_target(&target)?,
+            _ => return Err(InvalidLine(line.into())),
+        };
+        lines.push(ScriptLine {
+            command: words(tokens, line)?,
+            target,
+        });
+    }
+    Ok(lines)
+}
+
+fn fill_from_script(
+    sources: &mut [Option<TestSource>],
+    lines: &[ScriptLine],
+) -> Result<(), GeneratorScriptError> {
+    // `$` only takes what neither the package nor a numbered line uses
+    let numbered: BTreeSet<usize> = lines
+        .iter()
+        .flat_map(|line| match &line.target {
+            Target::Single(test) => vec![*test],
+            Target::Multi(tests) => tests.clone(),
+            Target::Next => vec![],
+        })
+        .collect();
+    let mut assigned = BTreeSet::new();
+    let mut next = 1;
+
+    for line in lines {
+        let tests: Vec<(usize, Option<String>)> = match &line.target {
+            Target::Single(test) => vec![(*test, None)],
+            Target::Multi(tests) => tests
+                .iter()
+                .enumerate()
+                .map(|(k, test)| (*test, Some((k + 1).to_string())))
+                .collect(),
+            Target::Next => {
+                while numbered.contains(&next)
+                    || assigned.contains(&next)
+                    || sources.get(next - 1).is_some_and(Option::is_some)
+                {
+                    next += 1;
+                }
+                vec![(next, None)]
+            }
+        };
+        for (test, output) in tests {
+            let source = sources.get_mut(test - 1).ok_or(TestOutOfRange(test))?;
+            match source {
+                Some(TestSource::Manual) => return Err(DuplicateTest(test)),
+                Some(_) if assigned.contains(&test) => return Err(DuplicateTest(test)),
+                // The test's own cmd wins over the script
+                Some(_) => {}
+                None => {
+                    *source = Some(TestSource::Generated {
+                        command: line.command.clone(),
+                        output,
+                    })
+                }
+            }
+            assigned.insert(test);
+        }
+    }
+    Ok(())
+}
+
+/// Works out where the input of each test comes from. The script, if any,
+/// only fills in the generated tests the package gives no `cmd` for.
+pub fn plan_tests(
+    tests: &[Test],
+    script: Option<&str>,
+) -> Result<Vec<TestSource>, GeneratorScriptError> {
+    let mut sources = tests
+        .iter()
+        .map(|test| {
+            Ok(match (test.method.as_deref(), &test.cmd) {
+                (Some("manual"), _) => Some(TestSource::Manual),
+                (_, Some(cmd)) => Some(TestSource::Generated {
+                    command: split_command(cmd)?,
+                    output: test.from_file.clone(),
+                }),
+                (_, None) => None,
+            })
+        })
+        .collect::<Result<Vec<_>, GeneratorScriptError>>()?;
+
+    if let Some(script) = script {
+        if sources.iter().any(Option::is_none) {
+            fill_from_script(&mut sources, &parse_script(script)?)?;
+        }
+    }
+
+    sources
+        .into_iter()
+        .enumerate()
+        .map(|(i, source)| source.ok_or(MissingCommand(i + 1)))
+        .collect()
+}
+
+/// The source path and Polygon language of the executable a command names,
+/// matched by file name without extension. Packages that don't list it get
+/// `files/{name}.cpp` as C++17.
+pub fn generator_source(problem: &Problem, name: &str) -> (String, String) {
+    problem
+        .files
+        .executables
+        .executable
+        .iter()
+        .map(|executable| &executable.source)
+        .find(|source| {
+            Path::new(&source.path)
+                .file_stem()
+                .is_some_and(|stem| stem == name)
+        })
+        .map(|source| (source.path.clone(), source.r#type.clone()))
+        .unwrap_or_else(|| (format!("files/{}.cpp", name), "cpp.g++17".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manual() -> Test {
+        Test {
+            method: Some("manual".into()),
+            sample: None,
+            description: None,
+            cmd: None,
+            from_file: None,
+        }
+    }
+
+    fn generated(cmd: Option<&str>) -> Test {
+        Test {
+            method: Some("generated".into()),
+            sample: None,
+            description: None,
+            cmd: cmd.map(String::from),
+            from_file: None,
+        }
+    }
+
+    fn command(command: &[&str], output: Option<&str>) -> TestSource {
+        TestSource::Generated {
+            command: command.iter().map(|word| word.to_string()).collect(),
+            output: output.map(String::from),
+        }
+    }
+
+    #[test]
+    fn splits_quoted_arguments() {
+        assert_eq!(
+            split_command(r#"gen 10 "a b" 'c'd "e\"f" """#),
+            Ok(vec!["gen".into(), "10".into(), "a b".into(), "cd".into(), "e\"f".into(), "".into()])
+        );
+    }
+
+    #[test]
+    fn parses_targets() {
+        assert!(matches!(parse_target("$"), Ok(Target::Next)));
+        assert!(matches!(parse_target("3"), Ok(Target::Single(3))));
+        assert!(matches!(parse_target("{4-6,9}"), Ok(Target::Multi(tests)) if tests == vec![4, 5, 6, 9]));
+    }
+
+    #[test]
+    fn rejects_invalid_targets() {
+        for target in ["0", "{0}", "{5-3}", "{}", "{1,}", "x", "-1"] {
+            assert_eq!(parse_target(target).err(), Some(InvalidTarget(target.into())), "{}", target);
+        }
+    }
+
+    #[test]
+    fn rejects_unterminated_quotes() {
+        assert_eq!(split_command("gen \"a"), Err(UnterminatedQuote("gen \"a".into())));
+        assert_eq!(split_command(r#"gen "a\""#), Err(UnterminatedQuote(r#"gen "a\""#.into())));
+    }
+
+    #[test]
+    fn rejects_unterminated_comments() {
+        assert_eq!(plan_tests(&[generated(None)], Some("<#-- gen > 1")), Err(UnterminatedComment));
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        assert_eq!(plan_tests(&[generated(None)], Some("gen 1")), Err(InvalidLine("gen 1".into())));
+        assert_eq!(plan_tests(&[generated(None)], Some("> 1")), Err(InvalidLine("> 1".into())));
+        assert_eq!(plan_tests(&[generated(None)], Some("gen > > 1")), Err(InvalidLine("gen > > 1".into())));
+    }
+
+    #[test]
+    fn rejects_freemarker() {
+        let script = "<#list 1..3 as i>\ngen ${i} > $\n</#list>";
+        assert_eq!(
+            plan_tests(&[generated(None)], Some(script)),
+            Err(Unsupported("<#list 1..3 as i>".into()))
+        );
+        assert_eq!(
+            plan_tests(&[generated(None)], Some("gen ${i} > 1")),
+            Err(Unsupported("gen ${i} > 1".into()))
+        );
+    }
+
+    #[test]
+    fn rejects_tests_out_of_range() {
+        assert_eq!(plan_tests(&[generated(None)], Some("gen > 2")), Err(TestOutOfRange(2)));
+        assert_eq!(plan_tests(&[generated(None)], Some("gen > 1\ngen > $")), Err(TestOutOfRange(2)));
+    }
+
+    #[test]
+    fn rejects_duplicate_tests() {
+        assert_eq!(
+            plan_tests(&[manual(), generated(None)], Some("gen > 1\ngen > 2")),
+            Err(DuplicateTest(1))
+        );
+        assert_eq!(
+            plan_tests(&[generated(None), generated(None)], Some("gen 1 > 2\ngen 2 > {1-2}")),
+            Err(DuplicateTest(2))
+        );
+    }
+
+    #[test]
+    fn rejects_missing_commands() {
+        assert_eq!(plan_tests(&[manual(), generated(None)], None), Err(MissingCommand(2)));
+        assert_eq!(
+            plan_tests(&[generated(None), generated(None)], Some("gen > 1")),
+            Err(MissingCommand(2))
+        );
+    }
+
+    #[test]
+    fn assigns_next_tests_around_manual_and_numbered_ones() {
+        let tests = [manual(), generated(None), generated(None), manual(), generated(None), generated(None)];
+        let script = "<#-- comment -->gen a > $\ngen b > 3\n\ngen c > $\ngen d > $";
+        assert_eq!(
+            plan_tests(&tests, Some(script)),
+            Ok(vec![
+                TestSource::Manual,
+                command(&["gen", "a"], None),
+                command(&["gen", "b"], None),
+                TestSource::Manual,
+                command(&["gen", "c"], None),
+                command(&["gen", "d"], None),
+            ])
+        );
+    }
+
+    #[test]
+    fn prefers_the_tests_own_command() {
+        let tests = [generated(Some("gen 1")), generated(None), generated(None)];
+        assert_eq!(
+            plan_tests(&tests, Some("gen 2 > 1\nmultigen 3 > {2-3}")),
+            Ok(vec![
+                command(&["gen", "1"], None),
+                command(&["multigen", "3"], Some("1")),
+                command(&["multigen", "3"], Some("2")),
+            ])
+        );
+    }
+}
@@ -0,0 +1,473 @@
+//! The Kattis problem package format, also used by the ICPC and DOMjudge:
+//! `problem.yaml`, tests in `data/sample` and `data/secret`, validators in
+//! `input_validators` and `output_validators` and solutions in
+//! `submissions/{expected verdict}`.
+
+use super::statement_sections::StatementProperties;
+use super::ImportContestError;
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use serde::Deserialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::io::{Read, Seek};
+use std::path::Path;
+use zip::ZipArchive;
+
+/// The output validator used when the package doesn't bring its own, for
+/// `validation: default`.
+pub const DEFAULT_OUTPUT_VALIDATOR: &str = include_str!("default_output_validator.cpp");
+
+/// Extensions of the sources we can compile, headers and the like being
+/// left out when looking for a program.
+pub const SOURCE_EXTENSIONS: [&str; 7] = ["c", "cc", "cpp", "cxx", "java", "pas", "py"];
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Metadata {
+    pub name: Option<Name>,
+    pub validation: Option<String>,
+    pub validator_flags: Option<String>,
+    #[serde(default)]
+    pub limits: Limits,
+}
+
+/// A single name, or one per language code in newer packages.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum Name {
+    Single(String),
+    ByLanguage(BTreeMap<String, String>),
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Limits {
+    /// In seconds, older packages leave it for the judge to pick
+    pub time_limit: Option<f64>,
+    /// In MiB
+    pub memory: Option<i32>,
+}
+
+/// Paths are relative to the root of the package.
+#[derive(Debug, Clone)]
+pub struct Test {
+    pub input_path: String,
+    pub answer_path: String,
+    pub sample: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Solution {
+    pub path: String,
+    /// The directory it's in, as `accepted` or `wrong_answer`
+    pub tag: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Statement {
+    /// ISO 639-1 code, as `en`
+    pub language: String,
+    pub path: String,
+    pub mime_type: String,
+}
+
+#[derive(Debug)]
+pub struct Problem {
+    pub short_name: String,
+    /// Follows the contents of the package, as Kattis has no revisions
+    pub revision: String,
+    pub name: String,
+    pub metadata: Metadata,
+    pub time_limit_seconds: Option<f64>,
+    pub tests: Vec<Test>,
+    pub input_validator: Option<String>,
+    pub output_validator: Option<String>,
+    pub solutions: Vec<Solution>,
+    pub statements: Vec<Statement>,
+}
+
+fn read_string<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    name: &str,
+) -> Result<String, ImportContestError> {
+    let mut content = String::new();
+    zip.by_name(name)?.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+fn is_source(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| SOURCE_EXTENSIONS.contains(&extension))
+}
+
+/// The first source in a validator directory, either alone in it or in a
+/// directory of its own along with its headers.
+fn find_program(names: &[String], directory: &str) -> Option<String> {
+    names
+        .iter()
+        .filter(|name| name.starts_with(directory) && is_source(name))
+        .min()
+        .cloned()
+}
+
+/// The time limit DOMjudge exports, in `.timelimit` or `domjudge-problem.ini`.
+fn read_domjudge_time_limit<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    prefix: &str,
+) -> Result<Option<f64>, ImportContestError> {
+    lazy_static! {
+        static ref INI_TIME_LIMIT: Regex =
+            Regex::new(r#"(?m)^\s*timelimit\s*=\s*['"]?([0-9.]+)"#).unwrap();
+    }
+    if let Ok(time_limit) = read_string(zip, &format!("{}.timelimit", prefix)) {
+        return Ok(time_limit.trim().parse().ok());
+    }
+    if let Ok(ini) = read_string(zip, &format!("{}domjudge-problem.ini", prefix)) {
+        return Ok(INI_TIME_LIMIT.captures(&ini).and_then(|c| c[1].parse().ok()));
+    }
+    Ok(None)
+}
+
+/// Reads the package found under `prefix` in the zip.
+pub fn read_problem<R: Read + Seek>(
+    zip: &mut ZipArchive<R>,
+    prefix: &str,
+    short_name: &str,
+) -> Result<Problem, ImportContestError> {
+    lazy_static! {
+        static ref TEST_INPUT: Regex = Regex::new(r"^data/(sample|secret)/(.*)\.in$").unwrap();
+        static ref SOLUTION: Regex = Regex::new(r"^submissions/([a-z_]+)/([^/]+)$").unwrap();
+        static ref STATEMENT: Regex =
+            Regex::new(r"^(problem_statement|statement)/problem(\.([a-z]{2}))?\.(tex|pdf)$").unwrap();
+    }
+
+    let metadata_yaml = read_string(zip, &format!("{}problem.yaml", prefix))?;
+    // Every field is optional, so the file may have nothing but comments
+    let metadata: Metadata = if metadata_yaml
+        .lines()
+        .all(|line| line.trim().is_empty() || line.trim_start().starts_with('#'))
+    {
+        Metadata::default()
+    } else {
+        serde_yaml::from_str(&metadata_yaml)?
+    };
+
+    // Sorted, as Kattis runs the tests in the order of their names
+    let mut names: Vec<String> = zip
+        .file_names()
+        .filter_map(|name| name.strip_prefix(prefix))
+        .filter(|name| !name.is_empty() && !name.ends_with('/'))
+        .map(String::from)
+        .collect();
+    names.sort();
+
+    let mut hasher = DefaultHasher::new();
+    for name in &names {
+        let file = zip.by_name(&format!("{}{}", prefix, name))?;
+        (name, file.crc32(), file.size()).hash(&mut hasher);
+    }
+
+    let mut tests = Vec::new();
+    for group in &["sample", "secret"] {
+        for name in &names {
+            let captures = match TEST_INPUT.captures(name) {
+                Some(captures) if &captures[1] == *group => captures,
+                _ => continue,
+            };
+            let answer_path = format!("data/{}/{}.ans", group, &captures[2]);
+            if !names.contains(&answer_path) {
+                return Err(ImportContestError::MissingAnswer(name.clone()));
+            }
+            tests.push(Test {
+                input_path: name.clone(),
+                answer_path,
+                sample: *group == "sample",
+            });
+        }
+    }
+
+    let solutions = names
+        .iter()
+        .filter_map(|name| SOLUTION.captures(name))
+        .filter(|captures| is_source(&captures[0]))
+        .map(|captures| Solution {
+            path: captures[0].into(),
+            tag: captures[1].into(),
+        })
+        .collect();
+
+    let statements: Vec<Statement> = names
+        .iter()
+        .filter_map(|name| STATEMENT.captures(name))
+        .map(|captures| Statement {
+            language: captures.get(3).map_or("en", |language| language.as_str()).into(),
+            path: captures[0].into(),
+            mime_type: if &captures[4] == "pdf" { "application/pdf" } else { "text/x-tex" }.into(),
+        })
+        .collect();
+
+    let name = match &metadata.name {
+        Some(Name::Single(name)) => Some(name.clone()),
+        Some(Name::ByLanguage(names)) => names.get("en").or(names.values().next()).cloned(),
+        None => None,
+    };
+    let name = match name {
+        Some(name) => name,
+        // Older packages only name the problem in the statement
+        None => {
+            let mut tex_statements: Vec<&Statement> =
+                statements.iter().filter(|s| s.mime_type == "text/x-tex").collect();
+            tex_statements.sort_by_key(|s| s.language != "en");
+            let statement_name = match tex_statements.first() {
+                Some(statement) => {
+                    statement_properties(&read_string(zip, &format!("{}{}", prefix, statement.path))?)
+                        .name
+                }
+                None => None,
+            };
+            statement_name.unwrap_or_else(|| short_name.into())
+        }
+    };
+
+    Ok(Problem {
+        short_name: short_name.into(),
+        revision: format!("{:08x}", hasher.finish() as u32),
+        name,
+        time_limit_seconds: match metadata.limits.time_limit {
+            Some(time_limit) => Some(time_limit),
+            None => read_domjudge_time_limit(zip, prefix)?,
+        },
+        metadata,
+        tests,
+        input_validator: find_program(&names, "input_validators/"),
+        output_validator: find_program(&names, "output_validators/"),
+        solutions,
+        statements,
+    })
+}
+
+/// Splits a Kattis `problem.tex` in the sections Polygon has, so it's shown
+/// as theirs are. Sections we don't know go into the legend.
+pub fn statement_properties(tex: &str) -> StatementProperties {
+    lazy_static! {
+        static ref PROBLEM_NAME: Regex = Regex::new(r"\\problemname\{([^{}]*)\}").unwrap();
+        static ref ILLUSTRATION: Regex =
+            Regex::new(r"\\illustration\{[^{}]*\}\{([^{}]*)\}\{([^{}]*)\}").unwrap();
+        static ref SECTION: Regex = Regex::new(r"\\section\*?\{([^{}]*)\}").unwrap();
+    }
+
+    let mut properties = StatementProperties {
+        name: PROBLEM_NAME.captures(tex).map(|c| c[1].trim().to_string()),
+        ..Default::default()
+    };
+    let tex = PROBLEM_NAME.replace_all(tex, "");
+    let tex = ILLUSTRATION.replace_all(&tex, |c: &Captures| {
+        format!("\\begin{{center}}\\includegraphics{{{}}}\\\\{}\\end{{center}}", &c[1], &c[2])
+    });
+
+    let mut legend = String::new();
+    let mut title: Option<String> = None;
+    let mut start = 0;
+    let sections = SECTION
+        .captures_iter(&tex)
+        .map(|c| {
+            let whole = c.get(0).unwrap();
+            (whole.start(), whole.end(), c[1].trim().to_string())
+        })
+        .chain(std::iter::once((tex.len(), tex.len(), String::new())));
+    for (section_start, section_end, next_title) in sections {
+        let body = tex[start..section_start].trim().to_string();
+        let field = match title.as_ref().map(|title| title.to_lowercase()).as_deref() {
+            Some("input") => Some(&mut properties.input),
+            Some("output") => Some(&mut properties.output),
+            Some("interaction") => Some(&mut properties.interaction),
+            Some("scoring") => Some(&mut properties.scoring),
+            Some("notes") | Some("note") => Some(&mut properties.notes),
+            _ => None,
+        };
+        match (field, &title) {
+            (Some(field), _) => *field = Some(body),
+            (None, Some(title)) => {
+                legend.push_str(&format!("\n\n\\textbf{{{}}}\n\n{}", title, body))
+            }
+            (None, None) => legend.push_str(&body),
+        }
+        title = Some(next_title);
+        start = section_end;
+    }
+    properties.legend = Some(legend);
+    properties
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::write::{FileOptions, ZipWriter};
+
+    fn package(files: &[(&str, &str)]) -> ZipArchive<Cursor<Vec<u8>>> {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in files {
+            writer.start_file(*name, FileOptions::default()).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        ZipArchive::new(writer.finish().unwrap()).unwrap()
+    }
+
+    fn read(files: &[(&str, &str)]) -> Problem {
+        read_problem(&mut package(files), "", "a").unwrap()
+    }
+
+    #[test]
+    fn reads_a_single_name_and_limits() {
+        let problem = read(&[(
+            "problem.yaml",
+            "name: Hello\nlimits:\n  time_limit: 1.5\n  memory: 512\n",
+        )]);
+        assert_eq!(problem.name, "Hello");
+        assert_eq!(problem.time_limit_seconds, Some(1.5));
+        assert_eq!(problem.metadata.limits.memory, Some(512));
+        assert_eq!(problem.metadata.validation, None);
+    }
+
+    #[test]
+    fn prefers_the_english_name() {
+        let problem = read(&[("problem.yaml", "name:\n  pt: Olá\n  en: Hello\n")]);
+        assert_eq!(problem.name, "Hello");
+        let problem = read(&[("problem.yaml", "name:\n  pt: Olá\n  es: Hola\n")]);
+        assert_eq!(problem.name, "Hola");
+    }
+
+    #[test]
+    fn names_the_problem_from_the_statement_or_short_name() {
+        let problem = read(&[
+            ("problem.yaml", "source: NWERC\n"),
+            ("problem_statement/problem.pt.tex", "\\problemname{Olá}\n"),
+            ("problem_statement/problem.en.tex", "\\problemname{ Hello }\n"),
+        ]);
+        assert_eq!(problem.name, "Hello");
+        assert_eq!(read(&[("problem.yaml", "")]).name, "a");
+        assert_eq!(read(&[("problem.yaml", "# Nothing to see\n\n")]).name, "a");
+    }
+
+    #[test]
+    fn reads_validation_and_validator_flags() {
+        let problem = read(&[
+            ("problem.yaml", "validation: custom interactive\nvalidator_flags: float_tolerance 1e-6\n"),
+            ("output_validators/checker/checker.cpp", ""),
+            ("output_validators/checker/testlib.h", ""),
+            ("input_validators/validate.py", ""),
+        ]);
+        assert_eq!(problem.metadata.validation.as_deref(), Some("custom interactive"));
+        assert_eq!(problem.metadata.validator_flags.as_deref(), Some("float_tolerance 1e-6"));
+        assert_eq!(problem.output_validator.as_deref(), Some("output_validators/checker/checker.cpp"));
+        assert_eq!(problem.input_validator.as_deref(), Some("input_validators/validate.py"));
+    }
+
+    #[test]
+    fn falls_back_to_the_domjudge_time_limit() {
+        let problem = read(&[("problem.yaml", ""), (".timelimit", "2\n")]);
+        assert_eq!(problem.time_limit_seconds, Some(2.0));
+        let problem = read(&[("problem.yaml", ""), ("domjudge-problem.ini", "name='A'\ntimelimit='3.5'\n")]);
+        assert_eq!(problem.time_limit_seconds, Some(3.5));
+        assert_eq!(read(&[("problem.yaml", "")]).time_limit_seconds, None);
+    }
+
+    #[test]
+    fn reads_samples_before_secret_tests() {
+        let problem = read(&[
+            ("problem.yaml", ""),
+            ("data/secret/2.in", ""),
+            ("data/secret/2.ans", ""),
+            ("data/secret/1.in", ""),
+            ("data/secret/1.ans", ""),
+            ("data/sample/1.in", ""),
+            ("data/sample/1.ans", ""),
+        ]);
+        let tests: Vec<(&str, &str, bool)> = problem
+            .tests
+            .iter()
+            .map(|test| (test.input_path.as_str(), test.answer_path.as_str(), test.sample))
+            .collect();
+        assert_eq!(
+            tests,
+            vec![
+                ("data/sample/1.in", "data/sample/1.ans", true),
+                ("data/secret/1.in", "data/secret/1.ans", false),
+                ("data/secret/2.in", "data/secret/2.ans", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn requires_answers() {
+        let result = read_problem(
+            &mut package(&[("problem.yaml", ""), ("data/secret/1.in", "")]),
+            "",
+            "a",
+        );
+        assert!(matches!(result, Err(ImportContestError::MissingAnswer(name)) if name == "data/secret/1.in"));
+    }
+
+    #[test]
+    fn tags_solutions_by_their_directory() {
+        let problem = read(&[
+            ("problem.yaml", ""),
+            ("submissions/accepted/a.cpp", ""),
+            ("submissions/wrong_answer/b.py", ""),
+            ("submissions/time_limit_exceeded/c.java", ""),
+            ("submissions/accepted/notes.txt", ""),
+            ("submissions/accepted/nested/d.cpp", ""),
+        ]);
+        let solutions: Vec<(&str, &str)> = problem
+            .solutions
+            .iter()
+            .map(|solution| (solution.path.as_str(), solution.tag.as_str()))
+            .collect();
+        assert_eq!(
+            solutions,
+            vec![
+                ("submissions/accepted/a.cpp", "accepted"),
+                ("submissions/time_limit_exceeded/c.java", "time_limit_exceeded"),
+                ("submissions/wrong_answer/b.py", "wrong_answer"),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_packages_under_a_prefix() {
+        let mut zip = package(&[
+            ("b/problem.yaml", "name: B\n"),
+            ("b/data/sample/1.in", ""),
+            ("b/data/sample/1.ans", ""),
+        ]);
+        let problem = read_problem(&mut zip, "b/", "b").unwrap();
+        assert_eq!(problem.name, "B");
+        assert_eq!(problem.tests[0].input_path, "data/sample/1.in");
+    }
+
+    #[test]
+    fn splits_statements_in_sections() {
+        let properties = statement_properties(
+            "\\problemname{Sum}\nAdd them.\n\\section*{Input}\nTwo integers.\n\\section{Output}\nTheir sum.\n\\section*{Story}\nLong ago.\n\\section*{Notes}\nEasy.",
+        );
+        assert_eq!(properties.name.as_deref(), Some("Sum"));
+        assert_eq!(properties.legend.as_deref(), Some("Add them.\n\n\\textbf{Story}\n\nLong ago."));
+        assert_eq!(properties.input.as_deref(), Some("Two integers."));
+        assert_eq!(properties.output.as_deref(), Some("Their sum."));
+        assert_eq!(properties.notes.as_deref(), Some("Easy."));
+        assert_eq!(properties.interaction, None);
+    }
+
+    #[test]
+    fn turns_illustrations_into_images() {
+        let properties = statement_properties("\\illustration{0.3}{cat.jpg}{A cat}Text");
+        assert_eq!(
+            properties.legend.as_deref(),
+            Some("\\begin{center}\\includegraphics{cat.jpg}\\\\A cat\\end{center}Text")
+        );
+        assert_eq!(properties.name, None);
+    }
+}
@@ -0,0 +1,209 @@
+//! Polygon's `statement-sections/{language}/problem-properties.json`, the
+//! statement split in TeX sections, turned into HTML for our own template.
+//! Math is left between its dollars for MathJax.
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct StatementProperties {
+    pub name: Option<String>,
+    pub legend: Option<String>,
+    pub input: Option<String>,
+    pub output: Option<String>,
+    pub interaction: Option<String>,
+    pub scoring: Option<String>,
+    pub notes: Option<String>,
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Takes the math out of the text, as `\u{0}{index}\u{0}` placeholders, so
+/// the text conversions don't touch it.
+fn extract_math(tex: &str) -> (String, Vec<String>) {
+    let mut text = String::new();
+    let mut math = Vec::new();
+    let mut chars = tex.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '\\' => {
+                text.push(c);
+                if let Some((_, escaped)) = chars.next() {
+                    text.push(escaped);
+                }
+            }
+            '$' => {
+                let display = matches!(chars.peek(), Some((_, '$')));
+                let delimiter = if display { "$$" } else { "$" };
+                let body_start = start + delimiter.len();
+                match find_closing(&tex[body_start..], delimiter) {
+                    Some(length) => {
+                        let end = body_start + length + delimiter.len();
+                        text.push_str(&format!("\u{0}{}\u{0}", math.len()));
+                        math.push(tex[start..end].to_string());
+                        while chars.peek().is_some_and(|(i, _)| *i < end) {
+                            chars.next();
+                        }
+                    }
+                    // A lone dollar is just a dollar
+                    None => text.push(c),
+                }
+            }
+            c => text.push(c),
+        }
+    }
+    (text, math)
+}
+
+fn find_closing(tex: &str, delimiter: &str) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in tex.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if tex[i..].starts_with(delimiter) {
+            return Some(i);
+        }
+    }
+    None
+}
+
+/// Converts the TeX Polygon allows in statements to HTML: paragraphs, the
+/// usual font commands, lists, centering and images, which `image_url` maps
+/// to where they're served.
+pub fn tex_to_html(tex: &str, image_url: impl Fn(&str) -> String) -> String {
+    lazy_static! {
+        static ref FONT: Regex =
+            Regex::new(r"\\(textbf|textit|emph|texttt|underline|tt|bf|it)\{([^{}]*)\}").unwrap();
+        static ref IMAGE: Regex =
+            Regex::new(r"\\includegraphics(\[[^\]]*\])?\{([^{}]*)\}").unwrap();
+        static ref ENVIRONMENT: Regex =
+            Regex::new(r"\\(begin|end)\{(itemize|enumerate|center)\}").unwrap();
+        static ref PARAGRAPH: Regex = Regex::new(r"\n[ \t]*\n\s*").unwrap();
+        static ref EMPTY_PARAGRAPH: Regex = Regex::new(r"<p>\s*</p>").unwrap();
+        static ref MATH: Regex = Regex::new("\u{0}([0-9]+)\u{0}").unwrap();
+    }
+
+    let (text, math) = extract_math(tex.trim());
+    let mut html = escape_html(&text);
+
+    html = IMAGE
+        .replace_all(&html, |c: &Captures| format!("<img src=\"{}\">", image_url(&c[2])))
+        .into_owned();
+    // Innermost first, until nothing nested is left
+    loop {
+        let replaced = FONT.replace_all(&html, |c: &Captures| {
+            let tag = match &c[1] {
+                "textbf" | "bf" => "b",
+                "textit" | "emph" | "it" => "i",
+                "texttt" | "tt" => "code",
+                _ => "u",
+            };
+            format!("<{}>{}</{}>", tag, &c[2], tag)
+        });
+        if replaced == html {
+            break;
+        }
+        html = replaced.into_owned();
+    }
+    html = ENVIRONMENT
+        .replace_all(&html, |c: &Captures| {
+            match (&c[1], &c[2]) {
+                ("begin", "itemize") => "</p><ul>",
+                ("end", "itemize") => "</ul><p>",
+                ("begin", "enumerate") => "</p><ol>",
+                ("end", "enumerate") => "</ol><p>",
+                ("begin", _) => "</p><div class=\"center\"><p>",
+                _ => "</p></div><p>",
+            }
+        })
+        .into_owned();
+    html = html
+        .replace("\\item", "<li>")
+        .replace("\\\\", "<br>")
+        .replace("---", "&mdash;")
+        .replace("--", "&ndash;")
+        .replace("``", "&ldquo;")
+        .replace("''", "&rdquo;")
+        .replace('~', "&nbsp;")
+        .replace("\\&amp;", "&amp;")
+        // A span keeps MathJax from taking it for the start of math
+        .replace("\\$", "<span>$</span>");
+    for escaped in &["%", "_", "#", "{", "}"] {
+        html = html.replace(&format!("\\{}", escaped), escaped);
+    }
+    html = format!("<p>{}</p>", PARAGRAPH.replace_all(&html, "</p><p>"));
+    html = EMPTY_PARAGRAPH.replace_all(&html, "").into_owned();
+
+    MATH.replace_all(&html, |c: &Captures| {
+        escape_html(&math[c[1].parse::<usize>().unwrap()])
+    })
+    .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn html(tex: &str) -> String {
+        tex_to_html(tex, |name| format!("/images/{}", name))
+    }
+
+    #[test]
+    fn keeps_inline_and_display_math() {
+        assert_eq!(html("Let $x_1 < 10$ be."), "<p>Let $x_1 &lt; 10$ be.</p>");
+        assert_eq!(html("Sum: $$\\sum_{i=1}^n a_i$$"), "<p>Sum: $$\\sum_{i=1}^n a_i$$</p>");
+        assert_eq!(html("$\\textbf{x}$ and -- $a$"), "<p>$\\textbf{x}$ and &ndash; $a$</p>");
+    }
+
+    #[test]
+    fn leaves_lone_and_escaped_dollars() {
+        assert_eq!(html("Costs 5$ now"), "<p>Costs 5$ now</p>");
+        assert_eq!(html("\\$5 and \\$6"), "<p><span>$</span>5 and <span>$</span>6</p>");
+    }
+
+    #[test]
+    fn converts_font_commands() {
+        assert_eq!(html("\\textbf{bold} \\emph{so}"), "<p><b>bold</b> <i>so</i></p>");
+        assert_eq!(html("\\textbf{\\emph{both}}"), "<p><b><i>both</i></b></p>");
+        assert_eq!(html("\\texttt{code} \\underline{u}"), "<p><code>code</code> <u>u</u></p>");
+    }
+
+    #[test]
+    fn escapes_html() {
+        assert_eq!(html("a < b & \"c\" > d"), "<p>a &lt; b &amp; &quot;c&quot; &gt; d</p>");
+        assert_eq!(html("<script>x</script>"), "<p>&lt;script&gt;x&lt;/script&gt;</p>");
+        assert_eq!(html("R\\&D"), "<p>R&amp;D</p>");
+    }
+
+    #[test]
+    fn leaves_unbalanced_braces() {
+        assert_eq!(html("\\textbf{open"), "<p>\\textbf{open</p>");
+        assert_eq!(html("\\emph{a} b}"), "<p><i>a</i> b}</p>");
+        assert_eq!(html("\\{x\\}"), "<p>{x}</p>");
+    }
+
+    #[test]
+    fn splits_paragraphs_and_lists() {
+        assert_eq!(html("One\n\nTwo"), "<p>One</p><p>Two</p>");
+        assert_eq!(
+            html("Items:\n\\begin{itemize}\n\\item a\n\\item b\n\\end{itemize}"),
+            "<p>Items:\n</p><ul>\n<li> a\n<li> b\n</ul>"
+        );
+    }
+
+    #[test]
+    fn maps_images() {
+        assert_eq!(
+            html("\\includegraphics[width=5cm]{pic.png}"),
+            "<p><img src=\"/images/pic.png\"></p>"
+        );
+    }
+}
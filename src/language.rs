@@ -1,7 +1,7 @@
 use crate::queue::job_protocol::job_result;
-use crate::queue::job_protocol::{job, Job, JobResult};
+use crate::queue::job_protocol::{job, Feature, Job, JobResult};
+use crate::queue::{job_deadline, JobResultRegistry};
 use async_channel::Sender;
-use tokio::sync::broadcast;
 use uuid::Uuid;
 use thiserror::Error;
 use log::info;
@@ -10,87 +10,185 @@ use log::info;
 #[error("job failed")]
 struct JobFailedError;
 
+#[derive(Error, Debug, Clone)]
+#[error("job went past its deadline")]
+pub struct JobTimedOutError;
+
+#[derive(Error, Debug, Clone)]
+#[error("job was cancelled")]
+pub struct JobCancelledError;
+
+#[derive(Error, Debug, Clone)]
+#[error("no connected worker can run the job")]
+pub struct NoCapableWorkerError;
+
+async fn send_and_await(
+    job_sender: &Sender<Job>,
+    job_results: &JobResultRegistry,
+    job: Job,
+) -> Result<JobResult, Box<dyn std::error::Error>> {
+    let uuid = job.uuid.clone();
+    let deadline = job_deadline(&job);
+    // Register before sending, so a fast worker can't answer before we listen
+    let job_result_receiver = job_results.await_result(&uuid);
+    info!("Sending job");
+    if let Err(e) = job_sender.send(job).await {
+        job_results.forget(&uuid);
+        return Err(Box::new(e));
+    }
+    info!("Sent, waiting for job result");
+    match tokio::time::timeout(deadline, job_result_receiver).await {
+        Ok(job_result) => Ok(job_result.map_err(|_| JobFailedError)?),
+        Err(_) => {
+            // Most likely still queued or parked, stop waiting for it
+            job_results.forget(&uuid);
+            Ok(JobResult {
+                uuid,
+                code: job_result::Code::TimedOut.into(),
+                which: None,
+            })
+        }
+    }
+}
+
+fn job_error(job_result: &JobResult) -> Box<dyn std::error::Error> {
+    match job_result::Code::from_i32(job_result.code) {
+        Some(job_result::Code::TimedOut) => Box::new(JobTimedOutError),
+        Some(job_result::Code::Cancelled) => Box::new(JobCancelledError),
+        Some(job_result::Code::NoCapableWorker) => Box::new(NoCapableWorkerError),
+        _ => Box::new(JobFailedError),
+    }
+}
+
 pub async fn run_cached(
     job_sender: &Sender<Job>,
-    job_result_sender: &broadcast::Sender<JobResult>,
+    job_results: &JobResultRegistry,
     language: &String,
     source_path: String,
     arguments: Vec<String>,
+    path_arguments: Vec<String>,
     stdin_path: Option<String>,
     stdout_path: Option<String>,
+    output_files: Vec<job::OutputFile>,
     memory_limit_kib: i32,
     time_limit_ms: i32,
 ) -> Result<job_result::RunCached, Box<dyn std::error::Error>> {
-    let uuid = Uuid::new_v4();
-    let mut job_result_receiver = job_result_sender.subscribe();
-    info!("Sending job");
-    job_sender.send(Job {
-        uuid: uuid.to_string(),
+    let job_result = send_and_await(job_sender, job_results, Job {
+        uuid: Uuid::new_v4().to_string(),
         language: language.to_string(),
         memory_limit_kib,
         time_limit_ms,
+        required_features: [
+            (Feature::OutputFiles, !output_files.is_empty()),
+            (Feature::PathArguments, !path_arguments.is_empty()),
+        ]
+        .iter()
+        .filter(|(_, required)| *required)
+        .map(|(feature, _)| (*feature).into())
+        .collect(),
         which: Some(job::Which::RunCached(job::RunCached {
             source_path,
             arguments,
+            path_arguments,
             stdin_path,
             stdout_path,
+            output_files,
         }))
     }).await?;
-    info!("Sent");
 
-    loop {
-        info!("Polling for job result");
-        let job_result = job_result_receiver.recv().await?;
-        if job_result.uuid == uuid.to_string() {
-            if let JobResult {
-                which: Some(job_result::Which::RunCached(compile)),
-                ..
-            } = job_result {
-                return Ok(compile);
-            }
-            return Err(Box::new(JobFailedError));
-        }
+    if let JobResult {
+        which: Some(job_result::Which::RunCached(compile)),
+        ..
+    } = job_result {
+        return Ok(compile);
+    }
+    Err(job_error(&job_result))
+}
+
+/// How the checker of a problem from a package format gives its verdict.
+pub fn checker_protocol(package_format: &str) -> job::judgement::CheckerProtocol {
+    match package_format {
+        "kattis" => job::judgement::CheckerProtocol::Kattis,
+        _ => job::judgement::CheckerProtocol::Testlib,
     }
 }
 
 pub async fn judge(
     job_sender: &Sender<Job>,
-    job_result_sender: &broadcast::Sender<JobResult>,
+    job_results: &JobResultRegistry,
     language: &String,
     source_text: String,
     test_count: i32,
     test_pattern: String,
     checker_language: String,
     checker_source_path: String,
+    checker_protocol: job::judgement::CheckerProtocol,
+    checker_arguments: Vec<String>,
+    grader_paths: Vec<String>,
     memory_limit_kib: i32,
     time_limit_ms: i32,
 ) -> Result<job_result::Judgement, Box<dyn std::error::Error>> {
-    let uuid = Uuid::new_v4();
-    let mut job_result_receiver = job_result_sender.subscribe();
-    job_sender.send(Job {
-        uuid: uuid.to_string(),
+    let job_result = send_and_await(job_sender, job_results, Job {
+        uuid: Uuid::new_v4().to_string(),
         language: language.to_string(),
         memory_limit_kib,
         time_limit_ms,
+        required_features: [
+            (Feature::MultiFile, !grader_paths.is_empty()),
+            (Feature::OutputValidators, checker_protocol == job::judgement::CheckerProtocol::Kattis),
+        ]
+        .iter()
+        .filter(|(_, required)| *required)
+        .map(|(feature, _)| (*feature).into())
+        .collect(),
         which: Some(job::Which::Judgement(job::Judgement {
             source_text,
             test_count,
             test_pattern,
             checker_language,
             checker_source_path,
+            source_files: vec![],
+            grader_paths,
+            checker_protocol: checker_protocol.into(),
+            checker_arguments,
         }))
     }).await?;
 
-    loop {
-        let job_result = job_result_receiver.recv().await?;
-        if job_result.uuid == uuid.to_string() {
-            if let JobResult {
-                which: Some(job_result::Which::Judgement(compile)),
-                ..
-            } = job_result {
-                return Ok(compile);
-            }
-            return Err(Box::new(JobFailedError));
-        }
+    if let JobResult {
+        which: Some(job_result::Which::Judgement(judgement)),
+        ..
+    } = job_result {
+        return Ok(judgement);
+    }
+    Err(job_error(&job_result))
+}
+
+pub async fn run_program(
+    job_sender: &Sender<Job>,
+    job_results: &JobResultRegistry,
+    language: &String,
+    source_text: String,
+    stdin_text: String,
+    memory_limit_kib: i32,
+    time_limit_ms: i32,
+) -> Result<job_result::RunProgram, Box<dyn std::error::Error>> {
+    let job_result = send_and_await(job_sender, job_results, Job {
+        uuid: Uuid::new_v4().to_string(),
+        language: language.to_string(),
+        memory_limit_kib,
+        time_limit_ms,
+        required_features: vec![Feature::RunProgram.into()],
+        which: Some(job::Which::RunProgram(job::RunProgram {
+            source_text,
+            stdin_text,
+        }))
+    }).await?;
+
+    if let JobResult {
+        which: Some(job_result::Which::RunProgram(run)),
+        ..
+    } = job_result {
+        return Ok(run);
     }
+    Err(job_error(&job_result))
 }
@@ -1,5 +1,6 @@
-mod queue;
+pub mod queue;
 pub mod import_contest;
+pub mod worker;
 
 pub mod job_protocol {
     pub use crate::queue::job_protocol::*;
@@ -10,6 +10,7 @@ use actix_identity::Identity;
 use actix_web::middleware::{ErrorHandlerResponse, ErrorHandlers};
 use actix_web::{dev, get, http, middleware, post, web, App, HttpServer};
 use diesel::pg::PgConnection;
+use diesel::{Connection, OptionalExtension};
 use std::env;
 use std::fs::File;
 use uuid::Uuid;
@@ -28,7 +29,6 @@ mod import_contest;
 mod models;
 mod schema;
 mod setup;
-mod queue;
 mod flash;
 mod language;
 
@@ -38,9 +38,10 @@ use std::sync::Mutex;
 type DbPool = r2d2::Pool<ConnectionManager<PgConnection>>;
 use chrono_tz::Tz;
 use std::time::Duration;
-use queue::{JobQueuer};
+use jughisto::{queue, worker};
+use queue::{Handshakes, JobQueuer, JobResultRegistry, ParkedJobs, RunningJobs, SupersededJobs, Workers, WorkerState};
 use queue::job_protocol::job_queue_server::JobQueueServer;
-use queue::job_protocol::{Language, job, Job, JobResult, job_result};
+use queue::job_protocol::{Language, job, job_progress, Job, JobProgress, JobResult, job_result, Feature};
 use tonic::transport::Server;
 use std::error::Error;
 use async_channel::Sender;
@@ -48,34 +49,109 @@ use futures::TryFutureExt;
 use actix_web::web::Data;
 use tokio::sync::broadcast;
 use std::fs;
-use submission::SubmissionCompletion;
+use submission::{SubmissionCompilation, SubmissionCompletion};
 
-async fn update_database(mut job_result_receiver: broadcast::Receiver<JobResult>, pool: DbPool) -> Result<(), PostError> {
+fn parse_judge_instant(instant: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(instant, "%Y-%m-%dT%H:%M:%S%.f")
+        .unwrap_or_else(|_| Local::now().naive_utc())
+}
+
+fn verdict_code(verdict: i32) -> String {
+    match job_result::judgement::Verdict::from_i32(verdict) {
+        Some(job_result::judgement::Verdict::Accepted) => "AC".into(),
+        Some(job_result::judgement::Verdict::WrongAnswer) => "WA".into(),
+        Some(job_result::judgement::Verdict::CompilationError) => "CE".into(),
+        Some(job_result::judgement::Verdict::TimeLimitExceeded) => "TL".into(),
+        Some(job_result::judgement::Verdict::MemoryLimitExceeded) => "ML".into(),
+        Some(job_result::judgement::Verdict::RuntimeError) => "RE".into(),
+        None => "XX".into(),
+    }
+}
+
+fn store_submission_result(pool: &DbPool, job_result: &JobResult) -> Result<(), PostError> {
+    let connection = pool.get()?;
+
+    if job_result.code == i32::from(job_result::Code::TimedOut) {
+        submission::fail_submission(&connection, &job_result.uuid, "JT", Local::now().naive_utc())?;
+        return Ok(());
+    }
+
+    if job_result.code == i32::from(job_result::Code::Cancelled) {
+        submission::fail_submission(&connection, &job_result.uuid, "CA", Local::now().naive_utc())?;
+        return Ok(());
+    }
+
+    if job_result.code == i32::from(job_result::Code::FailedToExecute)
+        || job_result.code == i32::from(job_result::Code::InvalidLanguage)
+        || job_result.code == i32::from(job_result::Code::NoCapableWorker)
+    {
+        submission::fail_submission(&connection, &job_result.uuid, "JE", Local::now().naive_utc())?;
+        return Ok(());
+    }
+
+    if let Some(job_result::Which::Judgement(judgement)) = &job_result.which {
+        submission::complete_submission(&connection, SubmissionCompletion {
+            uuid: job_result.uuid.clone(),
+            verdict: verdict_code(judgement.verdict),
+            judge_start_instant: parse_judge_instant(&judgement.judge_start_instant),
+            judge_end_instant: parse_judge_instant(&judgement.judge_end_instant),
+            memory_kib: Some(judgement.memory_kib),
+            time_ms: Some(judgement.time_ms),
+            time_wall_ms: Some(judgement.time_wall_ms),
+            error_output: Some(judgement.error_output.clone()),
+            compilation: judgement.compilation.as_ref().map(|compilation| SubmissionCompilation {
+                exit_code: compilation.exit_code,
+                time_ms: compilation.time_ms,
+                memory_kib: compilation.memory_kib,
+                stdout: compilation.stdout_text.clone(),
+                stderr: compilation.stderr_text.clone(),
+            }),
+        })?;
+    }
+
+    Ok(())
+}
+
+async fn update_database(
+    submission_result_receiver: async_channel::Receiver<JobResult>,
+    pool: DbPool,
+    broadcaster: Data<Mutex<Broadcaster>>,
+) -> Result<(), PostError> {
+    while let Ok(job_result) = submission_result_receiver.recv().await {
+        // Keep retrying, a database hiccup shouldn't lose a verdict
+        while let Err(e) = store_submission_result(&pool, &job_result) {
+            error!("Couldn't store result of job uuid={}: {}, trying again in 3 seconds", job_result.uuid, e);
+            actix_web::rt::time::sleep(Duration::from_secs(3)).await;
+        }
+        let mut broadcaster = broadcaster.lock().unwrap();
+        broadcaster.forget_job(&job_result.uuid);
+        broadcaster.send("update_submission", "");
+    }
+    Ok(())
+}
+
+fn format_job_progress(progress: &JobProgress) -> Option<String> {
+    match job_progress::Stage::from_i32(progress.stage)? {
+        job_progress::Stage::Compiling => Some("Compilando".into()),
+        job_progress::Stage::Running => {
+            Some(format!("Executando teste {}/{}", progress.test, progress.test_count))
+        }
+    }
+}
+
+async fn forward_job_progress(
+    mut job_progress_receiver: broadcast::Receiver<JobProgress>,
+    broadcaster: Data<Mutex<Broadcaster>>,
+) {
     loop {
-        let job_result = job_result_receiver.recv().await.unwrap();
-        if let JobResult {
-            which: Some(job_result::Which::Judgement(judgement)),
-            ..
-        } = job_result {
-            let connection = pool.get().unwrap();
-            submission::complete_submission(&connection, SubmissionCompletion {
-                uuid: job_result.uuid,
-                verdict: match job_result::judgement::Verdict::from_i32(judgement.verdict) {
-                    Some(job_result::judgement::Verdict::Accepted) => "AC".into(),
-                    Some(job_result::judgement::Verdict::WrongAnswer) => "WA".into(),
-                    Some(job_result::judgement::Verdict::CompilationError) => "CE".into(),
-                    Some(job_result::judgement::Verdict::TimeLimitExceeded) => "TL".into(),
-                    Some(job_result::judgement::Verdict::MemoryLimitExceeded) => "ML".into(),
-                    Some(job_result::judgement::Verdict::RuntimeError) => "RE".into(),
-                    None => "XX".into(),
-                },
-                judge_start_instant: chrono::NaiveDateTime::parse_from_str(&judgement.judge_start_instant, "%Y-%m-%dT%H:%M:%S%.f").unwrap(),
-                judge_end_instant: chrono::NaiveDateTime::parse_from_str(&judgement.judge_end_instant, "%Y-%m-%dT%H:%M:%S%.f").unwrap(),
-                memory_kib: Some(judgement.memory_kib),
-                time_ms: Some(judgement.time_ms),
-                time_wall_ms: Some(judgement.time_wall_ms),
-                error_output: Some(judgement.error_output)
-            }).unwrap();
+        let progress = match job_progress_receiver.recv().await {
+            Ok(progress) => progress,
+            // Progress is best effort, skipping a few updates is fine
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+        if let Some(message) = format_job_progress(&progress) {
+            broadcaster.lock().unwrap().send_job_progress(&progress.uuid, &message);
         }
     }
 }
@@ -98,6 +174,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .expect("Failed to create pool.");
 
     setup::setup_admin(&pool.get().expect("Couldn't get connection from the pool"));
+    setup::reset_interrupted_work(&pool.get().expect("Couldn't get connection from the pool"));
 
     let mut handlebars = Handlebars::new();
     handlebars
@@ -113,21 +190,38 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .expect("Invalid timezone in environment variable TZ");
 
     let (job_sender, job_receiver) = async_channel::unbounded();
-    let (job_result_sender, job_result_receiver) = broadcast::channel(40);
-
-    let broadcaster = Broadcaster::create(job_result_receiver);
+    let (submission_result_sender, submission_result_receiver) = async_channel::unbounded();
+    let job_results = JobResultRegistry::new(submission_result_sender);
+    let (job_progress_sender, job_progress_receiver) = broadcast::channel(40);
+    let running_jobs: RunningJobs = Arc::new(DashMap::new());
+    let parked_jobs: ParkedJobs = Arc::new(DashMap::new());
+    let superseded_jobs: SupersededJobs = Arc::new(DashMap::new());
+    let handshakes: Handshakes = Arc::new(DashMap::new());
+    let workers: Workers = Arc::new(DashMap::new());
+
+    let broadcaster = Broadcaster::create();
+    actix_web::rt::spawn(forward_job_progress(job_progress_receiver, broadcaster.clone()));
 
     let mut listenfd = ListenFd::from_env();
     let job_sender_data = job_sender.clone();
-    let job_result_sender_data = job_result_sender.clone();
+    let job_results_data = job_results.clone();
     let languages_data = languages.clone();
+    let workers_data = workers.clone();
+    let running_jobs_data = running_jobs.clone();
+    let parked_jobs_data = parked_jobs.clone();
+    let superseded_jobs_data = superseded_jobs.clone();
     let pool_data = pool.clone();
+    let update_database_broadcaster = broadcaster.clone();
     let mut server = HttpServer::new(move || {
         App::new()
             .app_data(Data::new(pool_data.clone()))
             .app_data(Data::new(job_sender_data.clone()))
-            .app_data(Data::new(job_result_sender_data.clone()))
+            .app_data(Data::new(job_results_data.clone()))
             .app_data(Data::new(languages_data.clone()))
+            .app_data(Data::new(workers_data.clone()))
+            .app_data(Data::new(running_jobs_data.clone()))
+            .app_data(Data::new(parked_jobs_data.clone()))
+            .app_data(Data::new(superseded_jobs_data.clone()))
             .app_data(Data::new(tz.clone()))
             .wrap(ErrorHandlers::new().handler(http::StatusCode::UNAUTHORIZED, render_401))
             .wrap(ErrorHandlers::new().handler(http::StatusCode::BAD_REQUEST, render_400))
@@ -146,21 +240,45 @@ async fn main() -> Result<(), Box<dyn Error>> {
             .service(get_login)
             .service(get_me)
             .service(change_password)
+            .service(change_statement_language)
             .service(post_login)
             .service(post_logout)
             .service(get_main)
             .service(get_contests)
             .service(get_contest_by_id)
+            // Before the problem page, which would take `samples.zip` for a label
+            .service(get_contest_samples)
+            .service(get_contest_problem_samples)
             .service(get_contest_problem_by_id_label)
             .service(get_submissions)
             .service(get_submissions_by_contest_id)
             .service(get_submissions_by_contest_id_problem_label)
             .service(create_submission)
+            .service(create_submission_files)
+            .service(create_custom_test)
             .service(create_contest)
+            .service(get_problems)
+            .service(create_problem)
+            .service(create_contest_problem)
+            .service(update_contest_testsets)
+            .service(create_contest_system_tests)
+            .service(update_contest_problem_package)
+            .service(create_contest_problem_rejudge)
+            .service(create_problem_validation)
+            .service(get_problem_tests)
+            .service(get_problem_tests_report)
+            .service(get_package_import)
+            .service(get_package_import_report)
             .service(create_user)
             .service(submission_updates)
+            .service(get_judges)
+            .service(get_judges_json)
+            .service(change_judge_state)
+            .service(cancel_job)
             .service(Files::new("/static/", "./static/"))
-            .service(get_problem_by_id_assets))
+            .service(get_problem_by_id_assets)
+            .service(get_problem_section_assets)
+            .service(get_problem_statement_pdf))
     });
 
     server = if let Some(l) = listenfd
@@ -174,19 +292,36 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     let addr = "0.0.0.0:50051".parse().unwrap();
 
-    let update_dabase_sender = job_result_sender.clone();
+    let job_queuer = JobQueuer {
+        job_sender,
+        job_receiver,
+        job_results: job_results.clone(),
+        job_progress_sender,
+        languages,
+        running_jobs: running_jobs.clone(),
+        workers,
+        handshakes: handshakes.clone(),
+        parked_jobs: parked_jobs.clone(),
+        superseded_jobs,
+    };
+
+    // Judge inside this process, one isolate box each, instead of relying on alvokanto
+    let local_judge_boxes: i32 = env::var("LOCAL_JUDGE_BOXES")
+        .map(|boxes| boxes.parse().expect("Invalid number in LOCAL_JUDGE_BOXES"))
+        .unwrap_or(0);
+    for box_id in 0..local_judge_boxes {
+        actix_web::rt::spawn(worker::run_local(job_queuer.clone(), box_id));
+    }
+
+    actix_web::rt::spawn(queue::watch_deadlines(running_jobs, parked_jobs, handshakes, job_results));
     log::info!("Starting at {}", addr);
     tokio::try_join!(
         server.run().map_err(|e| Into::<Box<dyn Error>>::into(e)),
         Server::builder()
-            .add_service(JobQueueServer::new(JobQueuer {
-                job_receiver,
-                job_result_sender,
-                languages,
-            }))
+            .add_service(JobQueueServer::new(job_queuer))
             .serve(addr)
             .map_err(|e| Into::<Box<dyn Error>>::into(e)),
-        update_database(update_dabase_sender.subscribe(), pool.clone())
+        update_database(submission_result_receiver, pool.clone(), update_database_broadcaster)
             .map_err(|e| Into::<Box<dyn Error>>::into(e))
     )?;
 
@@ -222,6 +357,8 @@ enum PostError {
     Io(#[from] std::io::Error),
     #[error("couldn't work with the zip")]
     Zip(#[from] zip::result::ZipError),
+    #[error("couldn't render")]
+    Render(#[from] handlebars::RenderError),
 }
 
 fn error_response_and_log(me: &impl actix_web::error::ResponseError) -> HttpResponse {
@@ -253,7 +390,8 @@ impl actix_web::error::ResponseError for PostError {
             | PostError::Database(_)
             | PostError::Io(_)
             | PostError::UserHashing(_)
-            | PostError::Zip(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            | PostError::Zip(_)
+            | PostError::Render(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
@@ -274,6 +412,8 @@ enum GetError {
     R2d2Pool(#[from] r2d2::Error),
     #[error("couldn't find file")]
     Io(#[from] std::io::Error),
+    #[error("couldn't write the zip")]
+    Zip(#[from] zip::result::ZipError),
 }
 
 impl actix_web::error::ResponseError for GetError {
@@ -288,7 +428,8 @@ impl actix_web::error::ResponseError for GetError {
             GetError::Render(_)
             | GetError::Actix(_)
             | GetError::Diesel(_)
-            | GetError::R2d2Pool(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            | GetError::R2d2Pool(_)
+            | GetError::Zip(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 }
@@ -325,21 +466,39 @@ async fn get_login(
 async fn get_me(
     flash: Option<flash::Message<String>>,
     identity: Identity,
+    pool: web::Data<DbPool>,
     hb: web::Data<Handlebars<'_>>,
 ) -> GetResult {
     let logged_user = require_identity(identity)?;
     #[derive(Serialize)]
+    struct StatementLanguageContext {
+        value: String,
+        name: String,
+    }
+    #[derive(Serialize)]
     struct MeContext {
         logged_user: LoggedUser,
+        statement_language: Option<String>,
+        statement_languages: Vec<StatementLanguageContext>,
         flash_message: String,
         base_url: String,
     }
+    let connection = pool.get()?;
+    let statement_language = user::get_statement_language(&connection, logged_user.id)?;
     Ok(flash::Response::new(
         None,
         HttpResponse::Ok().body(hb.render(
         "me",
         &MeContext {
             logged_user,
+            statement_language,
+            statement_languages: STATEMENT_LANGUAGES
+                .iter()
+                .map(|(_, value, name)| StatementLanguageContext {
+                    value: value.to_string(),
+                    name: name.to_string(),
+                })
+                .collect(),
             flash_message: flash.map_or("".into(), |f| f.into_inner()),
             base_url: env::var("BASE_URL")
                 .expect("BASE_URL environment variable is not set")
@@ -349,18 +508,240 @@ async fn get_me(
 
 use actix_files::NamedFile;
 
-#[get("/problems/{id}/assets/{filename}")]
+/// Polygon names statements after their language in English, these are the
+/// ones a browser can ask for, with the name shown to choose between them.
+const STATEMENT_LANGUAGES: [(&str, &str, &str); 11] = [
+    ("pt", "portuguese", "Português"),
+    ("en", "english", "English"),
+    ("es", "spanish", "Español"),
+    ("ru", "russian", "Русский"),
+    ("fr", "french", "Français"),
+    ("de", "german", "Deutsch"),
+    ("it", "italian", "Italiano"),
+    ("uk", "ukrainian", "Українська"),
+    ("zh", "chinese", "中文"),
+    ("ja", "japanese", "日本語"),
+    ("ko", "korean", "한국어"),
+];
+
+const DEFAULT_STATEMENT_LANGUAGE: &str = "portuguese";
+
+/// Our name for the language of an ISO 639-1 code, as Kattis names them.
+fn statement_language_by_code(code: &str) -> String {
+    STATEMENT_LANGUAGES
+        .iter()
+        .find(|(iso, _, _)| *iso == code)
+        .map(|(_, polygon_name, _)| polygon_name.to_string())
+        .unwrap_or_else(|| code.into())
+}
+
+fn statement_language_name(language: &str) -> String {
+    STATEMENT_LANGUAGES
+        .iter()
+        .find(|(_, polygon_name, _)| *polygon_name == language)
+        .map(|(_, _, name)| name.to_string())
+        .unwrap_or_else(|| language.into())
+}
+
+/// The statement languages of the request's Accept-Language, most preferred first.
+fn accepted_statement_languages(request: &HttpRequest) -> Vec<&'static str> {
+    let mut accepted: Vec<(f32, &'static str)> = request
+        .headers()
+        .get(http::header::ACCEPT_LANGUAGE)
+        .and_then(|header| header.to_str().ok())
+        .unwrap_or("")
+        .split(',')
+        .filter_map(|range| {
+            let mut parameters = range.split(';');
+            let tag = parameters.next()?.trim().to_lowercase();
+            let quality = parameters
+                .find_map(|parameter| parameter.trim().strip_prefix("q="))
+                .and_then(|quality| quality.parse().ok())
+                .unwrap_or(1.0);
+            let primary = tag.split('-').next()?;
+            STATEMENT_LANGUAGES
+                .iter()
+                .find(|(code, _, _)| *code == primary)
+                .map(|(_, language, _)| (quality, *language))
+        })
+        .filter(|(quality, _)| *quality > 0.0)
+        .collect();
+    // Stable, so languages of the same quality keep the order they were sent in
+    accepted.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+    accepted.into_iter().map(|(_, language)| language).collect()
+}
+
+/// The first of the problem's statement languages the viewer wants, either
+/// chosen explicitly or in their settings, then by the browser, falling back
+/// to Portuguese and then to any statement there is.
+fn choose_statement_language(
+    available: &[String],
+    preferred: Option<String>,
+    request: &HttpRequest,
+) -> Option<String> {
+    preferred
+        .into_iter()
+        .chain(accepted_statement_languages(request).into_iter().map(String::from))
+        .chain(std::iter::once(DEFAULT_STATEMENT_LANGUAGE.into()))
+        .find(|language| available.contains(language))
+        .or_else(|| available.first().cloned())
+}
+
+fn find_statement(
+    statements: Vec<problem::ProblemStatement>,
+    language: &str,
+    mime_type: &str,
+) -> Result<problem::ProblemStatement, GetError> {
+    statements
+        .into_iter()
+        .find(|statement| statement.language == language && statement.mime_type == mime_type)
+        .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound).into())
+}
+
+/// A file next to a statement, as its images and styles are.
+fn open_statement_file(
+    pool: &DbPool,
+    contest_problem_id: i32,
+    language: &str,
+    mime_type: &str,
+    filename: &str,
+) -> Result<NamedFile, GetError> {
+    if filename.contains("..") {
+        return Err(std::io::Error::from(std::io::ErrorKind::NotFound).into());
+    }
+    let connection = pool.get()?;
+    let problem = problem::get_problem_by_contest_id_metadata(&connection, contest_problem_id)?;
+    let statement = find_statement(
+        problem::get_problem_statements_by_contest_id(&connection, contest_problem_id)?,
+        language,
+        mime_type,
+    )?;
+    let directory = PathBuf::from(&statement.path)
+        .parent()
+        .map(PathBuf::from)
+        .unwrap_or_default();
+    let file_path = PathBuf::from("/data/").join(problem.id).join(directory).join(filename);
+    Ok(NamedFile::open(file_path)?)
+}
+
+#[get("/problems/{id}/statements/{language}/{filename}")]
 async fn get_problem_by_id_assets(
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    path: web::Path<(i32, String, String,)>,
+) -> Result<NamedFile, GetError> {
+    require_identity(identity)?;
+    let (contest_problem_id, language, filename) = path.into_inner();
+    open_statement_file(&pool, contest_problem_id, &language, "text/html", &filename)
+}
+
+#[get("/problems/{id}/sections/{language}/{filename}")]
+async fn get_problem_section_assets(
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    path: web::Path<(i32, String, String,)>,
+) -> Result<NamedFile, GetError> {
+    require_identity(identity)?;
+    let (contest_problem_id, language, filename) = path.into_inner();
+    open_statement_file(&pool, contest_problem_id, &language, "application/json", &filename)
+}
+
+#[derive(Serialize, Clone)]
+struct FormattedSample {
+    input: String,
+    output: String,
+}
+
+#[derive(Serialize, Clone)]
+struct FormattedStatementSections {
+    legend: Option<String>,
+    input: Option<String>,
+    output: Option<String>,
+    interaction: Option<String>,
+    scoring: Option<String>,
+    notes: Option<String>,
+    samples: Vec<FormattedSample>,
+    mathjax: bool,
+}
+
+/// The input and answer of each sample test of a contest problem's testset.
+fn read_samples(
+    connection: &PgConnection,
+    contest_problem_id: i32,
+    testset: &str,
+) -> Result<Vec<(Vec<u8>, Vec<u8>)>, GetError> {
+    let metadata = problem::get_problem_by_contest_id_testset_metadata(connection, contest_problem_id, testset)?;
+    let data_path = PathBuf::from("/data/").join(&metadata.id);
+    problem::get_problem_samples(connection, &metadata.id, testset)?
+        .into_iter()
+        .map(|test| {
+            let test_path =
+                data_path.join(import_contest::format_width(&metadata.test_pattern, test as usize));
+            Ok((fs::read(&test_path)?, fs::read(format!("{}.a", test_path.display()))?))
+        })
+        .collect()
+}
+
+/// The statement sections of a language in HTML, with the samples of the
+/// testset judged during the contest.
+fn get_statement_sections(
+    connection: &PgConnection,
+    contest_problem_id: i32,
+    testset: &str,
+    statement: &problem::ProblemStatement,
+) -> Result<FormattedStatementSections, GetError> {
+    let metadata = problem::get_problem_by_contest_id_testset_metadata(connection, contest_problem_id, testset)?;
+    let data_path = PathBuf::from("/data/").join(&metadata.id);
+    let properties: statement_sections::StatementProperties =
+        serde_json::from_str(&fs::read_to_string(data_path.join(&statement.path))?)
+            .map_err(|e| GetError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, e)))?;
+
+    let base_url = env::var("BASE_URL").expect("BASE_URL environment variable is not set");
+    let image_url = |name: &str| {
+        format!("{}problems/{}/sections/{}/{}", base_url, contest_problem_id, statement.language, name)
+    };
+    let section = |tex: &Option<String>| {
+        tex.as_deref()
+            .filter(|tex| !tex.trim().is_empty())
+            .map(|tex| statement_sections::tex_to_html(tex, &image_url))
+    };
+
+    let samples = read_samples(connection, contest_problem_id, testset)?
+        .into_iter()
+        .map(|(input, output)| FormattedSample {
+            input: String::from_utf8_lossy(&input).into(),
+            output: String::from_utf8_lossy(&output).into(),
+        })
+        .collect();
+
+    Ok(FormattedStatementSections {
+        legend: section(&properties.legend),
+        input: section(&properties.input),
+        output: section(&properties.output),
+        interaction: section(&properties.interaction),
+        scoring: section(&properties.scoring),
+        notes: section(&properties.notes),
+        samples,
+        mathjax: statement.mathjax,
+    })
+}
+
+#[get("/problems/{id}/pdf/{language}")]
+async fn get_problem_statement_pdf(
     identity: Identity,
     pool: web::Data<DbPool>,
     path: web::Path<(i32, String,)>,
 ) -> Result<NamedFile, GetError> {
     require_identity(identity)?;
-    let path = path.into_inner();
+    let (contest_problem_id, language) = path.into_inner();
     let connection = pool.get()?;
-    let problem = problem::get_problem_by_contest_id_metadata(&connection, path.0)?;
-    let file_path = PathBuf::from("/data/").join(problem.id).join("statements/.html/portuguese/").join(path.1);
-    Ok(NamedFile::open(file_path)?)
+    let problem = problem::get_problem_by_contest_id_metadata(&connection, contest_problem_id)?;
+    let statement = find_statement(
+        problem::get_problem_statements_by_contest_id(&connection, contest_problem_id)?,
+        &language,
+        "application/pdf",
+    )?;
+    Ok(NamedFile::open(PathBuf::from("/data/").join(problem.id).join(statement.path))?)
 }
 
 use actix_web::Responder;
@@ -412,6 +793,7 @@ struct LoginForm {
 }
 
 use models::problem;
+use models::package_import;
 use models::problem::ProblemByContest;
 
 fn get_identity(identity: Identity) -> Option<LoggedUser> {
@@ -452,6 +834,7 @@ async fn get_contest_by_id(
     struct ContestContext {
         contest: FormattedContest,
         problems: Vec<FormattedProblemByContestWithScore>,
+        testsets: Vec<String>,
         submissions: Vec<FormattedSubmission>,
         logged_user: LoggedUser,
         flash_message: String,
@@ -469,6 +852,7 @@ async fn get_contest_by_id(
 
     let problems = problem::get_problems_user_by_contest_id_with_score(&connection, logged_user.id, path.0)?;
     let submissions = submission::get_submissions_user_by_contest(&connection, logged_user.id, path.0)?;
+    let testsets = problem::get_testset_names_by_contest_id(&connection, path.0)?;
 
     Ok(flash::Response::new(
         None,
@@ -486,6 +870,7 @@ async fn get_contest_by_id(
                     memory_limit_mib: p.memory_limit_bytes / 1_024 / 1_024,
                     time_limit: format!("{}", f64::from(p.time_limit_ms) / 1000.0).replacen(".", ",", 1),
                 }).collect(),
+                testsets,
                 logged_user,
                 flash_message: flash.map_or("".into(), |f| f.into_inner()),
                 base_url: env::var("BASE_URL")
@@ -499,6 +884,96 @@ async fn get_contest_by_id(
     )))
 }
 
+/// Adds the samples of a contest problem to a zip as `{prefix}{n}.in` and
+/// `{prefix}{n}.out`, the names competitive-companion and the like expect.
+fn write_samples(
+    connection: &PgConnection,
+    zip: &mut zip::ZipWriter<Cursor<Vec<u8>>>,
+    contest_problem_id: i32,
+    prefix: &str,
+) -> Result<(), GetError> {
+    let testset = problem::get_contest_testset(connection, contest_problem_id)?;
+    for (i, (input, output)) in read_samples(connection, contest_problem_id, &testset)?
+        .into_iter()
+        .enumerate()
+    {
+        zip.start_file(format!("{}{}.in", prefix, i + 1), zip::write::FileOptions::default())?;
+        zip.write_all(&input)?;
+        zip.start_file(format!("{}{}.out", prefix, i + 1), zip::write::FileOptions::default())?;
+        zip.write_all(&output)?;
+    }
+    Ok(())
+}
+
+fn zip_response(filename: &str, mut zip: zip::ZipWriter<Cursor<Vec<u8>>>) -> Result<HttpResponse, GetError> {
+    Ok(HttpResponse::Ok()
+        .append_header(("content-type", "application/zip"))
+        .append_header((
+            "content-disposition",
+            format!("attachment; filename=\"{}\"", filename),
+        ))
+        .body(zip.finish()?.into_inner()))
+}
+
+/// Contestants only get at a contest's problems once it starts.
+fn require_started_contest(contest: &Contest, logged_user: &LoggedUser) -> Result<(), UnauthorizedError> {
+    if contest.start_instant.map(|s| s > Local::now().naive_utc()).unwrap_or(false) && !logged_user.is_admin {
+        return Err(UnauthorizedError {});
+    }
+    Ok(())
+}
+
+#[get("/contests/{id}/samples.zip")]
+async fn get_contest_samples(
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    path: web::Path<(i32,)>,
+) -> Result<HttpResponse, GetError> {
+    let logged_user = require_identity(identity)?;
+    let (contest_id,) = path.into_inner();
+    let connection = pool.get()?;
+    let contest = contest::get_contest_by_id(&connection, contest_id)?;
+    require_started_contest(&contest, &logged_user)?;
+
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    for problem in problem::get_problems_by_contest_id(&connection, contest.id)? {
+        write_samples(&connection, &mut zip, problem.id, &format!("{}/", problem.label))?;
+    }
+    zip_response(&format!("samples-{}.zip", contest.id), zip)
+}
+
+#[get("/contests/{id}/{label}/samples.zip")]
+async fn get_contest_problem_samples(
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    path: web::Path<(i32, String)>,
+) -> Result<HttpResponse, GetError> {
+    let logged_user = require_identity(identity)?;
+    let (contest_id, label) = path.into_inner();
+    let connection = pool.get()?;
+    let contest = contest::get_contest_by_id(&connection, contest_id)?;
+    require_started_contest(&contest, &logged_user)?;
+    let problem = problem::get_problem_by_contest_id_label(&connection, contest.id, &label)?;
+
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    write_samples(&connection, &mut zip, problem.id, "")?;
+    zip_response(&format!("samples-{}-{}.zip", contest.id, problem.label), zip)
+}
+
+#[derive(Serialize, Clone)]
+struct FormattedStatement {
+    language: String,
+    name: String,
+    html_file: Option<String>,
+    pdf: bool,
+    sections: Option<FormattedStatementSections>,
+}
+
+#[derive(Deserialize)]
+struct StatementQuery {
+    statement_language: Option<String>,
+}
+
 #[get("/contests/{id}/{label}")]
 async fn get_contest_problem_by_id_label(
     flash: Option<flash::Message<String>>,
@@ -508,7 +983,9 @@ async fn get_contest_problem_by_id_label(
     languages: web::Data<Arc<DashMap<String, Language>>>,
     session: Session,
     path: web::Path<(i32,String)>,
+    query: web::Query<StatementQuery>,
     tz: web::Data<Tz>,
+    request: HttpRequest,
 ) -> GetResult {
     let logged_user = require_identity(identity)?;
 
@@ -526,6 +1003,8 @@ async fn get_contest_problem_by_id_label(
         contest: FormattedContest,
         problems: Vec<ProblemByContest>,
         problem: ProblemByContest,
+        statement: Option<FormattedStatement>,
+        statements: Vec<FormattedStatement>,
         submissions: Vec<FormattedSubmission>,
         flash_message: String,
         base_url: String,
@@ -549,6 +1028,53 @@ async fn get_contest_problem_by_id_label(
     let problem = problem::get_problem_by_contest_id_label(&connection, path.0, &path.1)?;
     let submissions = submission::get_submissions_user_by_contest_problem(&connection, logged_user.id, path.0, &path.1)?;
 
+    let problem_statements = problem::get_problem_statements_by_contest_id(&connection, problem.id)?;
+    let mut statements: Vec<FormattedStatement> = Vec::new();
+    for problem_statement in &problem_statements {
+        let index = match statements.iter().position(|s| s.language == problem_statement.language) {
+            Some(index) => index,
+            None => {
+                statements.push(FormattedStatement {
+                    name: statement_language_name(&problem_statement.language),
+                    language: problem_statement.language.clone(),
+                    html_file: None,
+                    pdf: false,
+                    sections: None,
+                });
+                statements.len() - 1
+            }
+        };
+        match problem_statement.mime_type.as_str() {
+            "text/html" => {
+                statements[index].html_file = PathBuf::from(&problem_statement.path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into())
+            }
+            "application/pdf" => statements[index].pdf = true,
+            _ => {}
+        }
+    }
+    let preferred = match &query.statement_language {
+        Some(language) => Some(language.clone()),
+        None => user::get_statement_language(&connection, logged_user.id)?,
+    };
+    let available: Vec<String> = statements.iter().map(|s| s.language.clone()).collect();
+    let mut statement = choose_statement_language(&available, preferred, &request)
+        .and_then(|language| statements.iter().find(|s| s.language == language).cloned());
+    // Only the chosen statement is rendered natively, the others are just links
+    if let Some(statement) = &mut statement {
+        if let Some(problem_statement) = problem_statements
+            .iter()
+            .find(|s| s.language == statement.language && s.mime_type == "application/json")
+        {
+            match get_statement_sections(&connection, problem.id, &contest.contest_testset, problem_statement) {
+                Ok(sections) => statement.sections = Some(sections),
+                // Polygon's HTML is still there to fall back to
+                Err(e) => error!("Couldn't render statement sections of {}: {}", problem.id, e),
+            }
+        }
+    }
+
     Ok(flash::Response::new(
         None,
         HttpResponse::Ok().body(
@@ -559,6 +1085,8 @@ async fn get_contest_problem_by_id_label(
                 languages,
                 problems,
                 problem,
+                statement,
+                statements,
                 flash_message: flash.map_or("".into(), |f| f.into_inner()),
                 base_url: env::var("BASE_URL")
                     .expect("BASE_URL environment variable is not set"),
@@ -630,11 +1158,18 @@ async fn post_login(
 }
 
 #[get("/submission_updates/")]
-async fn submission_updates(broadcaster: web::Data<Mutex<Broadcaster>>) -> HttpResponse {
+async fn submission_updates(
+    identity: Identity,
+    broadcaster: web::Data<Mutex<Broadcaster>>,
+) -> HttpResponse {
+    let logged_user = get_identity(identity);
     let rx = broadcaster
         .lock()
         .expect("Submission broadcaster is not active")
-        .new_client();
+        .new_client(
+            logged_user.as_ref().map(|logged_user| logged_user.id),
+            logged_user.map(|logged_user| logged_user.is_admin).unwrap_or(false),
+        );
 
     HttpResponse::Ok()
         .append_header(("content-type", "text/event-stream"))
@@ -648,6 +1183,26 @@ struct FormattedSubmission {
     problem_label: String,
     submission_instant: String,
     error_output: Option<String>,
+    compilation_output: Option<String>,
+}
+
+// Long enough for the first few compiler errors, which are the ones that matter
+const SUBMISSION_OUTPUT_LIMIT_LINES: usize = 30;
+const SUBMISSION_OUTPUT_LIMIT_CHARS: usize = 4_000;
+
+fn truncate_output(output: &str) -> String {
+    let mut truncated = output
+        .lines()
+        .take(SUBMISSION_OUTPUT_LIMIT_LINES)
+        .collect::<Vec<_>>()
+        .join("\n");
+    if let Some((index, _)) = truncated.char_indices().nth(SUBMISSION_OUTPUT_LIMIT_CHARS) {
+        truncated.truncate(index);
+    }
+    if truncated.len() < output.trim_end().len() {
+        truncated.push_str("\n[...]");
+    }
+    truncated
 }
 
 fn format_utc_date_time(tz: &Tz, input: NaiveDateTime) -> String {
@@ -671,7 +1226,15 @@ fn format_submission(
             .into(),
         problem_label: contest_problem.label.clone(),
         submission_instant: format_utc_date_time(tz, submission.submission_instant),
-        error_output: submission.error_output.as_ref().map(|s| s.into()),
+        error_output: submission.error_output.as_ref().map(|s| truncate_output(s)),
+        compilation_output: match (&submission.compilation_stdout, &submission.compilation_stderr) {
+            (None, None) => None,
+            (stdout, stderr) => Some(truncate_output(&format!(
+                "{}{}",
+                stderr.as_deref().unwrap_or(""),
+                stdout.as_deref().unwrap_or("")
+            ))),
+        },
     }
 }
 
@@ -826,6 +1389,31 @@ async fn change_password(
     }
 }
 
+#[derive(Serialize, Deserialize)]
+struct StatementLanguageForm {
+    statement_language: String,
+}
+
+#[post("/me/statement_language")]
+async fn change_statement_language(
+    identity: Identity,
+    form: web::Form<StatementLanguageForm>,
+    pool: web::Data<DbPool>,
+    request: HttpRequest,
+) -> PostResult {
+    let identity = require_identity(identity)?;
+    // Empty goes back to the browser's languages
+    let statement_language = match form.statement_language.as_str() {
+        "" => None,
+        language if STATEMENT_LANGUAGES.iter().any(|(_, l, _)| *l == language) => Some(language),
+        _ => return Err(PostError::Validation("Idioma inexistente".into())),
+    };
+
+    let connection = pool.get()?;
+    user::set_statement_language(&connection, identity.id, statement_language)?;
+    Ok(redirect_to_referer("Idioma dos enunciados alterado com sucesso".into(), &request))
+}
+
 #[derive(Serialize, Deserialize)]
 struct CreateUserForm {
     name: String,
@@ -858,104 +1446,382 @@ async fn create_user(
     Ok(redirect_to_referer("Usuário criado com sucesso".into(), &request))
 }
 
-#[post("/submissions/")]
-async fn create_submission(
-    identity: Identity,
-    form: web::Form<SubmissionForm>,
-    pool: web::Data<DbPool>,
-    job_sender: web::Data<Sender<Job>>,
-    languages: web::Data<Arc<DashMap<String, Language>>>,
-    session: Session,
-    request: HttpRequest,
-) -> PostResult {
-    let identity = require_identity(identity)?;
-    let connection = pool.get()?;
-
-    languages
-        .get(&form.language)
-        .ok_or(PostError::Validation("Linguagem inexistente".into()))?;
-
-    let uuid = Uuid::new_v4();
-    submission::insert_submission(
-        &connection,
-        submission::NewSubmission {
-            uuid: uuid.to_string(),
-            source_text: (&form.source_text).into(),
-            language: (&form.language).into(),
-            submission_instant: Local::now().naive_utc(),
-            contest_problem_id: form.contest_problem_id,
-            user_id: identity.id,
-        },
-    )?;
-
-    let metadata =
-        problem::get_problem_by_contest_id_metadata(&connection, form.contest_problem_id)?;
-
-    job_sender.send(Job {
-        uuid: uuid.to_string(),
-        language: (&form.language).into(),
-        time_limit_ms: metadata.time_limit_ms,
-        memory_limit_kib: metadata.memory_limit_bytes / 1_024,
-
-        which: Some(job::Which::Judgement(job::Judgement {
-            source_text: (&form.source_text).into(),
-            test_count: metadata.test_count,
-            test_pattern: format!("./{}/{}", metadata.id, metadata.test_pattern).into(),
-            checker_language: metadata.checker_language,
-            checker_source_path: format!("./{}/{}", metadata.id, metadata.checker_path).into(),
-        }))
-    }).await?;
-
-    session.insert("language", &form.language)?;
-
-    Ok(redirect_to_referer(format!("Submetido {} com sucesso!", uuid), &request))
-}
-
 #[derive(Serialize)]
-struct FormattedContest {
-    pub id: i32,
-    pub name: String,
-    pub start_instant: Option<String>,
-    pub end_instant: Option<String>,
-    pub creation_instant: String,
-}
-
-fn get_formatted_contest(tz: &Tz, contest: &Contest) -> FormattedContest {
-    FormattedContest {
-        id: contest.id,
-        name: contest.name.clone(),
-        start_instant: contest.start_instant.map(|i| format_utc_date_time(&tz, i)),
-        end_instant: contest.end_instant.map(|i| format_utc_date_time(&tz, i)),
-        creation_instant: format_utc_date_time(&tz, contest.creation_instant),
-    }
+struct FormattedWorker {
+    id: String,
+    host: String,
+    languages: Vec<String>,
+    box_count: i32,
+    state: String,
+    current_job: Option<String>,
+    connected_instant: String,
+    last_heartbeat_instant: String,
+    jobs_done: i32,
+    failures: i32,
+    protocol_version: i32,
+    features: Vec<String>,
+    average_compile_time_ms: Option<i64>,
 }
 
-fn get_formatted_contests(pool: &DbPool, tz: &Tz) -> Result<Vec<FormattedContest>, GetError> {
-    let connection = pool.get()?;
-    let contests = contest::get_contests(&connection)?;
-
-    Ok(contests
+fn get_formatted_workers(tz: &Tz, workers: &Workers) -> Vec<FormattedWorker> {
+    let mut formatted_workers = workers
         .iter()
-        .map(|c| get_formatted_contest(tz, c))
-    .collect())
+        .map(|kv| {
+            let worker = kv.value();
+            FormattedWorker {
+                id: worker.id.clone(),
+                host: worker.host.clone(),
+                languages: worker.languages.clone(),
+                box_count: worker.box_count,
+                state: match worker.state {
+                    WorkerState::Active => "active",
+                    WorkerState::Paused => "paused",
+                    WorkerState::Draining => "draining",
+                }
+                .into(),
+                current_job: worker.current_job.clone(),
+                connected_instant: format_utc_date_time(tz, worker.connected_instant),
+                last_heartbeat_instant: format_utc_date_time(tz, worker.last_heartbeat_instant),
+                jobs_done: worker.jobs_done,
+                failures: worker.failures,
+                protocol_version: worker.protocol_version,
+                features: queue::feature_names(&worker.features),
+                average_compile_time_ms: match worker.compilations {
+                    0 => None,
+                    compilations => Some(worker.compile_time_ms / compilations as i64),
+                },
+            }
+        })
+        .collect::<Vec<_>>();
+    formatted_workers.sort_by(|a, b| a.id.cmp(&b.id));
+    formatted_workers
 }
 
-#[get("/")]
-async fn get_main(
+#[get("/judges/")]
+async fn get_judges(
     flash: Option<flash::Message<String>>,
     identity: Identity,
-    pool: web::Data<DbPool>,
     hb: web::Data<Handlebars<'_>>,
+    workers: web::Data<Workers>,
     tz: web::Data<Tz>,
 ) -> GetResult {
-    let logged_user = get_identity(identity);
+    let logged_user = require_identity(identity)?;
+    if !logged_user.is_admin {
+        return Err(GetError::Unauthorized(UnauthorizedError {}));
+    }
 
     #[derive(Serialize)]
-    struct MainContext {
-        logged_user: Option<LoggedUser>,
+    struct JudgesContext {
+        logged_user: LoggedUser,
         flash_message: String,
         base_url: String,
-        contests: Vec<FormattedContest>
+        workers: Vec<FormattedWorker>,
+    }
+
+    Ok(flash::Response::new(
+        None,
+        HttpResponse::Ok().body(
+        hb.render(
+            "judges",
+            &JudgesContext {
+                logged_user,
+                flash_message: flash.map_or("".into(), |f| f.into_inner()),
+                base_url: env::var("BASE_URL")
+                    .expect("BASE_URL environment variable is not set"),
+                workers: get_formatted_workers(&tz, &workers),
+            },
+        )?,
+    )))
+}
+
+#[get("/judges.json")]
+async fn get_judges_json(
+    identity: Identity,
+    workers: web::Data<Workers>,
+    tz: web::Data<Tz>,
+) -> Result<HttpResponse, GetError> {
+    let logged_user = require_identity(identity)?;
+    if !logged_user.is_admin {
+        return Err(GetError::Unauthorized(UnauthorizedError {}));
+    }
+
+    Ok(HttpResponse::Ok().json(get_formatted_workers(&tz, &workers)))
+}
+
+#[post("/judges/{id}/{action}")]
+async fn change_judge_state(
+    identity: Identity,
+    workers: web::Data<Workers>,
+    path: web::Path<(String, String)>,
+    request: HttpRequest,
+) -> PostResult {
+    let logged_user = require_identity(identity)?;
+    if !logged_user.is_admin {
+        return Err(PostError::Unauthorized(UnauthorizedError {}));
+    }
+
+    let (id, action) = path.into_inner();
+    let (state, message) = match action.as_str() {
+        "pause" => (WorkerState::Paused, "Juiz pausado"),
+        "resume" => (WorkerState::Active, "Juiz retomado"),
+        "drain" => (WorkerState::Draining, "Juiz será desligado após o trabalho atual"),
+        _ => return Err(PostError::Validation("Ação inválida".into())),
+    };
+
+    if !queue::set_worker_state(&workers, &id, state) {
+        return Err(PostError::Validation("Juiz não está conectado".into()));
+    }
+
+    Ok(redirect_to_referer(message.into(), &request))
+}
+
+#[post("/jobs/{uuid}/cancel")]
+async fn cancel_job(
+    identity: Identity,
+    running_jobs: web::Data<RunningJobs>,
+    job_results: web::Data<JobResultRegistry>,
+    path: web::Path<(String,)>,
+    request: HttpRequest,
+) -> PostResult {
+    let logged_user = require_identity(identity)?;
+    if !logged_user.is_admin {
+        return Err(PostError::Unauthorized(UnauthorizedError {}));
+    }
+
+    let (uuid,) = path.into_inner();
+    if !queue::cancel_job(&running_jobs, &job_results, &uuid, job_result::Code::Cancelled) {
+        return Err(PostError::Validation("Trabalho não está em execução".into()));
+    }
+
+    Ok(redirect_to_referer(format!("Trabalho {} cancelado", uuid), &request))
+}
+
+fn judgement_job(
+    uuid: &Uuid,
+    language: &str,
+    metadata: problem::ProblemByContestMetadata,
+    source_text: String,
+    source_files: Vec<job::SourceFile>,
+) -> Job {
+    let multi_file = !source_files.is_empty() || !metadata.grader_paths.is_empty();
+    let checker_protocol = language::checker_protocol(&metadata.package_format);
+    Job {
+        uuid: uuid.to_string(),
+        language: language.into(),
+        time_limit_ms: metadata.time_limit_ms,
+        memory_limit_kib: metadata.memory_limit_bytes / 1_024,
+        required_features: [
+            (Feature::MultiFile, multi_file),
+            (Feature::OutputValidators, checker_protocol == job::judgement::CheckerProtocol::Kattis),
+        ]
+        .iter()
+        .filter(|(_, required)| *required)
+        .map(|(feature, _)| (*feature).into())
+        .collect(),
+
+        which: Some(job::Which::Judgement(job::Judgement {
+            source_text,
+            test_count: metadata.test_count,
+            test_pattern: format!("./{}/{}", metadata.id, metadata.test_pattern).into(),
+            checker_language: metadata.checker_language.clone(),
+            checker_source_path: format!("./{}/{}", metadata.id, metadata.checker_path).into(),
+            grader_paths: metadata
+                .grader_paths
+                .iter()
+                .map(|path| format!("./{}/{}", metadata.id, path))
+                .collect(),
+            source_files,
+            checker_protocol: checker_protocol.into(),
+            checker_arguments: metadata.checker_arguments,
+        }))
+    }
+}
+
+#[post("/submissions/")]
+async fn create_submission(
+    identity: Identity,
+    form: web::Form<SubmissionForm>,
+    pool: web::Data<DbPool>,
+    job_sender: web::Data<Sender<Job>>,
+    languages: web::Data<Arc<DashMap<String, Language>>>,
+    broadcaster: web::Data<Mutex<Broadcaster>>,
+    session: Session,
+    request: HttpRequest,
+) -> PostResult {
+    let identity = require_identity(identity)?;
+    let connection = pool.get()?;
+
+    let multi_file = languages
+        .get(&form.language)
+        .ok_or(PostError::Validation("Linguagem inexistente".into()))?
+        .multi_file;
+
+    let metadata =
+        problem::get_problem_by_contest_id_metadata(&connection, form.contest_problem_id)?;
+    if !metadata.grader_paths.is_empty() && !multi_file {
+        return Err(PostError::Validation("Linguagem não suporta múltiplos arquivos".into()));
+    }
+
+    let uuid = Uuid::new_v4();
+    submission::insert_submission(
+        &connection,
+        submission::NewSubmission {
+            uuid: uuid.to_string(),
+            source_text: (&form.source_text).into(),
+            language: (&form.language).into(),
+            submission_instant: Local::now().naive_utc(),
+            contest_problem_id: form.contest_problem_id,
+            user_id: identity.id,
+        },
+    )?;
+
+    broadcaster.lock().unwrap().watch_job(&uuid.to_string(), identity.id);
+    job_sender.send(judgement_job(
+        &uuid,
+        &form.language,
+        metadata,
+        (&form.source_text).into(),
+        vec![],
+    )).await?;
+
+    session.insert("language", &form.language)?;
+
+    Ok(redirect_to_referer(format!("Submetido {} com sucesso!", uuid), &request))
+}
+
+#[derive(Serialize, Deserialize)]
+struct CustomTestForm {
+    contest_problem_id: i32,
+    language: String,
+    source_text: String,
+    stdin_text: String,
+}
+
+#[post("/custom_tests/")]
+async fn create_custom_test(
+    identity: Identity,
+    form: web::Form<CustomTestForm>,
+    pool: web::Data<DbPool>,
+    hb: web::Data<Handlebars<'_>>,
+    job_sender: web::Data<Sender<Job>>,
+    job_results: web::Data<JobResultRegistry>,
+    languages: web::Data<Arc<DashMap<String, Language>>>,
+) -> PostResult {
+    let logged_user = require_identity(identity)?;
+    let connection = pool.get()?;
+
+    languages
+        .get(&form.language)
+        .ok_or(PostError::Validation("Linguagem inexistente".into()))?;
+
+    let contest_problem = contest::get_contest_problem_by_id(&connection, form.contest_problem_id)?;
+    let contest = contest::get_contest_by_id(&connection, contest_problem.contest_id)?;
+    require_started_contest(&contest, &logged_user)?;
+
+    let metadata =
+        problem::get_problem_by_contest_id_metadata(&connection, form.contest_problem_id)?;
+
+    let form = form.into_inner();
+    let run = language::run_program(
+        &job_sender,
+        &job_results,
+        &form.language,
+        form.source_text,
+        form.stdin_text,
+        metadata.memory_limit_bytes / 1_024,
+        metadata.time_limit_ms,
+    )
+    .await
+    .map_err(|e| {
+        if e.is::<language::JobTimedOutError>() {
+            PostError::Custom("O teste demorou demais para ser executado".into())
+        } else {
+            PostError::Custom("Não foi possível executar o teste".into())
+        }
+    })?;
+
+    #[derive(Serialize)]
+    struct CustomTestResultContext {
+        result: String,
+        time_ms: i32,
+        memory_kib: i32,
+        exit_code: i32,
+        stdout_text: String,
+        stderr_text: String,
+    }
+
+    Ok(flash::Response::new(
+        None,
+        HttpResponse::Ok().body(hb.render(
+            "custom_test_result",
+            &CustomTestResultContext {
+                result: match job_result::run_program::Result::from_i32(run.result) {
+                    Some(job_result::run_program::Result::Ok) => "OK",
+                    Some(job_result::run_program::Result::CompilationError) => "CE",
+                    Some(job_result::run_program::Result::TimeLimitExceeded) => "TL",
+                    Some(job_result::run_program::Result::MemoryLimitExceeded) => "ML",
+                    Some(job_result::run_program::Result::RuntimeError) | None => "RE",
+                }
+                .into(),
+                time_ms: run.time_ms,
+                memory_kib: run.memory_kib,
+                exit_code: run.exit_code,
+                stdout_text: run.stdout_text,
+                stderr_text: run.stderr_text,
+            },
+        )?),
+    ))
+}
+
+#[derive(Serialize)]
+struct FormattedContest {
+    pub id: i32,
+    pub name: String,
+    pub start_instant: Option<String>,
+    pub end_instant: Option<String>,
+    pub creation_instant: String,
+    pub contest_testset: String,
+    pub system_testset: String,
+}
+
+fn get_formatted_contest(tz: &Tz, contest: &Contest) -> FormattedContest {
+    FormattedContest {
+        id: contest.id,
+        name: contest.name.clone(),
+        start_instant: contest.start_instant.map(|i| format_utc_date_time(&tz, i)),
+        end_instant: contest.end_instant.map(|i| format_utc_date_time(&tz, i)),
+        creation_instant: format_utc_date_time(&tz, contest.creation_instant),
+        contest_testset: contest.contest_testset.clone(),
+        system_testset: contest.system_testset.clone(),
+    }
+}
+
+fn get_formatted_contests(pool: &DbPool, tz: &Tz) -> Result<Vec<FormattedContest>, GetError> {
+    let connection = pool.get()?;
+    let contests = contest::get_contests(&connection)?;
+
+    Ok(contests
+        .iter()
+        .map(|c| get_formatted_contest(tz, c))
+    .collect())
+}
+
+#[get("/")]
+async fn get_main(
+    flash: Option<flash::Message<String>>,
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    hb: web::Data<Handlebars<'_>>,
+    tz: web::Data<Tz>,
+) -> GetResult {
+    let logged_user = get_identity(identity);
+
+    #[derive(Serialize)]
+    struct MainContext {
+        logged_user: Option<LoggedUser>,
+        flash_message: String,
+        base_url: String,
+        contests: Vec<FormattedContest>
     }
 
     Ok(flash::Response::new(
@@ -1016,327 +1882,2784 @@ use regex::Regex;
 use std::fs::create_dir_all;
 use std::io::Cursor;
 use std::io::Read;
+use std::io::Seek;
 use std::io::Write;
 use std::iter::FromIterator;
 use std::str;
 use std::path::PathBuf;
+use zip::ZipArchive;
+use import_contest::generator_script::{self, TestSource};
+use import_contest::statement_sections;
+
+/// Reads a whole field, refusing it once it's over `limit` bytes.
+async fn read_field(
+    field: &mut actix_multipart::Field,
+    limit: usize,
+) -> Result<Cursor<Vec<u8>>, PostError> {
+    let mut cursor = Cursor::new(vec![]);
+    while let Some(chunk) = field.next().await {
+        let data = chunk.map_err(|_| PostError::Validation("Corpo inválido".into()))?;
+        if cursor.get_ref().len() + data.len() > limit {
+            return Err(PostError::Validation("Arquivo grande demais".into()));
+        }
+        cursor
+            .write(&data)
+            .map_err(|_| PostError::Validation("Corpo inválido".into()))?;
+    }
 
-#[post("/contests/")]
-async fn create_contest(
+    cursor.set_position(0);
+    Ok(cursor)
+}
+
+fn parse_field(field: &str, cursor: &mut Cursor<Vec<u8>>) -> Result<String, PostError> {
+    let mut value = String::new();
+    cursor
+        .read_to_string(&mut value)
+        .map_err(|_| PostError::Validation(format!("Campo {} inválido", field)))?;
+    Ok(value)
+}
+
+const SUBMISSION_FILE_LIMIT: usize = 32;
+const SUBMISSION_SIZE_LIMIT: usize = 1_024 * 1_024;
+const PACKAGE_SIZE_LIMIT: usize = 1_024 * 1_024 * 1_024;
+
+/// Adds a file sent by the contestant, flattened to its base name, as the
+/// judge keeps every file in the same directory.
+fn push_source_file(
+    source_files: &mut Vec<job::SourceFile>,
+    path: &str,
+    reader: &mut impl Read,
+) -> Result<(), PostError> {
+    lazy_static! {
+        static ref SOURCE_FILE_NAME_REGEX: Regex = Regex::new(r"^[A-Za-z0-9_-]+\.[A-Za-z0-9]+$").unwrap();
+    }
+    let name = path.rsplit('/').next().unwrap_or("");
+    if !SOURCE_FILE_NAME_REGEX.is_match(name) {
+        return Err(PostError::Validation(format!("Nome de arquivo {} inválido", path)));
+    }
+    if source_files.iter().any(|source_file| source_file.name == name) {
+        return Err(PostError::Validation(format!("Arquivo {} repetido", name)));
+    }
+    if source_files.len() >= SUBMISSION_FILE_LIMIT {
+        return Err(PostError::Validation("Arquivos demais".into()));
+    }
+
+    // Read no further than the limit, zip entries can unpack to far more
+    // than they take
+    let remaining = SUBMISSION_SIZE_LIMIT
+        - source_files.iter().map(|source_file| source_file.text.len()).sum::<usize>();
+    let mut bytes = Vec::new();
+    reader
+        .take(remaining as u64 + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|_| PostError::Validation(format!("Arquivo {} inválido", name)))?;
+    if bytes.len() > remaining {
+        return Err(PostError::Validation("Arquivos grandes demais".into()));
+    }
+    let text = String::from_utf8(bytes)
+        .map_err(|_| PostError::Validation(format!("Arquivo {} não é texto", name)))?;
+    source_files.push(job::SourceFile {
+        name: name.into(),
+        text,
+    });
+    Ok(())
+}
+
+#[post("/submissions/files/")]
+async fn create_submission_files(
     identity: Identity,
     pool: web::Data<DbPool>,
     mut payload: Multipart,
     job_sender: web::Data<Sender<Job>>,
-    job_result_sender: web::Data<broadcast::Sender<JobResult>>,
-    tz: web::Data<Tz>,
+    languages: web::Data<Arc<DashMap<String, Language>>>,
+    broadcaster: web::Data<Mutex<Broadcaster>>,
+    session: Session,
+    request: HttpRequest,
 ) -> PostResult {
-    let logged_user = require_identity(identity)?;
-    if !logged_user.is_admin {
-      return Err(PostError::Unauthorized(UnauthorizedError {}));
-    }
-
-    #[derive(Debug)]
-    struct Form {
-        name: Option<String>,
-        start_instant: Option<String>,
-        end_instant: Option<String>,
-        polygon_zip: Option<Cursor<Vec<u8>>>,
-    }
+    let identity = require_identity(identity)?;
 
-    let mut form = Form {
-        name: None,
-        start_instant: None,
-        end_instant: None,
-        polygon_zip: None,
-    };
+    let mut contest_problem_id: Option<String> = None;
+    let mut language: Option<String> = None;
+    let mut source_files: Vec<job::SourceFile> = vec![];
 
     while let Ok(Some(mut field)) = payload.try_next().await {
-        let mut cursor = Cursor::new(vec![]);
-        while let Some(chunk) = field.next().await {
-            let data = chunk.unwrap();
-            cursor
-                .write(&data)
-                .map_err(|_| PostError::Validation("Corpo inválido".into()))?;
-        }
-
-        cursor.set_position(0);
-
-        fn parse_field(field: &str, cursor: &mut Cursor<Vec<u8>>) -> Result<String, PostError> {
-            let mut value = String::new();
-            cursor
-                .read_to_string(&mut value)
-                .map_err(|_| PostError::Validation(format!("Campo {} inválido", field)))?;
-            Ok(value)
-        }
+        let mut cursor = read_field(&mut field, SUBMISSION_SIZE_LIMIT).await?;
 
-        match field.content_disposition().unwrap().get_name() {
-            Some("name") => form.name = Some(parse_field("name", &mut cursor)?),
-            Some("start_instant") => {
-                form.start_instant = Some(parse_field("start_instant", &mut cursor)?)
+        let content_disposition = field.content_disposition().unwrap();
+        match content_disposition.get_name() {
+            Some("contest_problem_id") => {
+                contest_problem_id = Some(parse_field("contest_problem_id", &mut cursor)?)
             }
-            Some("end_instant") => {
-                form.end_instant = Some(parse_field("end_instant", &mut cursor)?)
+            Some("language") => language = Some(parse_field("language", &mut cursor)?),
+            Some("files") => {
+                let filename = content_disposition.get_filename().unwrap_or("");
+                if filename.ends_with(".zip") {
+                    let mut zip = zip::ZipArchive::new(cursor)?;
+                    for i in 0..zip.len() {
+                        let mut file = zip.by_index(i)?;
+                        if file.is_dir() {
+                            continue;
+                        }
+                        if file.size() > SUBMISSION_SIZE_LIMIT as u64 {
+                            return Err(PostError::Validation("Arquivos grandes demais".into()));
+                        }
+                        let path = file.name().to_string();
+                        push_source_file(&mut source_files, &path, &mut file)?;
+                    }
+                } else if !filename.is_empty() {
+                    push_source_file(&mut source_files, filename, &mut cursor)?;
+                }
             }
-            Some("polygon_zip") => form.polygon_zip = Some(cursor),
             _ => {}
         }
     }
 
-    let polygon_zip = form
-        .polygon_zip
-        .ok_or(PostError::Validation("Arquivo não informado".into()))?;
-    let imported = import_contest::import_file(polygon_zip)
-        .map_err(|e| {
-            println!("{}", e);
-            PostError::Validation("Não foi possível importar".into())
-        })?;
+    let contest_problem_id: i32 = contest_problem_id
+        .and_then(|id| id.parse().ok())
+        .ok_or(PostError::Validation("Problema não informado".into()))?;
+    let language = language.ok_or(PostError::Validation("Linguagem não informada".into()))?;
+    if source_files.is_empty() {
+        return Err(PostError::Validation("Nenhum arquivo enviado".into()));
+    }
+
+    let multi_file = languages
+        .get(&language)
+        .ok_or(PostError::Validation("Linguagem inexistente".into()))?
+        .multi_file;
+    if !multi_file {
+        return Err(PostError::Validation("Linguagem não suporta múltiplos arquivos".into()));
+    }
+
     let connection = pool.get()?;
+    let metadata = problem::get_problem_by_contest_id_metadata(&connection, contest_problem_id)?;
 
-    let contest = contest::insert_contest(
+    let uuid = Uuid::new_v4();
+    submission::insert_submission(
         &connection,
-        contest::NewContest {
-            name: form.name.unwrap(),
-            start_instant: form.start_instant.and_then(|s| tz.datetime_from_str(&s, "%Y-%m-%d %H:%M:%S").ok()).map(|d| d.naive_utc()),
-            end_instant: form.end_instant.and_then(|s| tz.datetime_from_str(&s, "%Y-%m-%d %H:%M:%S").ok()).map(|d| d.naive_utc()),
-            creation_instant: Local::now().naive_local(),
-            creation_user_id: logged_user.id,
+        submission::NewSubmission {
+            uuid: uuid.to_string(),
+            source_text: "".into(),
+            language: language.clone(),
+            submission_instant: Local::now().naive_utc(),
+            contest_problem_id,
+            user_id: identity.id,
         },
     )?;
+    submission::insert_submission_files(
+        &connection,
+        source_files
+            .iter()
+            .map(|source_file| submission::NewSubmissionFile {
+                submission_uuid: uuid.to_string(),
+                name: source_file.name.clone(),
+                text: source_file.text.clone(),
+            })
+            .collect(),
+    )?;
 
-    fn polygon_url_to_id_without_revision(url: String) -> String {
-        url.replace("https://polygon.codeforces.com/", "polygon.")
-            .replace("/", ".")
-    }
+    broadcaster.lock().unwrap().watch_job(&uuid.to_string(), identity.id);
+    job_sender.send(judgement_job(&uuid, &language, metadata, "".into(), source_files)).await?;
 
-    let problem_label: HashMap<String, String> =
-        HashMap::from_iter(imported.0.problems.problem.iter().map(|problem| {
-            (
-                polygon_url_to_id_without_revision(problem.url.clone()),
-                problem.index.clone(),
-            )
+    session.insert("language", &language)?;
+
+    Ok(redirect_to_referer(format!("Submetido {} com sucesso!", uuid), &request))
+}
+
+fn polygon_url_to_id_without_revision(url: String) -> String {
+    url.replace("https://polygon.codeforces.com/", "polygon.")
+        .replace("/", ".")
+}
+
+/// The id every revision of the package's problem shares, the full id
+/// adding `.r{revision}` to it.
+fn package_id_without_revision(package: &import_contest::ProblemPackage) -> String {
+    match package {
+        import_contest::ProblemPackage::Polygon(metadata) => {
+            polygon_url_to_id_without_revision(metadata.url.clone())
+        }
+        import_contest::ProblemPackage::Kattis(metadata) => format!("kattis.{}", metadata.short_name),
+    }
+}
+
+lazy_static! {
+    static ref CODEFORCES_LANGUAGE_TO_JUGHISTO: HashMap<String, String> = {
+        let mut m = HashMap::new();
+        m.insert("cpp.g++11".into(), "cpp.17.g++".into());
+        m.insert("cpp.g++14".into(), "cpp.17.g++".into());
+        m.insert("cpp.g++17".into(), "cpp.17.g++".into());
+        m.insert("cpp.msys2-mingw64-9-g++17".into(), "cpp.17.g++".into());
+        m.insert("c.gcc".into(), "c.18.gcc".into());
+        m.insert("pas.fpc".into(), "pascal.fpc".into());
+        m.insert("java.8".into(), "java.8".into());
+        m.insert("python.3".into(), "python.3".into());
+        m.insert("testlib".into(), "cpp.17.g++".into());
+        m
+    };
+}
+
+fn map_codeforces_language(input: &String) -> Result<String, PostError> {
+    Ok(CODEFORCES_LANGUAGE_TO_JUGHISTO
+        .get(input)
+        .ok_or_else(|| PostError::Validation(format!("Linguagem {} não suportada", input)))?
+        .into())
+}
+
+/// Kattis packages only tell the language of a source by its extension.
+fn map_kattis_language(path: &str) -> Result<String, PostError> {
+    let language = match PathBuf::from(path).extension().and_then(|extension| extension.to_str()) {
+        Some("c") => "c.18.gcc",
+        Some("cc") | Some("cpp") | Some("cxx") => "cpp.17.g++",
+        Some("java") => "java.8",
+        Some("pas") => "pascal.fpc",
+        Some("py") => "python.3",
+        _ => return Err(PostError::Validation(format!("Linguagem de {} não suportada", path))),
+    };
+    Ok(language.into())
+}
+
+/// Persists how a background package import is going, and pokes the report
+/// page so it reloads.
+struct ImportReporter {
+    pool: DbPool,
+    broadcaster: Data<Mutex<Broadcaster>>,
+    uuid: String,
+}
+
+impl ImportReporter {
+    fn notify(&self) {
+        self.broadcaster
+            .lock()
+            .unwrap()
+            .send(&format!("import_{}", self.uuid), "");
+    }
+
+    fn problem(&self, id: i32, status: &str, problem_id: Option<&str>, message: Option<&str>) {
+        let result = self.pool.get().map_err(PostError::from).and_then(|connection| {
+            Ok(package_import::set_problem_status(&connection, id, status, problem_id, message)?)
+        });
+        if let Err(e) = result {
+            error!("Couldn't store status of import uuid={}: {}", self.uuid, e);
+        }
+        self.notify();
+    }
+
+    fn solution(
+        &self,
+        problem: i32,
+        path: &str,
+        tag: &str,
+        status: &str,
+        verdict: Option<String>,
+        message: Option<String>,
+    ) {
+        let result = self.pool.get().map_err(PostError::from).and_then(|connection| {
+            Ok(package_import::upsert_package_import_solution(
+                &connection,
+                package_import::PackageImportSolution {
+                    package_import_problem_id: problem,
+                    path: path.into(),
+                    tag: tag.into(),
+                    status: status.into(),
+                    verdict,
+                    message,
+                },
+            )?)
+        });
+        if let Err(e) = result {
+            error!("Couldn't store status of import uuid={}: {}", self.uuid, e);
+        }
+        self.notify();
+    }
+
+    fn self_test(
+        &self,
+        problem: i32,
+        program: &str,
+        test: i32,
+        expected: &str,
+        verdict: &str,
+        message: Option<String>,
+    ) {
+        let matched = same_polygon_verdict(expected, verdict);
+        let result = self.pool.get().map_err(PostError::from).and_then(|connection| {
+            Ok(package_import::insert_package_import_self_test(
+                &connection,
+                package_import::PackageImportSelfTest {
+                    package_import_problem_id: problem,
+                    program: program.into(),
+                    test,
+                    expected: expected.into(),
+                    verdict: Some(verdict.into()),
+                    status: if matched { "ok" } else { "mismatch" }.into(),
+                    message: if matched { None } else { message },
+                },
+            )?)
+        });
+        if let Err(e) = result {
+            error!("Couldn't store status of import uuid={}: {}", self.uuid, e);
+        }
+        self.notify();
+    }
+
+    fn test(&self, problem: i32, testset: &str, test: i32, status: &str, message: Option<String>) {
+        let result = self.pool.get().map_err(PostError::from).and_then(|connection| {
+            Ok(package_import::upsert_package_import_test(
+                &connection,
+                package_import::PackageImportTest {
+                    package_import_problem_id: problem,
+                    test,
+                    status: status.into(),
+                    message,
+                    testset: testset.into(),
+                },
+            )?)
+        });
+        if let Err(e) = result {
+            error!("Couldn't store status of import uuid={}: {}", self.uuid, e);
+        }
+        self.notify();
+    }
+}
+
+/// Runs a problem's validator with the test as input, returning why it
+/// rejected the test, if it did. Kattis validators accept with 42 instead of 0.
+async fn validate_test(
+    job_sender: &Sender<Job>,
+    job_results: &JobResultRegistry,
+    package_format: &str,
+    validator_language: &String,
+    validator_path: String,
+    test_path: String,
+) -> Result<Option<String>, PostError> {
+    let run_stats = language::run_cached(
+        job_sender,
+        job_results,
+        validator_language,
+        validator_path,
+        vec![],
+        vec![],
+        Some(test_path),
+        None,
+        vec![],
+        // Validators read big tests, give them room regardless of the problem
+        1_024 * 1_024,
+        10_000,
+    )
+    .await
+    .map_err(|_| PostError::Validation("Couldn't run the validator".into()))?;
+
+    if run_stats.result == i32::from(job_result::run_cached::Result::CompilationError) {
+        return Err(PostError::Validation(format!(
+            "Validator didn't compile: {}",
+            run_stats.error_output
+        )));
+    }
+    let valid = match package_format {
+        "kattis" => run_stats.exit_code == 42,
+        _ => run_stats.result == i32::from(job_result::run_cached::Result::Ok),
+    };
+    if !valid {
+        return Ok(Some(if run_stats.error_output.is_empty() {
+            format!("Validator exited with code {}", run_stats.exit_code)
+        } else {
+            run_stats.error_output
         }));
+    }
+    Ok(None)
+}
 
-    let mut zip = imported.2;
+/// Polygon writes verdicts in several ways depending on where they come from,
+/// `WRONG_ANSWER` and `wrong-answer` being the same.
+fn same_polygon_verdict(expected: &str, verdict: &str) -> bool {
+    fn normalize(verdict: &str) -> String {
+        match verdict.to_lowercase().replace('_', "-").as_str() {
+            "crashed" | "failed" => "fail".into(),
+            verdict => verdict.into(),
+        }
+    }
+    normalize(expected) == normalize(verdict)
+}
 
-    lazy_static! {
-        static ref CODEFORCES_LANGUAGE_TO_JUGHISTO: HashMap<String, String> = {
-            let mut m = HashMap::new();
-            m.insert("cpp.g++17".into(), "cpp.17.g++".into());
-            m.insert("java.8".into(), "java.8".into());
-            m.insert("testlib".into(), "cpp.17.g++".into());
-            m
+/// The verdict a testlib checker gave by its exit code.
+fn checker_verdict(exit_code: i32) -> &'static str {
+    match exit_code {
+        0 => "ok",
+        1 => "wrong-answer",
+        // Dirty output and unexpected end of file are presentation errors
+        2 | 4 | 8 => "presentation-error",
+        7 => "points",
+        _ => "fail",
+    }
+}
+
+/// Runs the checker and the validator on the tests the package has for
+/// them, returning how many didn't get the verdict Polygon expects.
+async fn run_self_tests(
+    job_sender: &Sender<Job>,
+    job_results: &JobResultRegistry,
+    metadata: &import_contest::Problem,
+    problem: &problem::NewProblem,
+    data_dir: &str,
+    reporter: &ImportReporter,
+    import_problem_id: i32,
+) -> Result<usize, PostError> {
+    let mut mismatches = 0;
+
+    let checker_testset = &metadata.assets.checker.testset;
+    for (i, test) in checker_testset.tests.test.iter().flatten().enumerate() {
+        let i = i + 1;
+        let input_path = import_contest::format_width(&checker_testset.input_path_pattern.value, i);
+        let output_path = match &checker_testset.output_path_pattern {
+            Some(pattern) => import_contest::format_width(&pattern.value, i),
+            None => format!("{}.o", input_path),
         };
+        let answer_path =
+            import_contest::format_width(&checker_testset.answer_path_pattern.value, i);
+
+        let run_stats = language::run_cached(
+            job_sender,
+            job_results,
+            &problem.checker_language,
+            format!("./{}/{}", data_dir, problem.checker_path),
+            vec![],
+            vec![input_path, output_path, answer_path]
+                .into_iter()
+                .map(|path| format!("./{}/{}", data_dir, path))
+                .collect(),
+            None,
+            None,
+            vec![],
+            1_024 * 1_024,
+            10_000,
+        )
+        .await
+        .map_err(|_| PostError::Validation("Couldn't run the checker".into()))?;
+        if run_stats.result == i32::from(job_result::run_cached::Result::CompilationError) {
+            return Err(PostError::Validation(format!(
+                "Checker didn't compile: {}",
+                run_stats.error_output
+            )));
+        }
+
+        let verdict = checker_verdict(run_stats.exit_code);
+        if !same_polygon_verdict(&test.verdict, verdict) {
+            mismatches += 1;
+        }
+        reporter.self_test(
+            import_problem_id,
+            "checker",
+            i as i32,
+            &test.verdict,
+            verdict,
+            Some(run_stats.error_output),
+        );
     }
 
-    for (name, metadata) in imported.1 {
-        let problem_id_without_revision = polygon_url_to_id_without_revision(metadata.url);
-        let problem_id = format!("{}.r{}", problem_id_without_revision, &metadata.revision);
-
-        let files_regex: Regex = Regex::new(&format!(
-            concat!(
-                "^{}/(",
-                r"files/$|",
-                r"files/.*\.cpp$|",
-                r"files/.*\.h$|",
-                r"files/tests/$|",
-                r"files/tests/validator-tests/$|",
-                r"files/tests/validator-tests/.*$|",
-                r"files/tests/validator-tests/.*$|",
-                r"solutions/$|",
-                r"solutions/.*.cc$|",
-                r"solutions/.*.cpp$|",
-                r"statements/$|",
-                r"statements/.html/.*$|",
-                r"tests/$",
-                ")"
-            ),
-            name
-        ))
-        .unwrap();
-        let mut filenames = zip
-            .file_names()
-            .filter(|name| files_regex.is_match(name))
-            .map(|s| s.to_string())
-            .collect::<Vec<_>>();
-        filenames.sort();
-        for name in filenames {
-            let relative_path = files_regex
-                .captures(&name)
-                .unwrap()
-                .get(1)
-                .unwrap()
-                .as_str();
-            let data_path = format!("/data/{}/{}", problem_id, relative_path);
-
-            if name.ends_with("/") {
-                info!("Creating directory {} into {}", name, data_path);
-                create_dir_all(data_path)?;
-                continue;
-            }
+    let validator = metadata
+        .assets
+        .validators
+        .validator
+        .first()
+        .ok_or(PostError::Validation("No validator".into()))?;
+    for (i, test) in validator.testset.tests.test.iter().flatten().enumerate() {
+        let i = i + 1;
+        let input_path =
+            import_contest::format_width(&validator.testset.input_path_pattern.value, i);
+        let message = validate_test(
+            job_sender,
+            job_results,
+            &problem.package_format,
+            &problem.validator_language,
+            format!("./{}/{}", data_dir, problem.validator_path),
+            format!("./{}/{}", data_dir, input_path),
+        )
+        .await?;
+
+        let verdict = if message.is_some() { "invalid" } else { "valid" };
+        if !same_polygon_verdict(&test.verdict, verdict) {
+            mismatches += 1;
+        }
+        reporter.self_test(
+            import_problem_id,
+            "validator",
+            i as i32,
+            &test.verdict,
+            verdict,
+            // A valid verdict where invalid was expected has nothing to show
+            message.or(Some("Accepted by the validator".into())),
+        );
+    }
+
+    Ok(mismatches)
+}
+
+/// A problem staged by an import, stored only when the whole import is committed.
+struct ImportedProblem {
+    problem: problem::NewProblem,
+    tests: Vec<problem::ProblemTest>,
+    testsets: Vec<problem::ProblemTestset>,
+    statements: Vec<problem::ProblemStatement>,
+}
 
-            info!("Putting file {} into {}", name, data_path);
-            std::io::copy(&mut zip.by_name(&name)?, &mut File::create(data_path)?)?;
+/// Stages the problem package found under `prefix` in the zip, in whichever
+/// format it is.
+async fn import_problem<R: Read + Seek>(
+    job_sender: &Sender<Job>,
+    job_results: &JobResultRegistry,
+    zip: &mut ZipArchive<R>,
+    prefix: &str,
+    staging: &str,
+    package: import_contest::ProblemPackage,
+    user_id: i32,
+    reporter: &ImportReporter,
+    import_problem_id: i32,
+) -> Result<ImportedProblem, PostError> {
+    match package {
+        import_contest::ProblemPackage::Polygon(metadata) => {
+            import_polygon_problem(
+                job_sender, job_results, zip, prefix, staging, *metadata, user_id, reporter, import_problem_id,
+            )
+            .await
+        }
+        import_contest::ProblemPackage::Kattis(metadata) => {
+            import_kattis_problem(
+                job_sender, job_results, zip, prefix, staging, *metadata, user_id, reporter, import_problem_id,
+            )
+            .await
         }
+    }
+}
 
-        fn map_codeforces_language(input: &String) -> Result<String, PostError> {
-            Ok(CODEFORCES_LANGUAGE_TO_JUGHISTO
-                .get(input)
-                .ok_or_else(|| PostError::Validation(format!("Linguagem {} não suportada", input)))?
-                .into())
+/// Copies a Polygon problem found under `prefix` in the zip into
+/// /data/{staging}/{problem_id}, generates its answers, validates its tests
+/// and checks the main solution against them. Nothing is stored until the
+/// import is committed.
+async fn import_polygon_problem<R: Read + Seek>(
+    job_sender: &Sender<Job>,
+    job_results: &JobResultRegistry,
+    zip: &mut ZipArchive<R>,
+    prefix: &str,
+    staging: &str,
+    metadata: import_contest::Problem,
+    user_id: i32,
+    reporter: &ImportReporter,
+    import_problem_id: i32,
+) -> Result<ImportedProblem, PostError> {
+    let problem_id = format!(
+        "{}.r{}",
+        polygon_url_to_id_without_revision(metadata.url.clone()),
+        &metadata.revision
+    );
+    let data_dir = format!("{}/{}", staging, problem_id);
+
+    let files_regex: Regex = Regex::new(&format!(
+        concat!(
+            "^{}(",
+            r"files/$|",
+            r"files/.*\.cpp$|",
+            r"files/.*\.h$|",
+            r"files/.*\.c$|",
+            r"files/.*\.pas$|",
+            r"files/.*\.java$|",
+            r"files/.*\.py$|",
+            r"files/tests/$|",
+            r"files/tests/checker-tests/$|",
+            r"files/tests/checker-tests/.*$|",
+            r"files/tests/validator-tests/$|",
+            r"files/tests/validator-tests/.*$|",
+            r"solutions/$|",
+            r"solutions/.*.cc$|",
+            r"solutions/.*.cpp$|",
+            r"solutions/.*\.c$|",
+            r"solutions/.*\.pas$|",
+            r"solutions/.*\.java$|",
+            r"solutions/.*\.py$|",
+            r"statements/$|",
+            r"statements/.html/.*$|",
+            r"statements/.pdf/.*$|",
+            r"statement-sections/$|",
+            r"statement-sections/.*$|",
+            r"tests/$",
+            ")"
+        ),
+        regex::escape(prefix)
+    ))
+    .unwrap();
+    let mut filenames = zip
+        .file_names()
+        .filter(|name| files_regex.is_match(name))
+        .map(|s| s.to_string())
+        .collect::<Vec<_>>();
+    filenames.sort();
+    for name in filenames {
+        let relative_path = files_regex
+            .captures(&name)
+            .unwrap()
+            .get(1)
+            .unwrap()
+            .as_str();
+        let data_path = format!("/data/{}/{}", data_dir, relative_path);
+
+        if name.ends_with("/") {
+            info!("Creating directory {} into {}", name, data_path);
+            create_dir_all(data_path)?;
+            continue;
         }
 
-        let problem = problem::upsert_problem(
-            &connection,
-            problem::NewProblem {
-                id: problem_id.clone(),
-                name: metadata.names.name[0].value.clone(),
-                memory_limit_bytes: metadata.judging.testset[0]
-                    .memory_limit
-                    .value
-                    .parse()
-                    .unwrap(),
-                time_limit_ms: metadata.judging.testset[0]
-                    .time_limit
-                    .value
-                    .parse()
-                    .unwrap(),
-                checker_path: metadata.assets.checker.source.path.clone(),
-                checker_language: map_codeforces_language(&metadata.assets.checker.r#type)?,
-                validator_path: metadata.assets.validators.validator[0].source.path.clone(),
-                validator_language: map_codeforces_language(
-                    &metadata.assets.validators.validator[0].source.r#type,
-                )?,
-                main_solution_path: metadata
-                    .assets
-                    .solutions
-                    .solution
-                    .iter()
-                    .find(|s| s.tag == "main")
-                    .ok_or(PostError::Validation("No main solution".into()))?
-                    .source
-                    .path.clone(),
-                main_solution_language: map_codeforces_language(
-                    &metadata
-                        .assets
-                        .solutions
-                        .solution
-                        .iter()
-                        .find(|s| s.tag == "main")
-                        .ok_or(PostError::Validation("No main solution".into()))?
-                        .source
-                        .r#type,
-                )?,
-                test_pattern: metadata.judging.testset[0].input_path_pattern.value.clone(),
-                test_count: metadata.judging.testset[0]
-                    .test_count
-                    .value
-                    .parse()
-                    .unwrap(),
-                status: "compiled".into(),
-                creation_instant: Local::now().naive_local(),
-                creation_user_id: logged_user.id,
-            },
-        )?;
+        info!("Putting file {} into {}", name, data_path);
+        std::io::copy(&mut zip.by_name(&name)?, &mut File::create(data_path)?)?;
+    }
 
-        for (i, test) in metadata.judging.testset[0].tests.test.iter().enumerate() {
-            let i = i + 1;
-            let test_path = format!(
+    let testsets: Vec<_> = metadata
+        .judging
+        .testset
+        .iter()
+        .map(|testset| {
+            let invalid = |field: &str| {
+                PostError::Validation(format!("Invalid {} in testset {}", field, testset.name))
+            };
+            Ok(problem::ProblemTestset {
+                problem_id: problem_id.clone(),
+                name: testset.name.clone(),
+                time_limit_ms: testset.time_limit.value.parse().map_err(|_| invalid("time limit"))?,
+                memory_limit_bytes: testset.memory_limit.value.parse().map_err(|_| invalid("memory limit"))?,
+                test_count: testset.test_count.value.parse().map_err(|_| invalid("test count"))?,
+                test_pattern: testset.input_path_pattern.value.clone(),
+            })
+        })
+        .collect::<Result<_, PostError>>()?;
+    // Polygon judges on `tests`, the others being pretests and the like
+    let main_testset = testsets
+        .iter()
+        .find(|testset| testset.name == "tests")
+        .or(testsets.first())
+        .ok_or(PostError::Validation("No testsets".into()))?
+        .clone();
+
+    // The TeX sources aren't served, and a statement may be listed without being packaged
+    let mut statements: Vec<_> = metadata
+        .statements
+        .statement
+        .iter()
+        .filter(|statement| statement.r#type == "text/html" || statement.r#type == "application/pdf")
+        .filter(|statement| PathBuf::from(format!("/data/{}/{}", data_dir, statement.path)).is_file())
+        .map(|statement| problem::ProblemStatement {
+            problem_id: problem_id.clone(),
+            language: statement.language.clone(),
+            mime_type: statement.r#type.clone(),
+            path: statement.path.clone(),
+            charset: statement.charset.clone(),
+            mathjax: statement.mathjax.unwrap_or(false),
+        })
+        .collect();
+    // The sections of each language, rendered natively instead of Polygon's HTML
+    for statement in &metadata.statements.statement {
+        let path = format!("statement-sections/{}/problem-properties.json", statement.language);
+        if statements.iter().any(|s| s.language == statement.language && s.mime_type == "application/json")
+            || !PathBuf::from(format!("/data/{}/{}", data_dir, path)).is_file()
+        {
+            continue;
+        }
+        statements.push(problem::ProblemStatement {
+            problem_id: problem_id.clone(),
+            language: statement.language.clone(),
+            mime_type: "application/json".into(),
+            path,
+            charset: Some("UTF-8".into()),
+            mathjax: metadata
+                .statements
+                .statement
+                .iter()
+                .any(|s| s.language == statement.language && s.mathjax == Some(true)),
+        });
+    }
+
+    let validator = metadata
+        .assets
+        .validators
+        .validator
+        .first()
+        .ok_or(PostError::Validation("No validator".into()))?;
+    let problem = problem::NewProblem {
+        id: problem_id.clone(),
+        name: metadata
+            .names
+            .name
+            .first()
+            .ok_or(PostError::Validation("No name".into()))?
+            .value
+            .clone(),
+        memory_limit_bytes: main_testset.memory_limit_bytes,
+        time_limit_ms: main_testset.time_limit_ms,
+        checker_path: metadata.assets.checker.source.path.clone(),
+        checker_language: map_codeforces_language(&metadata.assets.checker.r#type)?,
+        validator_path: validator.source.path.clone(),
+        validator_language: map_codeforces_language(&validator.source.r#type)?,
+        main_solution_path: metadata
+            .assets
+            .solutions
+            .solution
+            .iter()
+            .find(|s| s.tag == "main")
+            .ok_or(PostError::Validation("No main solution".into()))?
+            .source
+            .path.clone(),
+        main_solution_language: map_codeforces_language(
+            &metadata
+                .assets
+                .solutions
+                .solution
+                .iter()
+                .find(|s| s.tag == "main")
+                .ok_or(PostError::Validation("No main solution".into()))?
+                .source
+                .r#type,
+        )?,
+        test_pattern: main_testset.test_pattern.clone(),
+        test_count: main_testset.test_count,
+        status: "ready".into(),
+        creation_instant: Local::now().naive_local(),
+        creation_user_id: user_id,
+        grader_paths: metadata
+            .files
+            .resources
+            .file
+            .iter()
+            .filter(|file| file.is_grader())
+            .map(|file| file.path.clone())
+            .collect(),
+        package_format: "polygon".into(),
+        checker_arguments: vec![],
+    };
+
+    // A broken checker or validator makes everything after it meaningless
+    let self_test_mismatches = run_self_tests(
+        job_sender,
+        job_results,
+        &metadata,
+        &problem,
+        &data_dir,
+        reporter,
+        import_problem_id,
+    )
+    .await?;
+    if self_test_mismatches > 0 {
+        return Err(PostError::Validation(format!(
+            "{} checker or validator test(s) got an unexpected verdict",
+            self_test_mismatches
+        )));
+    }
+
+    let validator_path = format!("./{}/{}", data_dir, problem.validator_path);
+    let mut problem_tests = Vec::new();
+    let mut invalid_tests = 0;
+    for (testset, xml_testset) in testsets.iter().zip(&metadata.judging.testset) {
+        let test_sources = generator_script::plan_tests(
+            &xml_testset.tests.test,
+            xml_testset.script.as_ref().map(|script| script.value.as_str()),
+        )
+        .map_err(|e| PostError::Validation(e.to_string()))?;
+        let test_path = |i: usize| {
+            format!(
                 "./{}/{}",
-                problem_id,
-                import_contest::format_width(&problem.test_pattern, i)
-            );
+                data_dir,
+                import_contest::format_width(&testset.test_pattern, i)
+            )
+        };
+        // Only the directory of `tests` comes in the package
+        if let Some(parent) = PathBuf::from("/data/").join(test_path(1)).parent() {
+            create_dir_all(parent)?;
+        }
+
+        // Multi-test generators run once, writing every test that names their command
+        let mut pending_output_files: HashMap<Vec<String>, Vec<job::OutputFile>> = HashMap::new();
+        for (i, source) in test_sources.iter().enumerate() {
+            if let TestSource::Generated {
+                command,
+                output: Some(output),
+            } = source
+            {
+                pending_output_files
+                    .entry(command.clone())
+                    .or_default()
+                    .push(job::OutputFile {
+                        name: output.clone(),
+                        path: test_path(i + 1),
+                    });
+            }
+        }
+
+        for (i, source) in test_sources.iter().enumerate() {
+            let i = i + 1;
+            let test_path = test_path(i);
+            reporter.test(import_problem_id, &testset.name, i as i32, "running", None);
+
+            // Ok(Some(message)) when the validator rejects the test
+            let result: Result<Option<String>, PostError> = async {
+                info!("Iterating through test {} to {:#?}, which is {:?}", i, test_path, source);
+                let (command, stdout_path, output_files) = match source {
+                    TestSource::Manual => {
+                        let test_name = PathBuf::from(prefix)
+                            .join(import_contest::format_width(&testset.test_pattern, i));
+                        info!("Extracting {:#?} from zip", test_name);
+                        std::io::copy(
+                            &mut zip.by_name(&test_name.to_str().unwrap())?,
+                            &mut File::create(PathBuf::from("/data/").join(&test_path))?,
+                        )?;
+                        (None, None, vec![])
+                    }
+                    TestSource::Generated {
+                        command,
+                        output: None,
+                    } => (Some(command), Some(test_path.clone()), vec![]),
+                    TestSource::Generated {
+                        command,
+                        output: Some(_),
+                    } => match pending_output_files.remove(command) {
+                        Some(output_files) => (Some(command), None, output_files),
+                        // Already written along with an earlier test
+                        None => (None, None, vec![]),
+                    },
+                };
+
+                if let Some(command) = command {
+                    let (source_path, source_type) =
+                        generator_script::generator_source(&metadata, &command[0]);
+                    let run_stats = language::run_cached(
+                        job_sender,
+                        job_results,
+                        &map_codeforces_language(&source_type)?,
+                        format!("./{}/{}", data_dir, source_path),
+                        command[1..].to_vec(),
+                        vec![],
+                        None,
+                        stdout_path,
+                        output_files,
+                        testset.memory_limit_bytes / 1_024,
+                        testset.time_limit_ms,
+                    )
+                    .await
+                    .map_err(|_| {
+                        PostError::Validation("Couldn't use an intermediate program".into())
+                    })?;
+
+                    if run_stats.result != i32::from(job_result::run_cached::Result::Ok) {
+                        return Err(PostError::Validation(format!(
+                            "Couldn't run generator {}: {}",
+                            command.join(" "),
+                            run_stats.error_output
+                        )));
+                    }
+                }
+
+                let validation_message = validate_test(
+                    job_sender,
+                    job_results,
+                    &problem.package_format,
+                    &problem.validator_language,
+                    validator_path.clone(),
+                    test_path.clone(),
+                )
+                .await?;
+                problem_tests.push(problem::ProblemTest {
+                    problem_id: problem_id.clone(),
+                    test: i as i32,
+                    testset: testset.name.clone(),
+                    validation_status: if validation_message.is_some() { "invalid" } else { "valid" }.into(),
+                    validation_message: validation_message.clone(),
+                    validation_instant: Local::now().naive_utc(),
+                    sample: xml_testset.tests.test[i - 1].sample.unwrap_or(false),
+                });
+                if validation_message.is_some() {
+                    return Ok(validation_message);
+                }
 
-            info!(
-                "Iterating through test {} to {:#?}, which is {}",
-                i,
-                test_path,
-                test.method.as_ref().unwrap()
-            );
-            if test.method.as_ref().unwrap() == "manual" {
-                let test_name = PathBuf::from(&name)
-                    .join(import_contest::format_width(&problem.test_pattern, i));
-                info!("Extracting {:#?} from zip", test_name);
-                std::io::copy(
-                    &mut zip.by_name(&test_name.to_str().unwrap())?,
-                    &mut File::create(PathBuf::from("/data/").join(&test_path))?,
-                )?;
-            } else {
-                let cmd: Vec<_> = test.cmd.as_ref().unwrap().split(" ").collect();
                 let run_stats = language::run_cached(
-                    &job_sender,
-                    &job_result_sender,
-                    &"cpp.17.g++".into(),
-                    format!("./{}/files/{}.cpp", problem.id, cmd.get(0).unwrap()),
-                    cmd[1..].iter().map(|s| s.clone().into()).collect(),
-                    None,
+                    job_sender,
+                    job_results,
+                    &problem.main_solution_language,
+                    format!("./{}/{}", data_dir, problem.main_solution_path),
+                    vec![],
+                    vec![],
                     Some(test_path.clone()),
-                    problem.memory_limit_bytes / 1_024,
-                    problem.time_limit_ms,
+                    Some(format!("{}.a", test_path)),
+                    vec![],
+                    testset.memory_limit_bytes / 1_024,
+                    testset.time_limit_ms,
                 )
                 .await
-                .map_err(|_| {
-                    PostError::Validation("Couldn't use an intermediate program".into())
-                })?;
-
-                if run_stats.result != i32::from(job_result::run_cached::Result::Ok) {
-                    return Err(PostError::Validation("Couldn't run an intermediate program".into()));
+                .map_err(|_| PostError::Validation("Couldn't run solution on test".into()))?;
+                if run_stats.exit_code != 0 {
+                    return Err(PostError::Validation(format!(
+                        "Couldn't run solution on test, exit code {}: {}",
+                        run_stats.exit_code, run_stats.error_output
+                    )));
+                }
+                Ok(None)
+            }.await;
+            match &result {
+                Ok(None) => reporter.test(import_problem_id, &testset.name, i as i32, "ok", None),
+                Ok(Some(message)) => {
+                    invalid_tests += 1;
+                    reporter.test(import_problem_id, &testset.name, i as i32, "invalid", Some(message.clone()));
                 }
+                Err(e) => reporter.test(import_problem_id, &testset.name, i as i32, "failed", Some(e.to_string())),
             }
+            result?;
+        }
+    }
 
-            let run_stats = language::run_cached(
-                &job_sender,
-                &job_result_sender,
-                &problem.main_solution_language,
-                format!("./{}/{}", problem.id, problem.main_solution_path),
-                vec![],
-                Some(test_path.clone()),
-                Some(format!("{}.a", test_path)),
-                problem.memory_limit_bytes / 1_024,
-                problem.time_limit_ms,
+    // Every test is validated before giving up, so the report lists them all
+    if invalid_tests > 0 {
+        return Err(PostError::Validation(format!(
+            "{} test(s) rejected by the validator",
+            invalid_tests
+        )));
+    }
+
+    let solutions = metadata
+        .assets
+        .solutions
+        .solution
+        .iter()
+        .filter(|solution| solution.tag != "main")
+        .map(|solution| PackageSolution {
+            path: solution.source.path.clone(),
+            tag: solution.tag.clone(),
+            language: map_codeforces_language(&solution.source.r#type),
+        })
+        .collect();
+    judge_package_solutions(
+        job_sender,
+        job_results,
+        &problem,
+        &testsets,
+        &main_testset,
+        &data_dir,
+        solutions,
+        reporter,
+        import_problem_id,
+    )
+    .await?;
+
+    Ok(ImportedProblem {
+        problem,
+        tests: problem_tests,
+        testsets,
+        statements,
+    })
+}
+
+/// Packages that leave the time limit for the judge to pick get this one.
+const KATTIS_DEFAULT_TIME_LIMIT_MS: i32 = 1_000;
+const KATTIS_DEFAULT_MEMORY_LIMIT_MIB: i32 = 1_024;
+
+/// Copies a Kattis problem found under `prefix` in the zip into
+/// /data/{staging}/{problem_id}, with its tests numbered in a single `tests`
+/// testset, validates them and judges the submissions as for Polygon.
+async fn import_kattis_problem<R: Read + Seek>(
+    job_sender: &Sender<Job>,
+    job_results: &JobResultRegistry,
+    zip: &mut ZipArchive<R>,
+    prefix: &str,
+    staging: &str,
+    metadata: import_contest::kattis::Problem,
+    user_id: i32,
+    reporter: &ImportReporter,
+    import_problem_id: i32,
+) -> Result<ImportedProblem, PostError> {
+    let problem_id = format!("kattis.{}.r{}", metadata.short_name, metadata.revision);
+    let data_dir = format!("{}/{}", staging, problem_id);
+
+    // The tests are copied one by one, renamed, further down
+    let files_regex: Regex = Regex::new(&format!(
+        concat!(
+            "^{}(",
+            r"problem_statement/.*|",
+            r"statement/.*|",
+            r"input_validators/.*|",
+            r"output_validators/.*|",
+            r"submissions/.*",
+            ")$"
+        ),
+        regex::escape(prefix)
+    ))
+    .unwrap();
+    let mut filenames = zip
+        .file_names()
+        .filter(|name| files_regex.is_match(name) && !name.ends_with('/'))
+        .map(|s| s.to_string())
+        .collect::<Vec<_>>();
+    filenames.sort();
+    for name in filenames {
+        let relative_path = files_regex.captures(&name).unwrap().get(1).unwrap().as_str();
+        let data_path = PathBuf::from(format!("/data/{}/{}", data_dir, relative_path));
+        // Zips made by hand often have no entries for directories
+        if let Some(parent) = data_path.parent() {
+            create_dir_all(parent)?;
+        }
+        info!("Putting file {} into {:?}", name, data_path);
+        std::io::copy(&mut zip.by_name(&name)?, &mut File::create(data_path)?)?;
+    }
+
+    let (checker_path, checker_language) =
+        match metadata.metadata.validation.as_deref().unwrap_or("default") {
+            "default" => {
+                let path = "output_validators/default/default_output_validator.cpp";
+                let data_path = PathBuf::from(format!("/data/{}/{}", data_dir, path));
+                create_dir_all(data_path.parent().unwrap())?;
+                fs::write(data_path, import_contest::kattis::DEFAULT_OUTPUT_VALIDATOR)?;
+                (path.to_string(), "cpp.17.g++".to_string())
+            }
+            "custom" => {
+                let path = metadata
+                    .output_validator
+                    .clone()
+                    .ok_or(PostError::Validation("No output validator".into()))?;
+                let language = map_kattis_language(&path)?;
+                (path, language)
+            }
+            validation => {
+                return Err(PostError::Validation(format!("Unsupported validation: {}", validation)))
+            }
+        };
+    // Input validators are optional, without one the tests are taken as they are
+    let validator_path = metadata.input_validator.clone();
+    let main_solution = metadata
+        .solutions
+        .iter()
+        .find(|solution| solution.tag == "accepted")
+        .ok_or(PostError::Validation("No accepted submission".into()))?
+        .clone();
+    if metadata.tests.is_empty() {
+        return Err(PostError::Validation("No tests".into()));
+    }
+
+    let testset = problem::ProblemTestset {
+        problem_id: problem_id.clone(),
+        name: "tests".into(),
+        time_limit_ms: metadata
+            .time_limit_seconds
+            .map(|seconds| (seconds * 1_000.0).round() as i32)
+            .unwrap_or(KATTIS_DEFAULT_TIME_LIMIT_MS),
+        // Kept below 2 GiB, as it's stored in bytes
+        memory_limit_bytes: metadata
+            .metadata
+            .limits
+            .memory
+            .unwrap_or(KATTIS_DEFAULT_MEMORY_LIMIT_MIB)
+            .min(2_047)
+            * 1_024
+            * 1_024,
+        test_count: metadata.tests.len() as i32,
+        test_pattern: "tests/%03d".into(),
+    };
+    create_dir_all(format!("/data/{}/tests", data_dir))?;
+
+    // The TeX statements are split in sections, shown as Polygon's are
+    let mut statements = Vec::new();
+    for statement in &metadata.statements {
+        let language = statement_language_by_code(&statement.language);
+        let mime_type = match statement.mime_type.as_str() {
+            "text/x-tex" => "application/json",
+            mime_type => mime_type,
+        };
+        // `problem.tex` and `problem.en.tex` are both in English
+        if statements
+            .iter()
+            .any(|s: &problem::ProblemStatement| s.language == language && s.mime_type == mime_type)
+        {
+            continue;
+        }
+        if statement.mime_type == "application/pdf" {
+            statements.push(problem::ProblemStatement {
+                problem_id: problem_id.clone(),
+                language,
+                mime_type: statement.mime_type.clone(),
+                path: statement.path.clone(),
+                charset: None,
+                mathjax: false,
+            });
+            continue;
+        }
+        let tex = fs::read_to_string(format!("/data/{}/{}", data_dir, statement.path))?;
+        let path = PathBuf::from(&statement.path)
+            .with_file_name(format!("problem-properties.{}.json", statement.language))
+            .to_str()
+            .unwrap()
+            .to_string();
+        fs::write(
+            format!("/data/{}/{}", data_dir, path),
+            serde_json::to_string(&import_contest::kattis::statement_properties(&tex))
+                .map_err(|e| PostError::Validation(e.to_string()))?,
+        )?;
+        statements.push(problem::ProblemStatement {
+            problem_id: problem_id.clone(),
+            language,
+            mime_type: "application/json".into(),
+            path,
+            charset: Some("UTF-8".into()),
+            mathjax: true,
+        });
+    }
+
+    let problem = problem::NewProblem {
+        id: problem_id.clone(),
+        name: metadata.name.clone(),
+        memory_limit_bytes: testset.memory_limit_bytes,
+        time_limit_ms: testset.time_limit_ms,
+        checker_path,
+        checker_language,
+        validator_language: validator_path
+            .as_deref()
+            .map(map_kattis_language)
+            .transpose()?
+            .unwrap_or_default(),
+        validator_path: validator_path.unwrap_or_default(),
+        main_solution_language: map_kattis_language(&main_solution.path)?,
+        main_solution_path: main_solution.path.clone(),
+        test_pattern: testset.test_pattern.clone(),
+        test_count: testset.test_count,
+        status: "ready".into(),
+        creation_instant: Local::now().naive_local(),
+        creation_user_id: user_id,
+        grader_paths: vec![],
+        package_format: "kattis".into(),
+        checker_arguments: metadata
+            .metadata
+            .validator_flags
+            .as_deref()
+            .unwrap_or("")
+            .split_whitespace()
+            .map(String::from)
+            .collect(),
+    };
+
+    let mut problem_tests = Vec::new();
+    let mut invalid_tests = 0;
+    for (i, test) in metadata.tests.iter().enumerate() {
+        let i = i + 1;
+        let test_path = format!(
+            "./{}/{}",
+            data_dir,
+            import_contest::format_width(&testset.test_pattern, i)
+        );
+        reporter.test(import_problem_id, &testset.name, i as i32, "running", None);
+
+        // Ok(Some(message)) when the validator rejects the test
+        let result: Result<Option<String>, PostError> = async {
+            info!("Extracting {} into {}", test.input_path, test_path);
+            std::io::copy(
+                &mut zip.by_name(&format!("{}{}", prefix, test.input_path))?,
+                &mut File::create(PathBuf::from("/data/").join(&test_path))?,
+            )?;
+            std::io::copy(
+                &mut zip.by_name(&format!("{}{}", prefix, test.answer_path))?,
+                &mut File::create(PathBuf::from("/data/").join(format!("{}.a", test_path)))?,
+            )?;
+
+            if problem.validator_path.is_empty() {
+                problem_tests.push(problem::ProblemTest {
+                    problem_id: problem_id.clone(),
+                    test: i as i32,
+                    testset: testset.name.clone(),
+                    validation_status: "unchecked".into(),
+                    validation_message: None,
+                    validation_instant: Local::now().naive_utc(),
+                    sample: test.sample,
+                });
+                return Ok(None);
+            }
+
+            let validation_message = validate_test(
+                job_sender,
+                job_results,
+                &problem.package_format,
+                &problem.validator_language,
+                format!("./{}/{}", data_dir, problem.validator_path),
+                test_path.clone(),
             )
-            .await
-            .map_err(|_| PostError::Validation("Couldn't run solution on test".into()))?;
-            if run_stats.exit_code != 0 {
-                return Err(PostError::Validation("Couldn't run solution on test".into()));
+            .await?;
+            problem_tests.push(problem::ProblemTest {
+                problem_id: problem_id.clone(),
+                test: i as i32,
+                testset: testset.name.clone(),
+                validation_status: if validation_message.is_some() { "invalid" } else { "valid" }.into(),
+                validation_message: validation_message.clone(),
+                validation_instant: Local::now().naive_utc(),
+                sample: test.sample,
+            });
+            Ok(validation_message)
+        }
+        .await;
+        match &result {
+            Ok(None) if problem.validator_path.is_empty() => {
+                reporter.test(import_problem_id, &testset.name, i as i32, "unchecked", None)
             }
+            Ok(None) => reporter.test(import_problem_id, &testset.name, i as i32, "ok", None),
+            Ok(Some(message)) => {
+                invalid_tests += 1;
+                reporter.test(import_problem_id, &testset.name, i as i32, "invalid", Some(message.clone()));
+            }
+            Err(e) => reporter.test(import_problem_id, &testset.name, i as i32, "failed", Some(e.to_string())),
         }
+        result?;
+    }
 
-        language::judge(
-            &job_sender,
-            &job_result_sender,
+    if invalid_tests > 0 {
+        return Err(PostError::Validation(format!(
+            "{} test(s) rejected by the validator",
+            invalid_tests
+        )));
+    }
+
+    let solutions = metadata
+        .solutions
+        .iter()
+        .filter(|solution| solution.path != main_solution.path)
+        .map(|solution| PackageSolution {
+            path: solution.path.clone(),
+            tag: solution.tag.clone(),
+            language: map_kattis_language(&solution.path),
+        })
+        .collect();
+    let testsets = vec![testset];
+    judge_package_solutions(
+        job_sender,
+        job_results,
+        &problem,
+        &testsets,
+        &testsets[0],
+        &data_dir,
+        solutions,
+        reporter,
+        import_problem_id,
+    )
+    .await?;
+
+    Ok(ImportedProblem {
+        problem,
+        tests: problem_tests,
+        testsets,
+        statements,
+    })
+}
+
+/// A packaged solution other than the main one, judged on import to see if
+/// it gets what its tag promises.
+struct PackageSolution {
+    path: String,
+    tag: String,
+    language: Result<String, PostError>,
+}
+
+/// Judges the main solution on every testset, failing the import unless it
+/// passes them all, and the other solutions on the main testset.
+async fn judge_package_solutions(
+    job_sender: &Sender<Job>,
+    job_results: &JobResultRegistry,
+    problem: &problem::NewProblem,
+    testsets: &[problem::ProblemTestset],
+    main_testset: &problem::ProblemTestset,
+    data_dir: &str,
+    solutions: Vec<PackageSolution>,
+    reporter: &ImportReporter,
+    import_problem_id: i32,
+) -> Result<(), PostError> {
+    // The main solution has to pass every testset, each with its own limits
+    for testset in testsets {
+        judge_package_solution(
+            job_sender,
+            job_results,
+            problem,
+            testset,
+            data_dir,
             &problem.main_solution_language,
-            fs::read_to_string(
-                PathBuf::from(format!("/data/{}/{}", problem.id, problem.main_solution_path))
-            )?,
-            problem.test_count,
-            format!("./{}/{}", problem.id, problem.test_pattern).into(),
-            problem.checker_language,
-            format!("./{}/{}", problem.id, problem.checker_path).into(),
-            problem.memory_limit_bytes / 1_024,
-            problem.time_limit_ms,
+            &problem.main_solution_path,
         )
         .await
-        .map_err(|_| PostError::Validation("Couldn't judge main solution".into()))?;
+        .and_then(|judgement| match (judgement.verdict(), judgement.compilation) {
+            (job_result::judgement::Verdict::CompilationError, Some(compilation)) => {
+                Err(PostError::Validation(format!(
+                    "Main solution didn't compile: {}",
+                    compilation.stderr_text
+                )))
+            }
+            (job_result::judgement::Verdict::Accepted, _) => Ok(()),
+            (verdict, _) => Err(PostError::Validation(format!(
+                "Main solution got {:?} on test {} of testset {}",
+                verdict, judgement.failed_test, testset.name
+            ))),
+        })?;
+    }
+
+    // The other solutions are only compared with their tags, a mismatch
+    // shows up in the report without failing the import
+    for PackageSolution { path, tag, language } in solutions {
+        reporter.solution(import_problem_id, &path, &tag, "running", None, None);
+        let result: Result<job_result::Judgement, PostError> = async {
+            let language = language?;
+            judge_package_solution(
+                job_sender,
+                job_results,
+                problem,
+                main_testset,
+                data_dir,
+                &language,
+                &path,
+            )
+            .await
+        }
+        .await;
+        match result {
+            Ok(judgement) => {
+                let verdict = verdict_code(judgement.verdict);
+                let status = match solution_tag_accepts(&tag, &verdict) {
+                    Some(true) => "ok",
+                    Some(false) => "mismatch",
+                    None => "unchecked",
+                };
+                let message = if verdict == "AC" {
+                    format!("{} ms", judgement.time_ms)
+                } else {
+                    format!("On test {}, {} ms", judgement.failed_test, judgement.time_ms)
+                };
+                reporter.solution(import_problem_id, &path, &tag, status, Some(verdict), Some(message));
+            }
+            Err(e) => {
+                reporter.solution(import_problem_id, &path, &tag, "failed", None, Some(e.to_string()))
+            }
+        }
+    }
+    Ok(())
+}
 
+/// Judges one of the solutions in a staged problem on a testset as a
+/// submission would be.
+async fn judge_package_solution(
+    job_sender: &Sender<Job>,
+    job_results: &JobResultRegistry,
+    problem: &problem::NewProblem,
+    testset: &problem::ProblemTestset,
+    data_dir: &str,
+    language: &String,
+    source_path: &str,
+) -> Result<job_result::Judgement, PostError> {
+    language::judge(
+        job_sender,
+        job_results,
+        language,
+        fs::read_to_string(PathBuf::from(format!("/data/{}/{}", data_dir, source_path)))?,
+        testset.test_count,
+        format!("./{}/{}", data_dir, testset.test_pattern).into(),
+        problem.checker_language.clone(),
+        format!("./{}/{}", data_dir, problem.checker_path).into(),
+        language::checker_protocol(&problem.package_format),
+        problem.checker_arguments.clone(),
+        problem
+            .grader_paths
+            .iter()
+            .map(|path| format!("./{}/{}", data_dir, path))
+            .collect(),
+        testset.memory_limit_bytes / 1_024,
+        testset.time_limit_ms,
+    )
+    .await
+    .map_err(|_| PostError::Validation(format!("Couldn't judge solution {}", source_path)))
+}
 
-        contest::relate_problem(
-            &connection,
-            contest::NewContestProblems {
-                label: problem_label
-                    .get(&problem_id_without_revision)
-                    .ok_or(PostError::Validation(
-                        "Arquivo não contém problemas listados".into(),
-                    ))?
-                    .to_string()
-                    .to_uppercase(),
-                contest_id: contest.id,
-                problem_id,
-            },
-        )?;
+/// Whether a verdict is what a Polygon solution tag or a Kattis submission
+/// directory promises, `None` for the tags that can't be checked here, like
+/// a failing checker.
+fn solution_tag_accepts(tag: &str, verdict: &str) -> Option<bool> {
+    Some(match tag {
+        "main" | "accepted" => verdict == "AC",
+        // There's no presentation error verdict, the checkers give WA instead
+        "wrong-answer" | "wrong_answer" | "presentation-error" => verdict == "WA",
+        "time-limit-exceeded" | "time_limit_exceeded" => verdict == "TL",
+        "run_time_error" => verdict == "RE",
+        "time-limit-exceeded-or-accepted" => verdict == "TL" || verdict == "AC",
+        "time-limit-exceeded-or-memory-limit-exceeded" => verdict == "TL" || verdict == "ML",
+        "memory-limit-exceeded" => verdict == "ML",
+        "rejected" => verdict != "AC",
+        _ => return None,
+    })
+}
+
+/// A date and time as typed in the server's timezone, `None` when it's
+/// malformed or doesn't exist there, as in the gap of a DST change.
+fn parse_local_date_time(tz: &Tz, s: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .and_then(|d| tz.from_local_datetime(&d).single())
+        .map(|d| d.naive_utc())
+}
+
+#[post("/contests/")]
+async fn create_contest(
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    mut payload: Multipart,
+    job_sender: web::Data<Sender<Job>>,
+    job_results: web::Data<JobResultRegistry>,
+    broadcaster: web::Data<Mutex<Broadcaster>>,
+    tz: web::Data<Tz>,
+) -> PostResult {
+    let logged_user = require_identity(identity)?;
+    if !logged_user.is_admin {
+      return Err(PostError::Unauthorized(UnauthorizedError {}));
     }
 
-    Ok(flash::Response::new(
+    #[derive(Debug)]
+    struct Form {
+        name: Option<String>,
+        start_instant: Option<String>,
+        end_instant: Option<String>,
+        polygon_zip: Option<Cursor<Vec<u8>>>,
+    }
+
+    let mut form = Form {
+        name: None,
+        start_instant: None,
+        end_instant: None,
+        polygon_zip: None,
+    };
+
+    while let Ok(Some(mut field)) = payload.try_next().await {
+        let mut cursor = read_field(&mut field, PACKAGE_SIZE_LIMIT).await?;
+
+        match field.content_disposition().unwrap().get_name() {
+            Some("name") => form.name = Some(parse_field("name", &mut cursor)?),
+            Some("start_instant") => {
+                form.start_instant = Some(parse_field("start_instant", &mut cursor)?)
+            }
+            Some("end_instant") => {
+                form.end_instant = Some(parse_field("end_instant", &mut cursor)?)
+            }
+            Some("polygon_zip") => form.polygon_zip = Some(cursor),
+            _ => {}
+        }
+    }
+
+    let mut polygon_zip = form
+        .polygon_zip
+        .ok_or(PostError::Validation("Arquivo não informado".into()))?;
+    let start_instant = form.start_instant.and_then(|s| parse_local_date_time(&tz, &s));
+    let end_instant = form.end_instant.and_then(|s| parse_local_date_time(&tz, &s));
+    // The form always sends the name, empty when left for the package to give
+    let name = form.name.filter(|name| !name.is_empty());
+
+    let (name, start_instant, end_instant, problems, zip) = if import_contest::clics::is_clics_file(&mut polygon_zip) {
+        let (imported_contest, problems, zip) = import_contest::clics::read_contest(polygon_zip)
+            .map_err(|e| {
+                error!("{}", e);
+                PostError::Validation("Não foi possível importar".into())
+            })?;
+        // What's in the form wins over what's in contest.yaml
+        let imported_start_instant = imported_contest.start_time.as_deref().and_then(|s| {
+            DateTime::parse_from_rfc3339(&s.replace(' ', "T"))
+                .map(|d| d.naive_utc())
+                .ok()
+                .or_else(|| parse_local_date_time(&tz, s))
+        });
+        let start_instant = start_instant.or(imported_start_instant);
+        let end_instant = end_instant.or_else(|| {
+            start_instant
+                .zip(imported_contest.duration())
+                .map(|(start_instant, duration)| start_instant + duration)
+        });
+        let name = name
+            .or(imported_contest.formal_name)
+            .or(imported_contest.name);
+        (name, start_instant, end_instant, problems, zip)
+    } else {
+        let (imported_contest, imported_problems, zip) = import_contest::import_file(polygon_zip)
+            .map_err(|e| {
+                error!("{}", e);
+                PostError::Validation("Não foi possível importar".into())
+            })?;
+
+        let problem_label: HashMap<String, String> =
+            HashMap::from_iter(imported_contest.problems.problem.iter().map(|problem| {
+                (
+                    polygon_url_to_id_without_revision(problem.url.clone()),
+                    problem.index.to_uppercase(),
+                )
+            }));
+
+        let problems = imported_problems
+            .into_iter()
+            .map(|(name, metadata)| {
+                let label = problem_label
+                    .get(&polygon_url_to_id_without_revision(metadata.url.clone()))
+                    .cloned();
+                (format!("{}/", name), import_contest::ProblemPackage::Polygon(Box::new(metadata)), label)
+            })
+            .collect();
+        (name, start_instant, end_instant, problems, zip)
+    };
+
+    let new_contest = contest::NewContest {
+        name: name.ok_or(PostError::Validation("Nome não informado".into()))?,
+        start_instant,
+        end_instant,
+        creation_instant: Local::now().naive_local(),
+        creation_user_id: logged_user.id,
+    };
+    let connection = pool.get()?;
+
+    let uuid = start_package_import(
+        &connection,
+        pool.get_ref().clone(),
+        job_sender.get_ref().clone(),
+        job_results.get_ref().clone(),
+        broadcaster,
+        zip,
+        problems,
+        Some(new_contest),
         None,
-        HttpResponse::Ok().body(imported.3),
+        logged_user.id,
+    )?;
+
+    Ok(flash::Response::with_redirect(
+        "Importação iniciada".into(),
+        &format!("{}imports/{}", env::var("BASE_URL").expect("BASE_URL environment variable is not set"), uuid),
     ))
 }
+
+type PackageZip = ZipArchive<Cursor<Vec<u8>>>;
+
+/// Records a pending import of each problem and runs it in the background,
+/// returning the uuid of the import for its report page.
+fn start_package_import(
+    connection: &PgConnection,
+    pool: DbPool,
+    job_sender: Sender<Job>,
+    job_results: JobResultRegistry,
+    broadcaster: Data<Mutex<Broadcaster>>,
+    zip: PackageZip,
+    problems: Vec<(String, import_contest::ProblemPackage, Option<String>)>,
+    new_contest: Option<contest::NewContest>,
+    replaced: Option<contest::ContestProblem>,
+    user_id: i32,
+) -> Result<String, PostError> {
+    let uuid = Uuid::new_v4().to_string();
+    package_import::insert_package_import(
+        connection,
+        package_import::NewPackageImport {
+            uuid: uuid.clone(),
+            status: "running".into(),
+            contest_id: None,
+            creation_user_id: user_id,
+            creation_instant: Local::now().naive_utc(),
+        },
+    )?;
+
+    let mut pending = Vec::new();
+    for (prefix, package, label) in problems {
+        let import_problem = package_import::insert_package_import_problem(
+            connection,
+            package_import::NewPackageImportProblem {
+                package_import_uuid: uuid.clone(),
+                name: package.name(),
+                label: label.clone(),
+                status: "pending".into(),
+                contest_problem_id: replaced.as_ref().map(|replaced| replaced.id),
+                previous_problem_id: replaced.as_ref().map(|replaced| replaced.problem_id.clone()),
+            },
+        )?;
+        pending.push((import_problem.id, prefix, package, label));
+    }
+
+    let reporter = ImportReporter {
+        pool: pool.clone(),
+        broadcaster,
+        uuid: uuid.clone(),
+    };
+    actix_web::rt::spawn(run_package_import(
+        pool,
+        job_sender,
+        job_results,
+        reporter,
+        zip,
+        pending,
+        new_contest,
+        replaced,
+        user_id,
+    ));
+
+    Ok(uuid)
+}
+
+/// Stores everything an import validated at once, moving the problems out of
+/// the staging directory, so a failure anywhere leaves nothing behind.
+fn commit_package_import(
+    connection: &PgConnection,
+    staging: &str,
+    new_contest: Option<contest::NewContest>,
+    replaced: Option<contest::ContestProblem>,
+    problems: Vec<(ImportedProblem, Option<String>)>,
+) -> Result<Option<i32>, PostError> {
+    let mut moved: Vec<PathBuf> = Vec::new();
+    let result = connection.transaction::<_, PostError, _>(|| {
+        let contest = match new_contest {
+            Some(new_contest) => Some(contest::insert_contest(connection, new_contest)?),
+            None => None,
+        };
+
+        for (imported, label) in problems {
+            let staged_path = PathBuf::from("/data/").join(staging).join(&imported.problem.id);
+            let data_path = PathBuf::from("/data/").join(&imported.problem.id);
+            let problem = problem::upsert_problem(connection, imported.problem)?;
+
+            if let Some(replaced) = &replaced {
+                contest::set_contest_problem(connection, replaced.id, &problem.id)?;
+            }
+
+            if let (Some(contest), Some(label)) = (&contest, label) {
+                contest::relate_problem(
+                    connection,
+                    contest::NewContestProblems {
+                        label,
+                        contest_id: contest.id,
+                        problem_id: problem.id,
+                    },
+                )?;
+            }
+
+            // The same revision imported before already has its files in place,
+            // and the validation results that go with them
+            if !data_path.exists() {
+                problem::insert_problem_testsets(connection, &imported.testsets)?;
+                problem::insert_problem_statements(connection, &imported.statements)?;
+                problem::upsert_problem_tests(connection, &imported.tests)?;
+                fs::rename(&staged_path, &data_path)?;
+                moved.push(data_path);
+            }
+        }
+
+        Ok(contest
+            .map(|contest| contest.id)
+            .or_else(|| replaced.as_ref().map(|replaced| replaced.contest_id)))
+    });
+
+    if result.is_err() {
+        for path in moved {
+            if let Err(e) = fs::remove_dir_all(&path) {
+                error!("Couldn't remove {:?} of a failed import: {}", path, e);
+            }
+        }
+    }
+    result
+}
+
+async fn run_package_import(
+    pool: DbPool,
+    job_sender: Sender<Job>,
+    job_results: JobResultRegistry,
+    reporter: ImportReporter,
+    mut zip: PackageZip,
+    problems: Vec<(i32, String, import_contest::ProblemPackage, Option<String>)>,
+    new_contest: Option<contest::NewContest>,
+    replaced: Option<contest::ContestProblem>,
+    user_id: i32,
+) {
+    let staging = format!(".staging/{}", reporter.uuid);
+    let mut validated = Vec::new();
+    let mut failed = false;
+    for (import_problem_id, prefix, package, label) in problems {
+        reporter.problem(import_problem_id, "running", None, None);
+
+        let result: Result<ImportedProblem, PostError> = async {
+            if new_contest.is_some() && label.is_none() {
+                return Err(PostError::Validation("Problema não listado no contest.xml".into()));
+            }
+            import_problem(
+                &job_sender,
+                &job_results,
+                &mut zip,
+                &prefix,
+                &staging,
+                package,
+                user_id,
+                &reporter,
+                import_problem_id,
+            ).await
+        }.await;
+
+        match result {
+            Ok(imported) => {
+                reporter.problem(import_problem_id, "ok", Some(imported.problem.id.as_str()), None);
+                validated.push((imported, label));
+            }
+            Err(e) => {
+                error!("Couldn't import {} on import uuid={}: {}", prefix, reporter.uuid, e);
+                failed = true;
+                reporter.problem(import_problem_id, "failed", None, Some(e.to_string().as_str()));
+            }
+        }
+    }
+
+    let result = if failed {
+        Err(PostError::Validation("Some problems failed to import".into()))
+    } else {
+        pool.get()
+            .map_err(PostError::from)
+            .and_then(|connection| {
+                commit_package_import(&connection, &staging, new_contest, replaced, validated)
+            })
+    };
+
+    let (status, contest_id) = match result {
+        Ok(contest_id) => ("done", contest_id),
+        Err(e) => {
+            error!("Rolled back import uuid={}: {}", reporter.uuid, e);
+            ("failed", None)
+        }
+    };
+
+    if let Err(e) = fs::remove_dir_all(PathBuf::from("/data/").join(&staging)) {
+        if e.kind() != std::io::ErrorKind::NotFound {
+            error!("Couldn't remove staging directory of import uuid={}: {}", reporter.uuid, e);
+        }
+    }
+
+    let result = pool.get().map_err(PostError::from).and_then(|connection| {
+        Ok(package_import::finish_package_import(
+            &connection,
+            &reporter.uuid,
+            status,
+            contest_id,
+            Local::now().naive_utc(),
+        )?)
+    });
+    if let Err(e) = result {
+        error!("Couldn't finish import uuid={}: {}", reporter.uuid, e);
+    }
+    reporter.notify();
+}
+
+#[derive(Serialize)]
+struct FormattedProblem {
+    pub id: String,
+    pub name: String,
+    pub memory_limit_mib: i32,
+    pub time_limit: String,
+    pub test_count: i32,
+    pub status: String,
+    pub status_name: String,
+    pub creation_instant: String,
+}
+
+fn format_problem_status(status: &str) -> String {
+    match status {
+        "compiled" => "Não validado",
+        "validating" => "Validando",
+        "ready" => "Pronto",
+        "invalid" => "Inválido",
+        _ => status,
+    }
+    .into()
+}
+
+#[get("/problems/")]
+async fn get_problems(
+    flash: Option<flash::Message<String>>,
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    hb: web::Data<Handlebars<'_>>,
+    tz: web::Data<Tz>,
+) -> GetResult {
+    let logged_user = require_identity(identity)?;
+    if !logged_user.is_admin {
+        return Err(GetError::Unauthorized(UnauthorizedError {}));
+    }
+
+    let connection = pool.get()?;
+    let problems = problem::get_problems(&connection)?
+        .into_iter()
+        .map(|p| FormattedProblem {
+            id: p.id,
+            name: p.name,
+            memory_limit_mib: p.memory_limit_bytes / 1_024 / 1_024,
+            time_limit: format!("{}", f64::from(p.time_limit_ms) / 1000.0).replacen(".", ",", 1),
+            test_count: p.test_count,
+            status_name: format_problem_status(&p.status),
+            status: p.status,
+            creation_instant: format_utc_date_time(&tz, p.creation_instant),
+        })
+        .collect();
+
+    #[derive(Serialize)]
+    struct ProblemsContext {
+        logged_user: LoggedUser,
+        flash_message: String,
+        base_url: String,
+        problems: Vec<FormattedProblem>,
+        contests: Vec<FormattedContest>,
+    }
+
+    Ok(flash::Response::new(
+        None,
+        HttpResponse::Ok().body(
+        hb.render(
+            "problems",
+            &ProblemsContext {
+                logged_user,
+                flash_message: flash.map_or("".into(), |f| f.into_inner()),
+                base_url: env::var("BASE_URL")
+                    .expect("BASE_URL environment variable is not set"),
+                problems,
+                contests: get_formatted_contests(&pool, &tz)?,
+            },
+        )?,
+    )))
+}
+
+#[post("/problems/")]
+async fn create_problem(
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    mut payload: Multipart,
+    job_sender: web::Data<Sender<Job>>,
+    job_results: web::Data<JobResultRegistry>,
+    broadcaster: web::Data<Mutex<Broadcaster>>,
+) -> PostResult {
+    let logged_user = require_identity(identity)?;
+    if !logged_user.is_admin {
+        return Err(PostError::Unauthorized(UnauthorizedError {}));
+    }
+
+    let mut polygon_zip: Option<(Cursor<Vec<u8>>, String)> = None;
+    while let Ok(Some(mut field)) = payload.try_next().await {
+        let cursor = read_field(&mut field, PACKAGE_SIZE_LIMIT).await?;
+        let content_disposition = field.content_disposition().unwrap();
+        if let Some("polygon_zip") = content_disposition.get_name() {
+            polygon_zip = Some((cursor, content_disposition.get_filename().unwrap_or_default().into()));
+        }
+    }
+
+    let (polygon_zip, file_name) = polygon_zip.ok_or(PostError::Validation("Arquivo não informado".into()))?;
+    let (prefix, package, zip) = import_contest::import_problem_file(polygon_zip, &file_name)
+        .map_err(|e| {
+            error!("{}", e);
+            PostError::Validation("Não foi possível importar, o zip deve ter um problem.xml ou problem.yaml".into())
+        })?;
+    let connection = pool.get()?;
+
+    let uuid = start_package_import(
+        &connection,
+        pool.get_ref().clone(),
+        job_sender.get_ref().clone(),
+        job_results.get_ref().clone(),
+        broadcaster,
+        zip,
+        vec![(prefix, package, None)],
+        None,
+        None,
+        logged_user.id,
+    )?;
+
+    Ok(flash::Response::with_redirect(
+        "Importação iniciada".into(),
+        &format!("{}imports/{}", env::var("BASE_URL").expect("BASE_URL environment variable is not set"), uuid),
+    ))
+}
+
+#[derive(Serialize, Deserialize)]
+struct ContestProblemForm {
+    problem_id: String,
+    label: String,
+}
+
+#[post("/contests/{id}/problems/")]
+async fn create_contest_problem(
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    path: web::Path<(i32,)>,
+    form: web::Form<ContestProblemForm>,
+    request: HttpRequest,
+) -> PostResult {
+    let logged_user = require_identity(identity)?;
+    if !logged_user.is_admin {
+        return Err(PostError::Unauthorized(UnauthorizedError {}));
+    }
+
+    let (contest_id,) = path.into_inner();
+    let label = form.label.trim().to_uppercase();
+    if label.is_empty() {
+        return Err(PostError::Validation("Rótulo não informado".into()));
+    }
+
+    let connection = pool.get()?;
+    let contest = contest::get_contest_by_id(&connection, contest_id)?;
+    let problem = problem::get_problem_by_id(&connection, &form.problem_id)?;
+    if problem.status != "ready" {
+        return Err(PostError::Validation(format!(
+            "Problema {} não está pronto: {}",
+            problem.id,
+            format_problem_status(&problem.status)
+        )));
+    }
+    if problem::get_problem_by_contest_id_label(&connection, contest.id, &label)
+        .optional()?
+        .is_some()
+    {
+        return Err(PostError::Validation(format!("Já existe um problema {} na competição", label)));
+    }
+
+    contest::relate_problem(
+        &connection,
+        contest::NewContestProblems {
+            label: label.clone(),
+            contest_id: contest.id,
+            problem_id: problem.id,
+        },
+    )?;
+
+    Ok(redirect_to_referer(
+        format!("Problema {} adicionado em {}", label, contest.name),
+        &request,
+    ))
+}
+
+#[derive(Serialize, Deserialize)]
+struct ContestTestsetsForm {
+    contest_testset: String,
+    system_testset: String,
+}
+
+#[post("/contests/{id}/testsets")]
+async fn update_contest_testsets(
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    path: web::Path<(i32,)>,
+    form: web::Form<ContestTestsetsForm>,
+    request: HttpRequest,
+) -> PostResult {
+    let logged_user = require_identity(identity)?;
+    if !logged_user.is_admin {
+        return Err(PostError::Unauthorized(UnauthorizedError {}));
+    }
+
+    let (contest_id,) = path.into_inner();
+    let connection = pool.get()?;
+    let contest = contest::get_contest_by_id(&connection, contest_id)?;
+    let testsets = problem::get_testset_names_by_contest_id(&connection, contest.id)?;
+    for testset in [&form.contest_testset, &form.system_testset] {
+        if !testsets.contains(testset) {
+            return Err(PostError::Validation(format!("Conjunto de testes {} inexistente", testset)));
+        }
+    }
+
+    contest::set_contest_testsets(&connection, contest.id, &form.contest_testset, &form.system_testset)?;
+
+    Ok(redirect_to_referer(
+        format!("Conjuntos de testes de {} atualizados", contest.name),
+        &request,
+    ))
+}
+
+/// Clears the verdict of a stored submission and judges it again.
+async fn rejudge_submission(
+    connection: &PgConnection,
+    job_sender: &Sender<Job>,
+    running_jobs: &RunningJobs,
+    parked_jobs: &ParkedJobs,
+    superseded_jobs: &SupersededJobs,
+    broadcaster: &Mutex<Broadcaster>,
+    submission: &submission::Submission,
+    metadata: problem::ProblemByContestMetadata,
+) -> Result<(), PostError> {
+    let uuid = Uuid::parse_str(&submission.uuid)
+        .map_err(|_| PostError::Validation(format!("Submissão {} inválida", submission.uuid)))?;
+    let source_files = submission::get_submission_files(connection, &submission.uuid)?
+        .into_iter()
+        .map(|file| job::SourceFile {
+            name: file.name,
+            text: file.text,
+        })
+        .collect();
+    let job = judgement_job(
+        &uuid,
+        &submission.language,
+        metadata,
+        submission.source_text.clone(),
+        source_files,
+    );
+    // The judgement in progress, if any, is of the old tests
+    queue::supersede_job(running_jobs, parked_jobs, superseded_jobs, &job);
+    submission::reset_submission(connection, &submission.uuid)?;
+    broadcaster.lock().unwrap().watch_job(&submission.uuid, submission.user_id);
+    job_sender.send(job).await?;
+    Ok(())
+}
+
+/// Judges every accepted submission of a contest again on its system testset.
+#[post("/contests/{id}/system_tests")]
+async fn create_contest_system_tests(
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    path: web::Path<(i32,)>,
+    job_sender: web::Data<Sender<Job>>,
+    running_jobs: web::Data<RunningJobs>,
+    parked_jobs: web::Data<ParkedJobs>,
+    superseded_jobs: web::Data<SupersededJobs>,
+    broadcaster: web::Data<Mutex<Broadcaster>>,
+    request: HttpRequest,
+) -> PostResult {
+    let logged_user = require_identity(identity)?;
+    if !logged_user.is_admin {
+        return Err(PostError::Unauthorized(UnauthorizedError {}));
+    }
+
+    let (contest_id,) = path.into_inner();
+    let connection = pool.get()?;
+    let contest = contest::get_contest_by_id(&connection, contest_id)?;
+    // Rejudges and later submissions go to the system testset as well
+    contest::set_system_tests_instant(&connection, contest.id, Local::now().naive_utc())?;
+    let submissions = submission::get_accepted_submissions_by_contest(&connection, contest.id)?;
+
+    let mut metadata_by_problem = HashMap::new();
+    for (submission, contest_problem) in &submissions {
+        if !metadata_by_problem.contains_key(&contest_problem.id) {
+            let metadata = problem::get_problem_by_contest_id_testset_metadata(
+                &connection,
+                contest_problem.id,
+                &contest.system_testset,
+            )?;
+            metadata_by_problem.insert(contest_problem.id, metadata);
+        }
+        let metadata = metadata_by_problem[&contest_problem.id].clone();
+        rejudge_submission(
+            &connection,
+            &job_sender,
+            &running_jobs,
+            &parked_jobs,
+            &superseded_jobs,
+            &broadcaster,
+            submission,
+            metadata,
+        )
+        .await?;
+    }
+    broadcaster.lock().unwrap().send("update_submission", "");
+
+    Ok(redirect_to_referer(
+        format!("{} submissão(ões) enviada(s) para os testes finais", submissions.len()),
+        &request,
+    ))
+}
+
+/// Imports a new revision of a contest's problem, pointing the contest at it
+/// once the import is committed.
+#[post("/contests/{id}/{label}/package")]
+async fn update_contest_problem_package(
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    path: web::Path<(i32, String)>,
+    mut payload: Multipart,
+    job_sender: web::Data<Sender<Job>>,
+    job_results: web::Data<JobResultRegistry>,
+    broadcaster: web::Data<Mutex<Broadcaster>>,
+) -> PostResult {
+    let logged_user = require_identity(identity)?;
+    if !logged_user.is_admin {
+        return Err(PostError::Unauthorized(UnauthorizedError {}));
+    }
+
+    let mut polygon_zip: Option<(Cursor<Vec<u8>>, String)> = None;
+    while let Ok(Some(mut field)) = payload.try_next().await {
+        let cursor = read_field(&mut field, PACKAGE_SIZE_LIMIT).await?;
+        let content_disposition = field.content_disposition().unwrap();
+        if let Some("polygon_zip") = content_disposition.get_name() {
+            polygon_zip = Some((cursor, content_disposition.get_filename().unwrap_or_default().into()));
+        }
+    }
+
+    let (contest_id, label) = path.into_inner();
+    let connection = pool.get()?;
+    let contest_problem = contest::get_contest_problem_by_label(&connection, contest_id, &label)?;
+
+    let (polygon_zip, file_name) = polygon_zip.ok_or(PostError::Validation("Arquivo não informado".into()))?;
+    let (prefix, package, zip) = import_contest::import_problem_file(polygon_zip, &file_name)
+        .map_err(|e| {
+            error!("{}", e);
+            PostError::Validation("Não foi possível importar, o zip deve ter um problem.xml ou problem.yaml".into())
+        })?;
+    if package_id_without_revision(&package)
+        != contest_problem.problem_id.rsplitn(2, ".r").last().unwrap_or_default()
+    {
+        return Err(PostError::Validation(format!(
+            "O pacote não é de uma revisão do problema {}",
+            contest_problem.problem_id
+        )));
+    }
+
+    let uuid = start_package_import(
+        &connection,
+        pool.get_ref().clone(),
+        job_sender.get_ref().clone(),
+        job_results.get_ref().clone(),
+        broadcaster,
+        zip,
+        vec![(prefix, package, None)],
+        None,
+        Some(contest_problem),
+        logged_user.id,
+    )?;
+
+    Ok(flash::Response::with_redirect(
+        "Importação iniciada".into(),
+        &format!("{}imports/{}", env::var("BASE_URL").expect("BASE_URL environment variable is not set"), uuid),
+    ))
+}
+
+/// Judges every submission of a contest problem again, as after fixing its tests.
+#[post("/contests/{id}/{label}/rejudge")]
+async fn create_contest_problem_rejudge(
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    path: web::Path<(i32, String)>,
+    job_sender: web::Data<Sender<Job>>,
+    running_jobs: web::Data<RunningJobs>,
+    parked_jobs: web::Data<ParkedJobs>,
+    superseded_jobs: web::Data<SupersededJobs>,
+    broadcaster: web::Data<Mutex<Broadcaster>>,
+    request: HttpRequest,
+) -> PostResult {
+    let logged_user = require_identity(identity)?;
+    if !logged_user.is_admin {
+        return Err(PostError::Unauthorized(UnauthorizedError {}));
+    }
+
+    let (contest_id, label) = path.into_inner();
+    let connection = pool.get()?;
+    let contest_problem = contest::get_contest_problem_by_label(&connection, contest_id, &label)?;
+    let metadata = problem::get_problem_by_contest_id_metadata(&connection, contest_problem.id)?;
+    let submissions = submission::get_submissions_by_contest_problem(&connection, contest_problem.id)?;
+    for submission in &submissions {
+        rejudge_submission(
+            &connection,
+            &job_sender,
+            &running_jobs,
+            &parked_jobs,
+            &superseded_jobs,
+            &broadcaster,
+            submission,
+            metadata.clone(),
+        )
+        .await?;
+    }
+    broadcaster.lock().unwrap().send("update_submission", "");
+
+    Ok(redirect_to_referer(
+        format!("{} submissão(ões) de {} enviada(s) para rejulgamento", submissions.len(), label),
+        &request,
+    ))
+}
+
+/// Runs the validator again on every stored test of a problem, which can't
+/// be added to a contest until all of them pass.
+async fn run_problem_validation(
+    pool: DbPool,
+    job_sender: Sender<Job>,
+    job_results: JobResultRegistry,
+    broadcaster: Data<Mutex<Broadcaster>>,
+    problem: problem::Problem,
+    testsets: Vec<problem::ProblemTestset>,
+) {
+    let notify = || {
+        broadcaster
+            .lock()
+            .unwrap()
+            .send(&format!("problem_{}", problem.id), "");
+    };
+    let mut valid = true;
+    for testset in &testsets {
+        for test in 1..=testset.test_count {
+            // Kattis problems may come without an input validator
+            let (validation_status, validation_message) = if problem.validator_path.is_empty() {
+                ("unchecked", None)
+            } else {
+                let result = validate_test(
+                    &job_sender,
+                    &job_results,
+                    &problem.package_format,
+                    &problem.validator_language,
+                    format!("./{}/{}", problem.id, problem.validator_path),
+                    format!(
+                        "./{}/{}",
+                        problem.id,
+                        import_contest::format_width(&testset.test_pattern, test as usize)
+                    ),
+                )
+                .await;
+                match result {
+                    Ok(None) => ("valid", None),
+                    Ok(Some(message)) => ("invalid", Some(message)),
+                    Err(e) => ("failed", Some(e.to_string())),
+                }
+            };
+            valid &= validation_status != "invalid" && validation_status != "failed";
+
+            let result = pool.get().map_err(PostError::from).and_then(|connection| {
+                Ok(problem::upsert_problem_tests(
+                    &connection,
+                    &[problem::ProblemTest {
+                        problem_id: problem.id.clone(),
+                        test,
+                        validation_status: validation_status.into(),
+                        validation_message,
+                        validation_instant: Local::now().naive_utc(),
+                        testset: testset.name.clone(),
+                        // Kept as imported on conflict, only the validation changes
+                        sample: false,
+                    }],
+                )?)
+            });
+            if let Err(e) = result {
+                error!(
+                    "Couldn't store validation of test {} of testset {} of {}: {}",
+                    test, testset.name, problem.id, e
+                );
+            }
+            notify();
+        }
+    }
+
+    let result = pool.get().map_err(PostError::from).and_then(|connection| {
+        Ok(problem::set_problem_status(
+            &connection,
+            &problem.id,
+            if valid { "ready" } else { "invalid" },
+        )?)
+    });
+    if let Err(e) = result {
+        error!("Couldn't finish validation of {}: {}", problem.id, e);
+    }
+    notify();
+}
+
+#[post("/problems/{id}/validation")]
+async fn create_problem_validation(
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    path: web::Path<(String,)>,
+    job_sender: web::Data<Sender<Job>>,
+    job_results: web::Data<JobResultRegistry>,
+    broadcaster: web::Data<Mutex<Broadcaster>>,
+) -> PostResult {
+    let logged_user = require_identity(identity)?;
+    if !logged_user.is_admin {
+        return Err(PostError::Unauthorized(UnauthorizedError {}));
+    }
+
+    let (id,) = path.into_inner();
+    let connection = pool.get()?;
+    let problem = problem::get_problem_by_id(&connection, &id)?;
+    if problem.status == "validating" {
+        return Err(PostError::Validation("Problema já está sendo validado".into()));
+    }
+    let testsets = problem::get_problem_testsets(&connection, &problem.id)?;
+    problem::set_problem_status(&connection, &problem.id, "validating")?;
+
+    actix_web::rt::spawn(run_problem_validation(
+        pool.get_ref().clone(),
+        job_sender.get_ref().clone(),
+        job_results.get_ref().clone(),
+        broadcaster,
+        problem,
+        testsets,
+    ));
+
+    Ok(flash::Response::with_redirect(
+        "Validação iniciada".into(),
+        &format!("{}problems/{}/tests", env::var("BASE_URL").expect("BASE_URL environment variable is not set"), id),
+    ))
+}
+
+#[derive(Serialize)]
+struct FormattedProblemTest {
+    testset: String,
+    test: i32,
+    status: String,
+    message: Option<String>,
+    instant: String,
+}
+
+#[derive(Serialize)]
+struct FormattedProblemTests {
+    id: String,
+    name: String,
+    status: String,
+    status_name: String,
+    invalid_tests: usize,
+    tests: Vec<FormattedProblemTest>,
+}
+
+fn get_formatted_problem_tests(
+    connection: &PgConnection,
+    tz: &Tz,
+    id: &str,
+) -> Result<FormattedProblemTests, GetError> {
+    let problem = problem::get_problem_by_id(connection, id)?;
+    let tests: Vec<_> = problem::get_problem_tests(connection, id)?
+        .into_iter()
+        .map(|t| FormattedProblemTest {
+            testset: t.testset,
+            test: t.test,
+            status: t.validation_status,
+            message: t.validation_message.as_deref().map(truncate_output),
+            instant: format_utc_date_time(tz, t.validation_instant),
+        })
+        .collect();
+    Ok(FormattedProblemTests {
+        id: problem.id,
+        name: problem.name,
+        status_name: format_problem_status(&problem.status),
+        status: problem.status,
+        invalid_tests: tests.iter().filter(|t| t.status == "invalid" || t.status == "failed").count(),
+        tests,
+    })
+}
+
+#[get("/problems/{id}/tests")]
+async fn get_problem_tests(
+    flash: Option<flash::Message<String>>,
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    hb: web::Data<Handlebars<'_>>,
+    tz: web::Data<Tz>,
+    path: web::Path<(String,)>,
+) -> GetResult {
+    let logged_user = require_identity(identity)?;
+    if !logged_user.is_admin {
+        return Err(GetError::Unauthorized(UnauthorizedError {}));
+    }
+
+    let (id,) = path.into_inner();
+    let connection = pool.get()?;
+
+    #[derive(Serialize)]
+    struct ProblemTestsContext {
+        logged_user: LoggedUser,
+        flash_message: String,
+        base_url: String,
+        problem: FormattedProblemTests,
+    }
+
+    Ok(flash::Response::new(
+        None,
+        HttpResponse::Ok().body(
+        hb.render(
+            "problem_tests",
+            &ProblemTestsContext {
+                logged_user,
+                flash_message: flash.map_or("".into(), |f| f.into_inner()),
+                base_url: env::var("BASE_URL")
+                    .expect("BASE_URL environment variable is not set"),
+                problem: get_formatted_problem_tests(&connection, &tz, &id)?,
+            },
+        )?,
+    )))
+}
+
+#[get("/problems/{id}/tests/report")]
+async fn get_problem_tests_report(
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    hb: web::Data<Handlebars<'_>>,
+    tz: web::Data<Tz>,
+    path: web::Path<(String,)>,
+) -> GetResult {
+    let logged_user = require_identity(identity)?;
+    if !logged_user.is_admin {
+        return Err(GetError::Unauthorized(UnauthorizedError {}));
+    }
+
+    let (id,) = path.into_inner();
+    let connection = pool.get()?;
+
+    #[derive(Serialize)]
+    struct ProblemTestsReportContext {
+        base_url: String,
+        problem: FormattedProblemTests,
+    }
+
+    Ok(flash::Response::new(
+        None,
+        HttpResponse::Ok().body(
+        hb.render(
+            "problem_tests_report",
+            &ProblemTestsReportContext {
+                base_url: env::var("BASE_URL")
+                    .expect("BASE_URL environment variable is not set"),
+                problem: get_formatted_problem_tests(&connection, &tz, &id)?,
+            },
+        )?,
+    )))
+}
+
+#[derive(Serialize)]
+struct FormattedPackageImportTest {
+    testset: String,
+    test: i32,
+    status: String,
+    message: Option<String>,
+}
+
+#[derive(Serialize)]
+struct FormattedPackageImportSelfTest {
+    program_name: String,
+    test: i32,
+    expected: String,
+    verdict: Option<String>,
+    message: Option<String>,
+}
+
+#[derive(Serialize)]
+struct FormattedPackageImportSolution {
+    path: String,
+    tag: String,
+    status: String,
+    status_name: String,
+    verdict: Option<String>,
+    message: Option<String>,
+}
+
+#[derive(Serialize)]
+struct FormattedPackageImportProblem {
+    name: String,
+    label: Option<String>,
+    problem_id: Option<String>,
+    status: String,
+    status_name: String,
+    message: Option<String>,
+    tests_ok: usize,
+    tests_total: usize,
+    failed_tests: Vec<FormattedPackageImportTest>,
+    self_tests_ok: usize,
+    self_tests_total: usize,
+    failed_self_tests: Vec<FormattedPackageImportSelfTest>,
+    solutions: Vec<FormattedPackageImportSolution>,
+    replaced: Option<FormattedProblemReplacement>,
+}
+
+#[derive(Serialize)]
+struct FormattedProblemReplacement {
+    contest_id: i32,
+    label: String,
+    previous_problem_id: String,
+    changes: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct FormattedPackageImport {
+    uuid: String,
+    status: String,
+    contest_id: Option<i32>,
+    creation_instant: String,
+    end_instant: Option<String>,
+    failed_problems: usize,
+    mismatched_solutions: usize,
+    problems: Vec<FormattedPackageImportProblem>,
+}
+
+fn format_import_status(status: &str) -> String {
+    match status {
+        "pending" => "Aguardando",
+        "running" => "Importando",
+        "ok" => "Validado",
+        "failed" => "Falhou",
+        "invalid" => "Inválido",
+        "mismatch" => "Divergente",
+        "unchecked" => "Não verificado",
+        _ => status,
+    }
+    .into()
+}
+
+fn format_time_limit(time_limit_ms: i32) -> String {
+    format!("{}s", f64::from(time_limit_ms) / 1000.0).replacen(".", ",", 1)
+}
+
+fn format_memory_limit(memory_limit_bytes: i32) -> String {
+    format!("{}MiB", memory_limit_bytes / 1_024 / 1_024)
+}
+
+fn same_file_contents(first: PathBuf, second: PathBuf) -> bool {
+    match (fs::read(first), fs::read(second)) {
+        (Ok(first), Ok(second)) => first == second,
+        _ => false,
+    }
+}
+
+/// What a new revision of a problem changes in its limits, checker and tests.
+fn get_problem_changes(
+    connection: &PgConnection,
+    previous_id: &str,
+    id: &str,
+) -> Result<Vec<String>, GetError> {
+    let previous = problem::get_problem_by_id(connection, previous_id)?;
+    let current = problem::get_problem_by_id(connection, id)?;
+    let previous_path = PathBuf::from("/data/").join(&previous.id);
+    let current_path = PathBuf::from("/data/").join(&current.id);
+    let mut changes = Vec::new();
+
+    if previous.name != current.name {
+        changes.push(format!("Nome: {} → {}", previous.name, current.name));
+    }
+    if previous.checker_path != current.checker_path
+        || !same_file_contents(
+            previous_path.join(&previous.checker_path),
+            current_path.join(&current.checker_path),
+        )
+    {
+        changes.push("Verificador alterado".into());
+    }
+
+    let previous_testsets = problem::get_problem_testsets(connection, previous_id)?;
+    let current_testsets = problem::get_problem_testsets(connection, id)?;
+    for testset in &previous_testsets {
+        if !current_testsets.iter().any(|t| t.name == testset.name) {
+            changes.push(format!("Conjunto {} removido", testset.name));
+        }
+    }
+    for testset in &current_testsets {
+        let previous_testset = match previous_testsets.iter().find(|t| t.name == testset.name) {
+            Some(previous_testset) => previous_testset,
+            None => {
+                changes.push(format!("Conjunto {} adicionado, com {} teste(s)", testset.name, testset.test_count));
+                continue;
+            }
+        };
+        if previous_testset.time_limit_ms != testset.time_limit_ms {
+            changes.push(format!(
+                "Tempo limite de {}: {} → {}",
+                testset.name,
+                format_time_limit(previous_testset.time_limit_ms),
+                format_time_limit(testset.time_limit_ms)
+            ));
+        }
+        if previous_testset.memory_limit_bytes != testset.memory_limit_bytes {
+            changes.push(format!(
+                "Memória limite de {}: {} → {}",
+                testset.name,
+                format_memory_limit(previous_testset.memory_limit_bytes),
+                format_memory_limit(testset.memory_limit_bytes)
+            ));
+        }
+        if previous_testset.test_count != testset.test_count {
+            changes.push(format!(
+                "Testes de {}: {} → {}",
+                testset.name, previous_testset.test_count, testset.test_count
+            ));
+        }
+        let changed_tests: Vec<String> = (1..=previous_testset.test_count.min(testset.test_count))
+            .filter(|&test| {
+                let previous_test = previous_path
+                    .join(import_contest::format_width(&previous_testset.test_pattern, test as usize));
+                let current_test = current_path
+                    .join(import_contest::format_width(&testset.test_pattern, test as usize));
+                !same_file_contents(previous_test.clone(), current_test.clone())
+                    || !same_file_contents(
+                        PathBuf::from(format!("{}.a", previous_test.display())),
+                        PathBuf::from(format!("{}.a", current_test.display())),
+                    )
+            })
+            .map(|test| test.to_string())
+            .collect();
+        if !changed_tests.is_empty() {
+            changes.push(format!("Testes de {} alterados: {}", testset.name, changed_tests.join(", ")));
+        }
+    }
+
+    Ok(changes)
+}
+
+fn get_formatted_package_import(
+    connection: &PgConnection,
+    tz: &Tz,
+    uuid: &str,
+) -> Result<FormattedPackageImport, GetError> {
+    let package_import = package_import::get_package_import(connection, uuid)?;
+    let mut tests = package_import::get_package_import_tests(connection, uuid)?;
+    let mut solutions = package_import::get_package_import_solutions(connection, uuid)?;
+    let mut self_tests = package_import::get_package_import_self_tests(connection, uuid)?;
+    let problems: Vec<_> = package_import::get_package_import_problems(connection, uuid)?
+        .into_iter()
+        .map(|p| {
+            let (problem_tests, other_tests): (Vec<_>, Vec<_>) = std::mem::take(&mut tests)
+                .into_iter()
+                .partition(|t| t.package_import_problem_id == p.id);
+            tests = other_tests;
+            let (problem_solutions, other_solutions): (Vec<_>, Vec<_>) =
+                std::mem::take(&mut solutions)
+                    .into_iter()
+                    .partition(|s| s.package_import_problem_id == p.id);
+            solutions = other_solutions;
+            let (problem_self_tests, other_self_tests): (Vec<_>, Vec<_>) =
+                std::mem::take(&mut self_tests)
+                    .into_iter()
+                    .partition(|t| t.package_import_problem_id == p.id);
+            self_tests = other_self_tests;
+            // The changes only make sense once the new revision is in place
+            let replaced = match (&p.contest_problem_id, &p.previous_problem_id, &p.problem_id) {
+                (Some(contest_problem_id), Some(previous_problem_id), Some(problem_id))
+                    if package_import.status == "done" =>
+                {
+                    let contest_problem = contest::get_contest_problem_by_id(connection, *contest_problem_id)?;
+                    Some(FormattedProblemReplacement {
+                        contest_id: contest_problem.contest_id,
+                        label: contest_problem.label,
+                        previous_problem_id: previous_problem_id.clone(),
+                        changes: get_problem_changes(connection, previous_problem_id, problem_id)?,
+                    })
+                }
+                _ => None,
+            };
+            Ok(FormattedPackageImportProblem {
+                name: p.name,
+                label: p.label,
+                problem_id: p.problem_id,
+                status_name: format_import_status(&p.status),
+                status: p.status,
+                message: p.message.as_deref().map(truncate_output),
+                tests_ok: problem_tests.iter().filter(|t| t.status == "ok").count(),
+                tests_total: problem_tests.len(),
+                failed_tests: problem_tests
+                    .into_iter()
+                    .filter(|t| t.status == "failed" || t.status == "invalid")
+                    .map(|t| FormattedPackageImportTest {
+                        testset: t.testset,
+                        test: t.test,
+                        status: t.status,
+                        message: t.message.as_deref().map(truncate_output),
+                    })
+                    .collect(),
+                self_tests_ok: problem_self_tests.iter().filter(|t| t.status == "ok").count(),
+                self_tests_total: problem_self_tests.len(),
+                failed_self_tests: problem_self_tests
+                    .into_iter()
+                    .filter(|t| t.status != "ok")
+                    .map(|t| FormattedPackageImportSelfTest {
+                        program_name: match t.program.as_str() {
+                            "checker" => "Verificador".into(),
+                            "validator" => "Validador".into(),
+                            _ => t.program.clone(),
+                        },
+                        test: t.test,
+                        expected: t.expected,
+                        verdict: t.verdict,
+                        message: t.message.as_deref().map(truncate_output),
+                    })
+                    .collect(),
+                solutions: problem_solutions
+                    .into_iter()
+                    .map(|s| FormattedPackageImportSolution {
+                        path: s.path,
+                        tag: s.tag,
+                        status_name: format_import_status(&s.status),
+                        status: s.status,
+                        verdict: s.verdict,
+                        message: s.message.as_deref().map(truncate_output),
+                    })
+                    .collect(),
+                replaced,
+            })
+        })
+        .collect::<Result<_, GetError>>()?;
+
+    Ok(FormattedPackageImport {
+        uuid: package_import.uuid,
+        status: package_import.status,
+        contest_id: package_import.contest_id,
+        creation_instant: format_utc_date_time(tz, package_import.creation_instant),
+        end_instant: package_import.end_instant.map(|i| format_utc_date_time(tz, i)),
+        failed_problems: problems.iter().filter(|p| p.status == "failed").count(),
+        mismatched_solutions: problems
+            .iter()
+            .flat_map(|p| &p.solutions)
+            .filter(|s| s.status == "mismatch")
+            .count(),
+        problems,
+    })
+}
+
+#[get("/imports/{uuid}")]
+async fn get_package_import(
+    flash: Option<flash::Message<String>>,
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    hb: web::Data<Handlebars<'_>>,
+    tz: web::Data<Tz>,
+    path: web::Path<(String,)>,
+) -> GetResult {
+    let logged_user = require_identity(identity)?;
+    if !logged_user.is_admin {
+        return Err(GetError::Unauthorized(UnauthorizedError {}));
+    }
+
+    let (uuid,) = path.into_inner();
+    let connection = pool.get()?;
+
+    #[derive(Serialize)]
+    struct PackageImportContext {
+        logged_user: LoggedUser,
+        flash_message: String,
+        base_url: String,
+        package_import: FormattedPackageImport,
+    }
+
+    Ok(flash::Response::new(
+        None,
+        HttpResponse::Ok().body(
+        hb.render(
+            "package_import",
+            &PackageImportContext {
+                logged_user,
+                flash_message: flash.map_or("".into(), |f| f.into_inner()),
+                base_url: env::var("BASE_URL")
+                    .expect("BASE_URL environment variable is not set"),
+                package_import: get_formatted_package_import(&connection, &tz, &uuid)?,
+            },
+        )?,
+    )))
+}
+
+#[get("/imports/{uuid}/report")]
+async fn get_package_import_report(
+    identity: Identity,
+    pool: web::Data<DbPool>,
+    hb: web::Data<Handlebars<'_>>,
+    tz: web::Data<Tz>,
+    path: web::Path<(String,)>,
+) -> GetResult {
+    let logged_user = require_identity(identity)?;
+    if !logged_user.is_admin {
+        return Err(GetError::Unauthorized(UnauthorizedError {}));
+    }
+
+    let (uuid,) = path.into_inner();
+    let connection = pool.get()?;
+
+    #[derive(Serialize)]
+    struct PackageImportReportContext {
+        base_url: String,
+        package_import: FormattedPackageImport,
+    }
+
+    Ok(flash::Response::new(
+        None,
+        HttpResponse::Ok().body(
+        hb.render(
+            "package_import_report",
+            &PackageImportReportContext {
+                base_url: env::var("BASE_URL")
+                    .expect("BASE_URL environment variable is not set"),
+                package_import: get_formatted_package_import(&connection, &tz, &uuid)?,
+            },
+        )?,
+    )))
+}
@@ -1,4 +1,5 @@
 pub mod contest;
+pub mod package_import;
 pub mod problem;
 pub mod submission;
 pub mod user;
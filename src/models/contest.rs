@@ -13,6 +13,9 @@ pub struct Contest {
     pub end_instant: Option<NaiveDateTime>,
     pub creation_user_id: i32,
     pub creation_instant: NaiveDateTime,
+    pub contest_testset: String,
+    pub system_testset: String,
+    pub system_tests_instant: Option<NaiveDateTime>,
 }
 
 #[derive(Insertable)]
@@ -44,6 +47,35 @@ pub fn get_contest_by_id(connection: &PgConnection, id: i32) -> QueryResult<Cont
         .first(connection)
 }
 
+pub fn set_contest_testsets(
+    connection: &PgConnection,
+    id: i32,
+    contest_testset: &str,
+    system_testset: &str,
+) -> QueryResult<()> {
+    diesel::update(contest::table)
+        .filter(contest::id.eq(id))
+        .set((
+            contest::contest_testset.eq(contest_testset),
+            contest::system_testset.eq(system_testset),
+        ))
+        .execute(connection)?;
+    Ok(())
+}
+
+/// From now on the contest is judged on its system testset.
+pub fn set_system_tests_instant(
+    connection: &PgConnection,
+    id: i32,
+    system_tests_instant: NaiveDateTime,
+) -> QueryResult<()> {
+    diesel::update(contest::table)
+        .filter(contest::id.eq(id))
+        .set(contest::system_tests_instant.eq(system_tests_instant))
+        .execute(connection)?;
+    Ok(())
+}
+
 #[derive(Insertable)]
 #[table_name = "contest_problems"]
 pub struct NewContestProblems {
@@ -61,3 +93,44 @@ pub fn relate_problem(
         .execute(connection)?;
     Ok(())
 }
+
+#[derive(Queryable)]
+pub struct ContestProblem {
+    pub id: i32,
+    pub label: String,
+    pub contest_id: i32,
+    pub problem_id: String,
+}
+
+pub fn get_contest_problem_by_id(
+    connection: &PgConnection,
+    id: i32,
+) -> QueryResult<ContestProblem> {
+    contest_problems::table
+        .filter(contest_problems::id.eq(id))
+        .first(connection)
+}
+
+pub fn get_contest_problem_by_label(
+    connection: &PgConnection,
+    contest_id: i32,
+    label: &str,
+) -> QueryResult<ContestProblem> {
+    contest_problems::table
+        .filter(contest_problems::contest_id.eq(contest_id))
+        .filter(contest_problems::label.eq(label))
+        .first(connection)
+}
+
+/// Points a contest problem at another revision of its problem.
+pub fn set_contest_problem(
+    connection: &PgConnection,
+    id: i32,
+    problem_id: &str,
+) -> QueryResult<()> {
+    diesel::update(contest_problems::table)
+        .filter(contest_problems::id.eq(id))
+        .set(contest_problems::problem_id.eq(problem_id))
+        .execute(connection)?;
+    Ok(())
+}
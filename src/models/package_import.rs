@@ -0,0 +1,290 @@
+use chrono::prelude::*;
+use diesel::insert_into;
+use diesel::prelude::*;
+
+use crate::schema::package_import;
+use crate::schema::package_import_problem;
+use crate::schema::package_import_self_test;
+use crate::schema::package_import_solution;
+use crate::schema::package_import_test;
+
+#[derive(Queryable)]
+pub struct PackageImport {
+    pub uuid: String,
+    pub status: String,
+    pub contest_id: Option<i32>,
+    pub creation_user_id: i32,
+    pub creation_instant: NaiveDateTime,
+    pub end_instant: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[table_name = "package_import"]
+pub struct NewPackageImport {
+    pub uuid: String,
+    pub status: String,
+    pub contest_id: Option<i32>,
+    pub creation_user_id: i32,
+    pub creation_instant: NaiveDateTime,
+}
+
+pub fn insert_package_import(
+    connection: &PgConnection,
+    new_package_import: NewPackageImport,
+) -> QueryResult<()> {
+    insert_into(package_import::table)
+        .values(new_package_import)
+        .execute(connection)?;
+    Ok(())
+}
+
+pub fn finish_package_import(
+    connection: &PgConnection,
+    uuid: &str,
+    status: &str,
+    contest_id: Option<i32>,
+    end_instant: NaiveDateTime,
+) -> QueryResult<()> {
+    diesel::update(package_import::table)
+        .filter(package_import::uuid.eq(uuid))
+        .set((
+            package_import::status.eq(status),
+            package_import::contest_id.eq(contest_id),
+            package_import::end_instant.eq(end_instant),
+        ))
+        .execute(connection)?;
+    Ok(())
+}
+
+/// Fails the imports, and the problems in them, that were cut short.
+pub fn fail_interrupted_imports(connection: &PgConnection, end_instant: NaiveDateTime) -> QueryResult<usize> {
+    diesel::update(package_import_problem::table)
+        .filter(package_import_problem::status.eq("running"))
+        .set((
+            package_import_problem::status.eq("failed"),
+            package_import_problem::message.eq("Interrupted by a restart"),
+        ))
+        .execute(connection)?;
+    diesel::update(package_import::table)
+        .filter(package_import::status.eq("running"))
+        .set((
+            package_import::status.eq("failed"),
+            package_import::end_instant.eq(end_instant),
+        ))
+        .execute(connection)
+}
+
+pub fn get_package_import(connection: &PgConnection, uuid: &str) -> QueryResult<PackageImport> {
+    package_import::table
+        .filter(package_import::uuid.eq(uuid))
+        .first(connection)
+}
+
+#[derive(Queryable)]
+pub struct PackageImportProblem {
+    pub id: i32,
+    pub package_import_uuid: String,
+    pub name: String,
+    pub label: Option<String>,
+    pub problem_id: Option<String>,
+    pub status: String,
+    pub message: Option<String>,
+    pub contest_problem_id: Option<i32>,
+    pub previous_problem_id: Option<String>,
+}
+
+#[derive(Insertable)]
+#[table_name = "package_import_problem"]
+pub struct NewPackageImportProblem {
+    pub package_import_uuid: String,
+    pub name: String,
+    pub label: Option<String>,
+    pub status: String,
+    pub contest_problem_id: Option<i32>,
+    pub previous_problem_id: Option<String>,
+}
+
+pub fn insert_package_import_problem(
+    connection: &PgConnection,
+    new_package_import_problem: NewPackageImportProblem,
+) -> QueryResult<PackageImportProblem> {
+    insert_into(package_import_problem::table)
+        .values(new_package_import_problem)
+        .get_result(connection)
+}
+
+pub fn set_problem_status(
+    connection: &PgConnection,
+    id: i32,
+    status: &str,
+    problem_id: Option<&str>,
+    message: Option<&str>,
+) -> QueryResult<()> {
+    diesel::update(package_import_problem::table)
+        .filter(package_import_problem::id.eq(id))
+        .set((
+            package_import_problem::status.eq(status),
+            package_import_problem::problem_id.eq(problem_id),
+            package_import_problem::message.eq(message),
+        ))
+        .execute(connection)?;
+    Ok(())
+}
+
+pub fn get_package_import_problems(
+    connection: &PgConnection,
+    uuid: &str,
+) -> QueryResult<Vec<PackageImportProblem>> {
+    package_import_problem::table
+        .filter(package_import_problem::package_import_uuid.eq(uuid))
+        .order(package_import_problem::id)
+        .load(connection)
+}
+
+#[derive(Queryable, Insertable)]
+#[table_name = "package_import_test"]
+pub struct PackageImportTest {
+    pub package_import_problem_id: i32,
+    pub test: i32,
+    pub status: String,
+    pub message: Option<String>,
+    pub testset: String,
+}
+
+pub fn upsert_package_import_test(
+    connection: &PgConnection,
+    package_import_test: PackageImportTest,
+) -> QueryResult<()> {
+    insert_into(package_import_test::table)
+        .values(&package_import_test)
+        .on_conflict((
+            package_import_test::package_import_problem_id,
+            package_import_test::testset,
+            package_import_test::test,
+        ))
+        .do_update()
+        .set((
+            package_import_test::status.eq(&package_import_test.status),
+            package_import_test::message.eq(&package_import_test.message),
+        ))
+        .execute(connection)?;
+    Ok(())
+}
+
+pub fn get_package_import_tests(
+    connection: &PgConnection,
+    uuid: &str,
+) -> QueryResult<Vec<PackageImportTest>> {
+    package_import_test::table
+        .inner_join(package_import_problem::table)
+        .filter(package_import_problem::package_import_uuid.eq(uuid))
+        .select((
+            package_import_test::package_import_problem_id,
+            package_import_test::test,
+            package_import_test::status,
+            package_import_test::message,
+            package_import_test::testset,
+        ))
+        .order((
+            package_import_test::package_import_problem_id,
+            package_import_test::testset,
+            package_import_test::test,
+        ))
+        .load(connection)
+}
+
+#[derive(Queryable, Insertable)]
+#[table_name = "package_import_solution"]
+pub struct PackageImportSolution {
+    pub package_import_problem_id: i32,
+    pub path: String,
+    pub tag: String,
+    pub status: String,
+    pub verdict: Option<String>,
+    pub message: Option<String>,
+}
+
+pub fn upsert_package_import_solution(
+    connection: &PgConnection,
+    package_import_solution: PackageImportSolution,
+) -> QueryResult<()> {
+    insert_into(package_import_solution::table)
+        .values(&package_import_solution)
+        .on_conflict((
+            package_import_solution::package_import_problem_id,
+            package_import_solution::path,
+        ))
+        .do_update()
+        .set((
+            package_import_solution::status.eq(&package_import_solution.status),
+            package_import_solution::verdict.eq(&package_import_solution.verdict),
+            package_import_solution::message.eq(&package_import_solution.message),
+        ))
+        .execute(connection)?;
+    Ok(())
+}
+
+pub fn get_package_import_solutions(
+    connection: &PgConnection,
+    uuid: &str,
+) -> QueryResult<Vec<PackageImportSolution>> {
+    package_import_solution::table
+        .inner_join(package_import_problem::table)
+        .filter(package_import_problem::package_import_uuid.eq(uuid))
+        .select((
+            package_import_solution::package_import_problem_id,
+            package_import_solution::path,
+            package_import_solution::tag,
+            package_import_solution::status,
+            package_import_solution::verdict,
+            package_import_solution::message,
+        ))
+        .order((package_import_solution::package_import_problem_id, package_import_solution::path))
+        .load(connection)
+}
+
+#[derive(Queryable, Insertable)]
+#[table_name = "package_import_self_test"]
+pub struct PackageImportSelfTest {
+    pub package_import_problem_id: i32,
+    pub program: String,
+    pub test: i32,
+    pub expected: String,
+    pub verdict: Option<String>,
+    pub status: String,
+    pub message: Option<String>,
+}
+
+pub fn insert_package_import_self_test(
+    connection: &PgConnection,
+    package_import_self_test: PackageImportSelfTest,
+) -> QueryResult<()> {
+    insert_into(package_import_self_test::table)
+        .values(package_import_self_test)
+        .execute(connection)?;
+    Ok(())
+}
+
+pub fn get_package_import_self_tests(
+    connection: &PgConnection,
+    uuid: &str,
+) -> QueryResult<Vec<PackageImportSelfTest>> {
+    package_import_self_test::table
+        .inner_join(package_import_problem::table)
+        .filter(package_import_problem::package_import_uuid.eq(uuid))
+        .select((
+            package_import_self_test::package_import_problem_id,
+            package_import_self_test::program,
+            package_import_self_test::test,
+            package_import_self_test::expected,
+            package_import_self_test::verdict,
+            package_import_self_test::status,
+            package_import_self_test::message,
+        ))
+        .order((
+            package_import_self_test::package_import_problem_id,
+            package_import_self_test::program,
+            package_import_self_test::test,
+        ))
+        .load(connection)
+}
@@ -6,6 +6,10 @@ use serde::Serialize;
 
 use crate::schema::contest_problems;
 use crate::schema::problem;
+use crate::schema::problem_statement;
+use crate::schema::problem_test;
+use crate::schema::problem_testset;
+use crate::schema::contest;
 
 #[derive(Queryable)]
 pub struct Problem {
@@ -24,6 +28,9 @@ pub struct Problem {
     pub status: String,
     pub creation_user_id: i32,
     pub creation_instant: NaiveDateTime,
+    pub grader_paths: Vec<String>,
+    pub package_format: String,
+    pub checker_arguments: Vec<String>,
 }
 
 #[derive(Insertable)]
@@ -44,6 +51,9 @@ pub struct NewProblem {
     pub status: String,
     pub creation_user_id: i32,
     pub creation_instant: NaiveDateTime,
+    pub grader_paths: Vec<String>,
+    pub package_format: String,
+    pub checker_arguments: Vec<String>,
 }
 
 #[derive(Queryable, Serialize)]
@@ -113,12 +123,15 @@ pub fn get_problems_user_by_contest_id_with_score(
             contest_problems.id,
             problem.name,
             contest_problems.label,
-            problem.memory_limit_bytes,
-            problem.time_limit_ms
+            coalesce(problem_testset.memory_limit_bytes, problem.memory_limit_bytes) as memory_limit_bytes,
+            coalesce(problem_testset.time_limit_ms, problem.time_limit_ms) as time_limit_ms
         from contest_problems
+        inner join contest on contest.id = contest_problems.contest_id
         inner join problem on problem.id = contest_problems.problem_id
+        left join problem_testset on problem_testset.problem_id = problem.id
+            and problem_testset.name = contest.contest_testset
         left join first_ac on first_ac.contest_problem_id = contest_problems.id
-        where contest_id = $2
+        where contest_problems.contest_id = $2
         order by contest_problems.label
     ")
     .bind::<sql_types::Integer, _>(user_id)
@@ -140,7 +153,7 @@ pub fn get_problem_by_contest_id_label(
         .first(connection)
 }
 
-#[derive(Queryable)]
+#[derive(Queryable, Clone)]
 pub struct ProblemByContestMetadata {
     pub id: String,
     pub memory_limit_bytes: i32,
@@ -154,11 +167,60 @@ pub struct ProblemByContestMetadata {
     pub test_count: i32,
     pub test_pattern: String,
     pub status: String,
+    pub grader_paths: Vec<String>,
+    pub package_format: String,
+    pub checker_arguments: Vec<String>,
 }
 
+/// The problem as judged in its contest, with the limits and tests of the
+/// testset it's judged on.
 pub fn get_problem_by_contest_id_metadata(
     connection: &PgConnection,
     contest_problem_id: i32,
+) -> QueryResult<ProblemByContestMetadata> {
+    let testset = get_contest_testset(connection, contest_problem_id)?;
+    get_problem_by_contest_id_testset_metadata(connection, contest_problem_id, &testset)
+}
+
+/// The testset a contest problem is judged on: the contest's one, and the
+/// system one once the system tests have run.
+pub fn get_contest_testset(connection: &PgConnection, contest_problem_id: i32) -> QueryResult<String> {
+    let (contest_testset, system_testset, system_tests_instant) = contest_problems::table
+        .inner_join(contest::table)
+        .filter(contest_problems::id.eq(contest_problem_id))
+        .select((contest::contest_testset, contest::system_testset, contest::system_tests_instant))
+        .first::<(String, String, Option<NaiveDateTime>)>(connection)?;
+    Ok(match system_tests_instant {
+        Some(_) => system_testset,
+        None => contest_testset,
+    })
+}
+
+/// The problem as judged on one of its testsets, problems without it keeping
+/// their main one.
+pub fn get_problem_by_contest_id_testset_metadata(
+    connection: &PgConnection,
+    contest_problem_id: i32,
+    testset: &str,
+) -> QueryResult<ProblemByContestMetadata> {
+    let mut metadata = get_problem_by_contest_id_main_metadata(connection, contest_problem_id)?;
+    if let Some(testset) = problem_testset::table
+        .filter(problem_testset::problem_id.eq(&metadata.id))
+        .filter(problem_testset::name.eq(testset))
+        .first::<ProblemTestset>(connection)
+        .optional()?
+    {
+        metadata.time_limit_ms = testset.time_limit_ms;
+        metadata.memory_limit_bytes = testset.memory_limit_bytes;
+        metadata.test_count = testset.test_count;
+        metadata.test_pattern = testset.test_pattern;
+    }
+    Ok(metadata)
+}
+
+fn get_problem_by_contest_id_main_metadata(
+    connection: &PgConnection,
+    contest_problem_id: i32,
 ) -> QueryResult<ProblemByContestMetadata> {
     problem::table
         .inner_join(contest_problems::table)
@@ -176,10 +238,39 @@ pub fn get_problem_by_contest_id_metadata(
             problem::test_count,
             problem::test_pattern,
             problem::status,
+            problem::grader_paths,
+            problem::package_format,
+            problem::checker_arguments,
         ))
         .first(connection)
 }
 
+#[derive(Queryable)]
+pub struct ProblemListing {
+    pub id: String,
+    pub name: String,
+    pub memory_limit_bytes: i32,
+    pub time_limit_ms: i32,
+    pub test_count: i32,
+    pub status: String,
+    pub creation_instant: NaiveDateTime,
+}
+
+pub fn get_problems(connection: &PgConnection) -> QueryResult<Vec<ProblemListing>> {
+    problem::table
+        .select((
+            problem::id,
+            problem::name,
+            problem::memory_limit_bytes,
+            problem::time_limit_ms,
+            problem::test_count,
+            problem::status,
+            problem::creation_instant,
+        ))
+        .order(problem::creation_instant.desc())
+        .load(connection)
+}
+
 pub fn upsert_problem(
     connection: &PgConnection,
     new_problem: NewProblem,
@@ -200,3 +291,157 @@ pub fn upsert_problem(
         }
     }
 }
+
+pub fn get_problem_by_id(connection: &PgConnection, id: &str) -> QueryResult<Problem> {
+    problem::table.filter(problem::id.eq(id)).first(connection)
+}
+
+pub fn set_problem_status(connection: &PgConnection, id: &str, status: &str) -> QueryResult<()> {
+    diesel::update(problem::table)
+        .filter(problem::id.eq(id))
+        .set(problem::status.eq(status))
+        .execute(connection)?;
+    Ok(())
+}
+
+/// Marks problems whose validation was cut short as invalid, so they can be
+/// validated again.
+pub fn fail_interrupted_validations(connection: &PgConnection) -> QueryResult<usize> {
+    diesel::update(problem::table)
+        .filter(problem::status.eq("validating"))
+        .set(problem::status.eq("invalid"))
+        .execute(connection)
+}
+
+#[derive(Queryable, Insertable)]
+#[table_name = "problem_test"]
+pub struct ProblemTest {
+    pub problem_id: String,
+    pub test: i32,
+    pub validation_status: String,
+    pub validation_message: Option<String>,
+    pub validation_instant: NaiveDateTime,
+    pub testset: String,
+    pub sample: bool,
+}
+
+pub fn upsert_problem_tests(
+    connection: &PgConnection,
+    problem_tests: &[ProblemTest],
+) -> QueryResult<()> {
+    use diesel::pg::upsert::excluded;
+
+    insert_into(problem_test::table)
+        .values(problem_tests)
+        .on_conflict((problem_test::problem_id, problem_test::testset, problem_test::test))
+        .do_update()
+        .set((
+            problem_test::validation_status.eq(excluded(problem_test::validation_status)),
+            problem_test::validation_message.eq(excluded(problem_test::validation_message)),
+            problem_test::validation_instant.eq(excluded(problem_test::validation_instant)),
+        ))
+        .execute(connection)?;
+    Ok(())
+}
+
+pub fn get_problem_tests(connection: &PgConnection, id: &str) -> QueryResult<Vec<ProblemTest>> {
+    problem_test::table
+        .filter(problem_test::problem_id.eq(id))
+        .order((problem_test::testset, problem_test::test))
+        .load(connection)
+}
+
+#[derive(Queryable, Insertable, Clone)]
+#[table_name = "problem_testset"]
+pub struct ProblemTestset {
+    pub problem_id: String,
+    pub name: String,
+    pub time_limit_ms: i32,
+    pub memory_limit_bytes: i32,
+    pub test_count: i32,
+    pub test_pattern: String,
+}
+
+pub fn insert_problem_testsets(
+    connection: &PgConnection,
+    problem_testsets: &[ProblemTestset],
+) -> QueryResult<()> {
+    insert_into(problem_testset::table)
+        .values(problem_testsets)
+        .on_conflict_do_nothing()
+        .execute(connection)?;
+    Ok(())
+}
+
+pub fn get_problem_testsets(
+    connection: &PgConnection,
+    id: &str,
+) -> QueryResult<Vec<ProblemTestset>> {
+    problem_testset::table
+        .filter(problem_testset::problem_id.eq(id))
+        .order(problem_testset::name)
+        .load(connection)
+}
+
+/// Names of the testsets any problem of the contest has.
+pub fn get_testset_names_by_contest_id(
+    connection: &PgConnection,
+    contest_id: i32,
+) -> QueryResult<Vec<String>> {
+    problem_testset::table
+        .inner_join(contest_problems::table.on(contest_problems::problem_id.eq(problem_testset::problem_id)))
+        .filter(contest_problems::contest_id.eq(contest_id))
+        .select(problem_testset::name)
+        .distinct()
+        .order(problem_testset::name)
+        .load(connection)
+}
+
+#[derive(Queryable, Insertable, Clone)]
+#[table_name = "problem_statement"]
+pub struct ProblemStatement {
+    pub problem_id: String,
+    pub language: String,
+    pub mime_type: String,
+    pub path: String,
+    pub charset: Option<String>,
+    pub mathjax: bool,
+}
+
+pub fn insert_problem_statements(
+    connection: &PgConnection,
+    problem_statements: &[ProblemStatement],
+) -> QueryResult<()> {
+    insert_into(problem_statement::table)
+        .values(problem_statements)
+        .on_conflict_do_nothing()
+        .execute(connection)?;
+    Ok(())
+}
+
+pub fn get_problem_statements_by_contest_id(
+    connection: &PgConnection,
+    contest_problem_id: i32,
+) -> QueryResult<Vec<ProblemStatement>> {
+    problem_statement::table
+        .inner_join(contest_problems::table.on(contest_problems::problem_id.eq(problem_statement::problem_id)))
+        .filter(contest_problems::id.eq(contest_problem_id))
+        .select(problem_statement::all_columns)
+        .order((problem_statement::language, problem_statement::mime_type))
+        .load(connection)
+}
+
+/// The sample tests of a testset, in order.
+pub fn get_problem_samples(
+    connection: &PgConnection,
+    id: &str,
+    testset: &str,
+) -> QueryResult<Vec<i32>> {
+    problem_test::table
+        .filter(problem_test::problem_id.eq(id))
+        .filter(problem_test::testset.eq(testset))
+        .filter(problem_test::sample.eq(true))
+        .select(problem_test::test)
+        .order(problem_test::test)
+        .load(connection)
+}
@@ -1,4 +1,5 @@
 use crate::schema::submission;
+use crate::schema::submission_file;
 use crate::schema::contest_problems;
 use chrono::prelude::*;
 use diesel::insert_into;
@@ -19,6 +20,11 @@ pub struct Submission {
     pub error_output: Option<String>,
     pub contest_problem_id: i32,
     pub user_id: i32,
+    pub compilation_exit_code: Option<i32>,
+    pub compilation_time_ms: Option<i32>,
+    pub compilation_memory_kib: Option<i32>,
+    pub compilation_stdout: Option<String>,
+    pub compilation_stderr: Option<String>,
 }
 
 #[derive(Queryable)]
@@ -50,6 +56,41 @@ pub fn insert_submission(
     Ok(())
 }
 
+#[derive(Insertable)]
+#[table_name = "submission_file"]
+pub struct NewSubmissionFile {
+    pub submission_uuid: String,
+    pub name: String,
+    pub text: String,
+}
+
+#[derive(Queryable)]
+pub struct SubmissionFile {
+    pub submission_uuid: String,
+    pub name: String,
+    pub text: String,
+}
+
+pub fn get_submission_files(
+    connection: &PgConnection,
+    uuid: &str,
+) -> QueryResult<Vec<SubmissionFile>> {
+    submission_file::table
+        .filter(submission_file::submission_uuid.eq(uuid))
+        .order(submission_file::name)
+        .load(connection)
+}
+
+pub fn insert_submission_files(
+    connection: &PgConnection,
+    new_submission_files: Vec<NewSubmissionFile>,
+) -> QueryResult<()> {
+    insert_into(submission_file::table)
+        .values(new_submission_files)
+        .execute(connection)?;
+    Ok(())
+}
+
 pub struct SubmissionCompletion {
     pub uuid: String,
     pub verdict: String,
@@ -59,12 +100,22 @@ pub struct SubmissionCompletion {
     pub time_ms: Option<i32>,
     pub time_wall_ms: Option<i32>,
     pub error_output: Option<String>,
+    pub compilation: Option<SubmissionCompilation>,
+}
+
+pub struct SubmissionCompilation {
+    pub exit_code: i32,
+    pub time_ms: i32,
+    pub memory_kib: i32,
+    pub stdout: String,
+    pub stderr: String,
 }
 
 pub fn complete_submission(
     connection: &PgConnection,
     submission: SubmissionCompletion,
 ) -> QueryResult<()> {
+    let compilation = submission.compilation;
     diesel::update(submission::table)
         .filter(submission::uuid.eq(submission.uuid))
         .set((
@@ -75,6 +126,27 @@ pub fn complete_submission(
             submission::time_ms.eq(submission.time_ms),
             submission::time_wall_ms.eq(submission.time_wall_ms),
             submission::error_output.eq(submission.error_output),
+            submission::compilation_exit_code.eq(compilation.as_ref().map(|c| c.exit_code)),
+            submission::compilation_time_ms.eq(compilation.as_ref().map(|c| c.time_ms)),
+            submission::compilation_memory_kib.eq(compilation.as_ref().map(|c| c.memory_kib)),
+            submission::compilation_stdout.eq(compilation.as_ref().map(|c| c.stdout.clone())),
+            submission::compilation_stderr.eq(compilation.map(|c| c.stderr)),
+        ))
+        .execute(connection)?;
+    Ok(())
+}
+
+pub fn fail_submission(
+    connection: &PgConnection,
+    uuid: &str,
+    verdict: &str,
+    judge_end_instant: NaiveDateTime,
+) -> QueryResult<()> {
+    diesel::update(submission::table)
+        .filter(submission::uuid.eq(uuid))
+        .set((
+            submission::verdict.eq(verdict),
+            submission::judge_end_instant.eq(judge_end_instant),
         ))
         .execute(connection)?;
     Ok(())
@@ -115,3 +187,38 @@ pub fn get_submissions_user_by_contest_problem(
         .order_by(submission::submission_instant.desc())
         .load::<(Submission, ContestProblem)>(connection)
 }
+
+pub fn get_accepted_submissions_by_contest(
+    connection: &PgConnection,
+    contest_id: i32,
+) -> QueryResult<Vec<(Submission, ContestProblem)>> {
+    submission::table
+        .inner_join(contest_problems::table)
+        .filter(contest_problems::contest_id.eq(contest_id))
+        .filter(submission::verdict.eq("AC"))
+        .order_by(submission::submission_instant)
+        .load::<(Submission, ContestProblem)>(connection)
+}
+
+/// Clears a submission's verdict so it shows as waiting while it's judged again.
+pub fn reset_submission(connection: &PgConnection, uuid: &str) -> QueryResult<()> {
+    diesel::update(submission::table)
+        .filter(submission::uuid.eq(uuid))
+        .set((
+            submission::verdict.eq(None::<String>),
+            submission::judge_start_instant.eq(None::<NaiveDateTime>),
+            submission::judge_end_instant.eq(None::<NaiveDateTime>),
+        ))
+        .execute(connection)?;
+    Ok(())
+}
+
+pub fn get_submissions_by_contest_problem(
+    connection: &PgConnection,
+    contest_problem_id: i32,
+) -> QueryResult<Vec<Submission>> {
+    submission::table
+        .filter(submission::contest_problem_id.eq(contest_problem_id))
+        .order_by(submission::submission_instant)
+        .load(connection)
+}
@@ -138,3 +138,22 @@ pub fn insert_new_user(
 
     Ok(get_user_by_name(connection, new_user.name)?)
 }
+
+pub fn get_statement_language(connection: &PgConnection, id: i32) -> QueryResult<Option<String>> {
+    user::table
+        .filter(user::id.eq(id))
+        .select(user::statement_language)
+        .first(connection)
+}
+
+pub fn set_statement_language(
+    connection: &PgConnection,
+    id: i32,
+    statement_language: Option<&str>,
+) -> QueryResult<()> {
+    diesel::update(user::table)
+        .filter(user::id.eq(id))
+        .set(user::statement_language.eq(statement_language))
+        .execute(connection)?;
+    Ok(())
+}
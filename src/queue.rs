@@ -1,21 +1,327 @@
 use job_protocol::{GetJobRequest, JobResult, JobResultConfirmation, Job, Language};
+use job_protocol::{JobProgress, WorkerMessage, ServerMessage, Shutdown, worker_message, server_message};
+use job_protocol::{CancelJobRequest, CancelJobConfirmation, job, job_result};
+use job_protocol::{Feature, HandshakeRequest, HandshakeResponse};
 use job_protocol::job_queue_server::JobQueue;
-use tonic::{Request, Response, Status};
-use async_channel::Receiver;
-use tokio::sync::broadcast;
+use tonic::{Request, Response, Status, Streaming};
+use async_channel::{Receiver, Sender};
+use tokio::sync::{broadcast, oneshot};
 use dashmap::DashMap;
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
 use std::sync::Arc;
-use log::info;
+use std::time::{Duration, Instant};
+use chrono::prelude::*;
+use log::{info, warn};
+use uuid::Uuid;
 
+// Generated, a job travels in the same message as the small ones
+#[allow(clippy::large_enum_variant)]
 pub mod job_protocol {
     tonic::include_proto!("job_protocol");
 }
 
+/// Version 1 only had the unary GetJob and SubmitJobResult calls, version 2
+/// added StreamJobs and the handshake. Version 1 workers never shake hands,
+/// so the oldest a handshake can bring is 2.
+pub const PROTOCOL_VERSION: i32 = 2;
+pub const MINIMUM_PROTOCOL_VERSION: i32 = 2;
+pub const SUPPORTED_FEATURES: [Feature; 7] = [
+    Feature::Progress,
+    Feature::Cancellation,
+    Feature::RunProgram,
+    Feature::MultiFile,
+    Feature::OutputFiles,
+    Feature::PathArguments,
+    Feature::OutputValidators,
+];
+
+/// What a worker agreed on in its handshake, until its stream registers.
 #[derive(Debug)]
+pub struct Handshake {
+    pub protocol_version: i32,
+    pub features: Vec<i32>,
+    pub deadline: Instant,
+}
+
+/// Keyed by worker id.
+pub type Handshakes = Arc<DashMap<String, Handshake>>;
+
+// A worker streams right after shaking hands, one that doesn't never will
+const HANDSHAKE_DEADLINE: Duration = Duration::from_secs(60);
+
+pub fn feature_names(features: &[i32]) -> Vec<String> {
+    features
+        .iter()
+        .map(|feature| match Feature::from_i32(*feature) {
+            Some(feature) => format!("{:?}", feature),
+            None => format!("Unknown({})", feature),
+        })
+        .collect()
+}
+
+fn supports_required_features(features: &[i32], job: &Job) -> bool {
+    job.required_features.iter().all(|feature| features.contains(feature))
+}
+
+#[derive(Debug)]
+pub struct ParkedJob {
+    pub job: Job,
+    pub deadline: Instant,
+}
+
+/// Jobs no connected worker can run, keyed by uuid, held until one that can
+/// registers or they expire.
+pub type ParkedJobs = Arc<DashMap<String, ParkedJob>>;
+
+// Long enough for a capable worker to be restarted
+const PARKED_DEADLINE: Duration = Duration::from_secs(5 * 60);
+
+/// Puts a job a worker couldn't take back in the queue, or parks it if no
+/// connected worker supports what it requires, so it doesn't go around the
+/// queue forever.
+async fn requeue_or_park(job_sender: &Sender<Job>, workers: &Workers, parked_jobs: &ParkedJobs, job: Job) {
+    if workers.iter().any(|worker| supports_required_features(&worker.features, &job)) {
+        job_sender.send(job).await.expect("Failed to requeue job");
+    } else {
+        warn!(
+            "No connected worker supports the features job uuid={} requires, parking it",
+            job.uuid
+        );
+        parked_jobs.insert(job.uuid.clone(), ParkedJob {
+            job,
+            deadline: Instant::now() + PARKED_DEADLINE,
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WorkerState {
+    Active,
+    Paused,
+    Draining,
+}
+
+#[derive(Debug, Clone)]
+pub struct WorkerStatus {
+    pub id: String,
+    pub host: String,
+    pub languages: Vec<String>,
+    pub box_count: i32,
+    pub state: WorkerState,
+    pub current_job: Option<String>,
+    pub connected_instant: NaiveDateTime,
+    pub last_heartbeat_instant: NaiveDateTime,
+    pub jobs_done: i32,
+    pub failures: i32,
+    pub protocol_version: i32,
+    pub features: Vec<i32>,
+    pub compilations: i32,
+    pub compile_time_ms: i64,
+}
+
+pub type Workers = Arc<DashMap<String, WorkerStatus>>;
+
+pub fn set_worker_state(workers: &Workers, id: &str, state: WorkerState) -> bool {
+    match workers.get_mut(id) {
+        Some(mut worker) => {
+            worker.state = state;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Routes each job result to whoever is awaiting that job, falling back to
+/// the unbounded submission result channel for fire-and-forget judgements.
+#[derive(Debug, Clone)]
+pub struct JobResultRegistry {
+    awaited: Arc<DashMap<String, oneshot::Sender<JobResult>>>,
+    submission_result_sender: Sender<JobResult>,
+}
+
+impl JobResultRegistry {
+    pub fn new(submission_result_sender: Sender<JobResult>) -> Self {
+        JobResultRegistry {
+            awaited: Arc::new(DashMap::new()),
+            submission_result_sender,
+        }
+    }
+
+    pub fn await_result(&self, uuid: &str) -> oneshot::Receiver<JobResult> {
+        let (sender, receiver) = oneshot::channel();
+        self.awaited.insert(uuid.into(), sender);
+        receiver
+    }
+
+    pub fn forget(&self, uuid: &str) {
+        self.awaited.remove(uuid);
+    }
+
+    pub fn route(&self, job_result: JobResult) {
+        match self.awaited.remove(&job_result.uuid) {
+            Some((_, sender)) => {
+                // The waiting side may have given up already
+                sender.send(job_result).ok();
+            }
+            None => {
+                self.submission_result_sender
+                    .try_send(job_result)
+                    .expect("Submission result channel should be unbounded and open");
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RunningJob {
+    /// The id of the stream the job went down and its sender, unary workers
+    /// can't be told to stop
+    pub worker: Option<(Uuid, Sender<Result<ServerMessage, Status>>)>,
+    pub deadline: Instant,
+}
+
+pub type RunningJobs = Arc<DashMap<String, RunningJob>>;
+
+/// The latest job of each uuid queued again while an older one may still be
+/// in the queue, held until it's taken, so the older one is dropped instead.
+pub type SupersededJobs = Arc<DashMap<String, Job>>;
+
+/// Makes `job` the only one to run for its uuid, as when judging a submission
+/// again: a running one is stopped without a result and a parked or queued
+/// one is dropped. `job` should be queued right after.
+pub fn supersede_job(
+    running_jobs: &RunningJobs,
+    parked_jobs: &ParkedJobs,
+    superseded_jobs: &SupersededJobs,
+    job: &Job,
+) {
+    if let Some((_, running_job)) = running_jobs.remove(&job.uuid) {
+        info!("Stopping job uuid={} to run it again", job.uuid);
+        if let Some((_, worker)) = running_job.worker {
+            worker
+                .try_send(Ok(ServerMessage {
+                    which: Some(server_message::Which::Cancel(CancelJobRequest { uuid: job.uuid.clone() })),
+                }))
+                .ok();
+        }
+    }
+    parked_jobs.remove(&job.uuid);
+    superseded_jobs.insert(job.uuid.clone(), job.clone());
+}
+
+/// Whether a job taken from the queue was queued again since, forgetting the
+/// latest one once it's taken itself.
+fn is_superseded(superseded_jobs: &SupersededJobs, job: &Job) -> bool {
+    superseded_jobs.remove_if(&job.uuid, |_, latest| latest == job);
+    superseded_jobs.contains_key(&job.uuid)
+}
+
+// Same as the compilation limit used by the workers
+const COMPILE_DEADLINE: Duration = Duration::from_secs(25);
+// Slack for sandbox setup, checker and file copies on each run
+const RUN_OVERHEAD: Duration = Duration::from_secs(5);
+
+pub fn job_deadline(job: &Job) -> Duration {
+    let time_limit = Duration::from_millis(job.time_limit_ms.max(0) as u64);
+    match &job.which {
+        Some(job::Which::Judgement(judgement)) => {
+            // Each test runs the solution and then the checker with the same limit
+            COMPILE_DEADLINE * 2 + (time_limit * 2 + RUN_OVERHEAD) * judgement.test_count.max(1) as u32
+        }
+        Some(job::Which::RunCached(_)) | Some(job::Which::RunProgram(_)) => {
+            COMPILE_DEADLINE + time_limit + RUN_OVERHEAD
+        }
+        None => RUN_OVERHEAD,
+    }
+}
+
+/// Stops a job that was already handed to a worker, answering whoever waits
+/// for it with `code` instead of a result.
+pub fn cancel_job(
+    running_jobs: &RunningJobs,
+    job_results: &JobResultRegistry,
+    uuid: &str,
+    code: job_result::Code,
+) -> bool {
+    match running_jobs.remove(uuid) {
+        Some((_, running_job)) => {
+            if let Some((_, worker)) = running_job.worker {
+                worker
+                    .try_send(Ok(ServerMessage {
+                        which: Some(server_message::Which::Cancel(CancelJobRequest { uuid: uuid.into() })),
+                    }))
+                    .ok();
+            }
+            job_results.route(JobResult {
+                uuid: uuid.into(),
+                code: code.into(),
+                which: None,
+            });
+            true
+        }
+        None => false,
+    }
+}
+
+pub async fn watch_deadlines(
+    running_jobs: RunningJobs,
+    parked_jobs: ParkedJobs,
+    handshakes: Handshakes,
+    job_results: JobResultRegistry,
+) {
+    let mut tick = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        tick.tick().await;
+        let now = Instant::now();
+        let expired = running_jobs
+            .iter()
+            .filter(|kv| kv.value().deadline < now)
+            .map(|kv| kv.key().clone())
+            .collect::<Vec<_>>();
+        for uuid in expired {
+            warn!("Job uuid={} went past its deadline, cancelling", uuid);
+            cancel_job(&running_jobs, &job_results, &uuid, job_result::Code::TimedOut);
+        }
+
+        let expired = parked_jobs
+            .iter()
+            .filter(|kv| kv.value().deadline < now)
+            .map(|kv| kv.key().clone())
+            .collect::<Vec<_>>();
+        for uuid in expired {
+            if parked_jobs.remove(&uuid).is_some() {
+                warn!("No capable worker showed up for parked job uuid={}, failing it", uuid);
+                job_results.route(JobResult {
+                    uuid,
+                    code: job_result::Code::NoCapableWorker.into(),
+                    which: None,
+                });
+            }
+        }
+
+        handshakes.retain(|worker_id, handshake| {
+            if handshake.deadline < now {
+                warn!("Worker id={} shook hands but never streamed, forgetting it", worker_id);
+                return false;
+            }
+            true
+        });
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct JobQueuer {
+    pub job_sender: Sender<Job>,
     pub job_receiver: Receiver<Job>,
-    pub job_result_sender: broadcast::Sender<JobResult>,
+    pub job_results: JobResultRegistry,
+    pub job_progress_sender: broadcast::Sender<JobProgress>,
     pub languages: Arc<DashMap<String, Language>>,
+    pub running_jobs: RunningJobs,
+    pub workers: Workers,
+    pub handshakes: Handshakes,
+    pub parked_jobs: ParkedJobs,
+    pub superseded_jobs: SupersededJobs,
 }
 
 #[tonic::async_trait]
@@ -31,8 +337,57 @@ impl JobQueue for JobQueuer {
         }
 
         info!("Waiting for job to send");
-        let job = self.job_receiver.recv().await.expect("Failed to receive from job queue");
-        Ok(Response::new(job))
+        loop {
+            let job = self.job_receiver.recv().await.expect("Failed to receive from job queue");
+            if is_superseded(&self.superseded_jobs, &job) {
+                info!("Dropping superseded job uuid={}", job.uuid);
+                continue;
+            }
+            if job.required_features.is_empty() {
+                self.running_jobs.insert(job.uuid.clone(), RunningJob {
+                    worker: None,
+                    deadline: Instant::now() + job_deadline(&job),
+                });
+                return Ok(Response::new(job));
+            }
+            // Unary workers predate the handshake and support no features
+            requeue_or_park(&self.job_sender, &self.workers, &self.parked_jobs, job).await;
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    async fn handshake(
+        &self,
+        request: Request<HandshakeRequest>,
+    ) -> Result<Response<HandshakeResponse>, Status> {
+        let request = request.into_inner();
+        info!(
+            "Handshake from worker id={} protocol_version={}",
+            request.worker_id, request.protocol_version
+        );
+        if request.protocol_version < MINIMUM_PROTOCOL_VERSION {
+            return Err(Status::failed_precondition(format!(
+                "Protocol version {} is older than the minimum supported {}",
+                request.protocol_version, MINIMUM_PROTOCOL_VERSION
+            )));
+        }
+
+        let features = request
+            .features
+            .into_iter()
+            .filter(|feature| SUPPORTED_FEATURES.iter().any(|supported| i32::from(*supported) == *feature))
+            .collect::<Vec<_>>();
+        let protocol_version = request.protocol_version.min(PROTOCOL_VERSION);
+        self.handshakes.insert(request.worker_id, Handshake {
+            protocol_version,
+            features: features.clone(),
+            deadline: Instant::now() + HANDSHAKE_DEADLINE,
+        });
+
+        Ok(Response::new(HandshakeResponse {
+            protocol_version,
+            features,
+        }))
     }
 
     async fn submit_job_result(
@@ -41,7 +396,252 @@ impl JobQueue for JobQueuer {
     ) -> Result<Response<JobResultConfirmation>, Status> {
         let request = request.into_inner();
         println!("{:?}", request);
-        self.job_result_sender.send(request).expect("Failed to send to job result broadcast");
+        // A streaming worker may have the job now, if it was run again
+        if self
+            .running_jobs
+            .remove_if(&request.uuid, |_, running_job| running_job.worker.is_none())
+            .is_none()
+        {
+            info!("Dropping late result of job uuid={}", request.uuid);
+            return Ok(Response::new(JobResultConfirmation {}));
+        }
+        self.job_results.route(request);
         Ok(Response::new(JobResultConfirmation {}))
     }
+
+    async fn cancel_job(
+        &self,
+        request: Request<CancelJobRequest>,
+    ) -> Result<Response<CancelJobConfirmation>, Status> {
+        let request = request.into_inner();
+        info!("Cancelling job uuid={} by request", request.uuid);
+        Ok(Response::new(CancelJobConfirmation {
+            found: cancel_job(
+                &self.running_jobs,
+                &self.job_results,
+                &request.uuid,
+                job_result::Code::Cancelled,
+            ),
+        }))
+    }
+
+    type StreamJobsStream =
+        Pin<Box<dyn Stream<Item = Result<ServerMessage, Status>> + Send + Sync + 'static>>;
+
+    async fn stream_jobs(
+        &self,
+        request: Request<Streaming<WorkerMessage>>,
+    ) -> Result<Response<Self::StreamJobsStream>, Status> {
+        Ok(Response::new(Box::pin(self.serve_worker(request.into_inner()))))
+    }
+}
+
+impl JobQueuer {
+    /// Drives one connected worker, be it a gRPC stream or an in-process one,
+    /// returning the messages that should be sent back to it.
+    pub fn serve_worker<S>(&self, mut inbound: S) -> Receiver<Result<ServerMessage, Status>>
+    where
+        S: Stream<Item = Result<WorkerMessage, Status>> + Send + Unpin + 'static,
+    {
+        info!("Worker connected to job stream");
+        let (server_message_sender, server_message_receiver) = async_channel::unbounded();
+
+        let job_sender = self.job_sender.clone();
+        let job_receiver = self.job_receiver.clone();
+        let job_results = self.job_results.clone();
+        let job_progress_sender = self.job_progress_sender.clone();
+        let languages = self.languages.clone();
+        let running_jobs = self.running_jobs.clone();
+
+        let workers = self.workers.clone();
+        let handshakes = self.handshakes.clone();
+        let parked_jobs = self.parked_jobs.clone();
+        let superseded_jobs = self.superseded_jobs.clone();
+        let stream_id = Uuid::new_v4();
+        // Whether a running job went down this stream, and not another one
+        // that got the same uuid after it was run again
+        let is_ours = move |_: &String, running_job: &RunningJob| {
+            matches!(&running_job.worker, Some((id, _)) if *id == stream_id)
+        };
+
+        tokio::spawn(async move {
+            let mut worker_id: Option<String> = None;
+            let mut connected_instant = Local::now().naive_utc();
+            let mut waiting_for_job = false;
+            let mut shutdown_sent = false;
+            let mut current_job: Option<Job> = None;
+            let mut features: Vec<i32> = vec![];
+            let mut skipped_job = false;
+            let mut tick = tokio::time::interval(Duration::from_secs(1));
+
+            loop {
+                let state = worker_id
+                    .as_ref()
+                    .and_then(|id| workers.get(id).map(|worker| worker.state))
+                    .unwrap_or(WorkerState::Active);
+
+                tokio::select! {
+                    message = inbound.next() => {
+                        let message = match message {
+                            Some(Ok(message)) => message,
+                            _ => break,
+                        };
+
+                        if let Some(mut worker) = worker_id.as_ref().and_then(|id| workers.get_mut(id)) {
+                            worker.last_heartbeat_instant = Local::now().naive_utc();
+                        }
+
+                        match message.which {
+                            Some(worker_message::Which::Register(register)) => {
+                                info!("Registered worker id={} host={}", register.worker_id, register.host);
+                                connected_instant = Local::now().naive_utc();
+                                for language in register.supported_languages.iter() {
+                                    languages.insert(language.key.clone(), language.clone());
+                                }
+                                let (protocol_version, negotiated_features) = match handshakes.remove(&register.worker_id) {
+                                    Some((_, handshake)) => (handshake.protocol_version, handshake.features),
+                                    None => {
+                                        // Streaming came with the handshake, so nothing was negotiated
+                                        warn!("Refusing worker id={} that didn't shake hands", register.worker_id);
+                                        server_message_sender
+                                            .send(Err(Status::failed_precondition("Handshake before streaming jobs")))
+                                            .await
+                                            .ok();
+                                        break;
+                                    }
+                                };
+                                features = negotiated_features;
+                                workers.insert(register.worker_id.clone(), WorkerStatus {
+                                    id: register.worker_id.clone(),
+                                    host: register.host,
+                                    languages: register.supported_languages.into_iter().map(|l| l.key).collect(),
+                                    box_count: register.box_count,
+                                    state: WorkerState::Active,
+                                    current_job: None,
+                                    connected_instant,
+                                    last_heartbeat_instant: Local::now().naive_utc(),
+                                    jobs_done: 0,
+                                    failures: 0,
+                                    protocol_version,
+                                    features: features.clone(),
+                                    compilations: 0,
+                                    compile_time_ms: 0,
+                                });
+                                worker_id = Some(register.worker_id);
+
+                                let runnable = parked_jobs
+                                    .iter()
+                                    .filter(|kv| supports_required_features(&features, &kv.value().job))
+                                    .map(|kv| kv.key().clone())
+                                    .collect::<Vec<_>>();
+                                for uuid in runnable {
+                                    if let Some((_, parked_job)) = parked_jobs.remove(&uuid) {
+                                        info!("Unparking job uuid={}", uuid);
+                                        job_sender.send(parked_job.job).await.expect("Failed to requeue job");
+                                    }
+                                }
+                            }
+                            Some(worker_message::Which::GetJob(request)) => {
+                                for language in request.supported_languages {
+                                    languages.insert(language.key.clone(), language);
+                                }
+                                waiting_for_job = true;
+                            }
+                            Some(worker_message::Which::Progress(progress)) => {
+                                // Nobody may be listening, which is fine
+                                job_progress_sender.send(progress).ok();
+                            }
+                            Some(worker_message::Which::Result(result)) => {
+                                current_job = None;
+                                if let Some(mut worker) = worker_id.as_ref().and_then(|id| workers.get_mut(id)) {
+                                    worker.current_job = None;
+                                    if result.code == i32::from(job_result::Code::Ok) {
+                                        worker.jobs_done += 1;
+                                    } else if result.code != i32::from(job_result::Code::Cancelled) {
+                                        // Cancelling was asked of the worker, it didn't fail
+                                        worker.failures += 1;
+                                    }
+                                    if let Some(job_result::Which::Judgement(job_result::Judgement {
+                                        compilation: Some(compilation),
+                                        ..
+                                    })) = &result.which {
+                                        worker.compilations += 1;
+                                        worker.compile_time_ms += compilation.time_ms as i64;
+                                    }
+                                }
+                                if running_jobs.remove_if(&result.uuid, is_ours).is_none() {
+                                    // Already answered as cancelled or timed out, or run again
+                                    info!("Dropping late result of job uuid={}", result.uuid);
+                                    continue;
+                                }
+                                job_results.route(result);
+                            }
+                            Some(worker_message::Which::Heartbeat(_)) | None => {}
+                        }
+                    }
+                    job = job_receiver.recv(), if waiting_for_job && !skipped_job && state == WorkerState::Active => {
+                        let job = job.expect("Failed to receive from job queue");
+                        if is_superseded(&superseded_jobs, &job) {
+                            info!("Dropping superseded job uuid={}", job.uuid);
+                            continue;
+                        }
+                        if !supports_required_features(&features, &job) {
+                            // Leave it to another worker and back off until the next tick
+                            requeue_or_park(&job_sender, &workers, &parked_jobs, job).await;
+                            skipped_job = true;
+                            continue;
+                        }
+                        waiting_for_job = false;
+                        running_jobs.insert(job.uuid.clone(), RunningJob {
+                            worker: Some((stream_id, server_message_sender.clone())),
+                            deadline: Instant::now() + job_deadline(&job),
+                        });
+                        if server_message_sender
+                            .send(Ok(ServerMessage {
+                                which: Some(server_message::Which::Job(job.clone())),
+                            }))
+                            .await
+                            .is_err()
+                        {
+                            warn!("Worker went away before receiving job uuid={}", job.uuid);
+                            running_jobs.remove_if(&job.uuid, is_ours);
+                            job_sender.send(job).await.expect("Failed to requeue job");
+                            break;
+                        }
+                        if let Some(mut worker) = worker_id.as_ref().and_then(|id| workers.get_mut(id)) {
+                            worker.current_job = Some(job.uuid.clone());
+                        }
+                        current_job = Some(job);
+                    }
+                    _ = tick.tick() => {
+                        skipped_job = false;
+                        if state == WorkerState::Draining && current_job.is_none() && !shutdown_sent {
+                            info!("Worker drained, asking it to shut down");
+                            shutdown_sent = true;
+                            server_message_sender
+                                .send(Ok(ServerMessage {
+                                    which: Some(server_message::Which::Shutdown(Shutdown {})),
+                                }))
+                                .await
+                                .ok();
+                        }
+                    }
+                }
+            }
+
+            if let Some(job) = current_job {
+                if running_jobs.remove_if(&job.uuid, is_ours).is_some() {
+                    warn!("Worker disconnected while running job uuid={}, requeueing", job.uuid);
+                    job_sender.send(job).await.expect("Failed to requeue job");
+                }
+            }
+            if let Some(id) = worker_id {
+                // A reconnected worker with the same id may have replaced this entry already
+                workers.remove_if(&id, |_, worker| worker.connected_instant == connected_instant);
+            }
+            info!("Worker disconnected from job stream");
+        });
+
+        server_message_receiver
+    }
 }
@@ -6,6 +6,9 @@ table! {
         end_instant -> Nullable<Timestamp>,
         creation_user_id -> Int4,
         creation_instant -> Timestamp,
+        contest_testset -> Text,
+        system_testset -> Text,
+        system_tests_instant -> Nullable<Timestamp>,
     }
 }
 
@@ -18,6 +21,64 @@ table! {
     }
 }
 
+table! {
+    package_import (uuid) {
+        uuid -> Text,
+        status -> Text,
+        contest_id -> Nullable<Int4>,
+        creation_user_id -> Int4,
+        creation_instant -> Timestamp,
+        end_instant -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    package_import_problem (id) {
+        id -> Int4,
+        package_import_uuid -> Text,
+        name -> Text,
+        label -> Nullable<Text>,
+        problem_id -> Nullable<Text>,
+        status -> Text,
+        message -> Nullable<Text>,
+        contest_problem_id -> Nullable<Int4>,
+        previous_problem_id -> Nullable<Text>,
+    }
+}
+
+table! {
+    package_import_self_test (package_import_problem_id, program, test) {
+        package_import_problem_id -> Int4,
+        program -> Text,
+        test -> Int4,
+        expected -> Text,
+        verdict -> Nullable<Text>,
+        status -> Text,
+        message -> Nullable<Text>,
+    }
+}
+
+table! {
+    package_import_solution (package_import_problem_id, path) {
+        package_import_problem_id -> Int4,
+        path -> Text,
+        tag -> Text,
+        status -> Text,
+        verdict -> Nullable<Text>,
+        message -> Nullable<Text>,
+    }
+}
+
+table! {
+    package_import_test (package_import_problem_id, testset, test) {
+        package_import_problem_id -> Int4,
+        test -> Int4,
+        status -> Text,
+        message -> Nullable<Text>,
+        testset -> Text,
+    }
+}
+
 table! {
     problem (id) {
         id -> Text,
@@ -35,6 +96,43 @@ table! {
         status -> Text,
         creation_user_id -> Int4,
         creation_instant -> Timestamp,
+        grader_paths -> Array<Text>,
+        package_format -> Text,
+        checker_arguments -> Array<Text>,
+    }
+}
+
+table! {
+    problem_test (problem_id, testset, test) {
+        problem_id -> Text,
+        test -> Int4,
+        validation_status -> Text,
+        validation_message -> Nullable<Text>,
+        validation_instant -> Timestamp,
+        testset -> Text,
+        sample -> Bool,
+    }
+}
+
+table! {
+    problem_statement (problem_id, language, mime_type) {
+        problem_id -> Text,
+        language -> Text,
+        mime_type -> Text,
+        path -> Text,
+        charset -> Nullable<Text>,
+        mathjax -> Bool,
+    }
+}
+
+table! {
+    problem_testset (problem_id, name) {
+        problem_id -> Text,
+        name -> Text,
+        time_limit_ms -> Int4,
+        memory_limit_bytes -> Int4,
+        test_count -> Int4,
+        test_pattern -> Text,
     }
 }
 
@@ -53,6 +151,19 @@ table! {
         error_output -> Nullable<Text>,
         contest_problem_id -> Int4,
         user_id -> Int4,
+        compilation_exit_code -> Nullable<Int4>,
+        compilation_time_ms -> Nullable<Int4>,
+        compilation_memory_kib -> Nullable<Int4>,
+        compilation_stdout -> Nullable<Text>,
+        compilation_stderr -> Nullable<Text>,
+    }
+}
+
+table! {
+    submission_file (submission_uuid, name) {
+        submission_uuid -> Text,
+        name -> Text,
+        text -> Text,
     }
 }
 
@@ -64,20 +175,41 @@ table! {
         is_admin -> Bool,
         creation_user_id -> Nullable<Int4>,
         creation_instant -> Timestamp,
+        statement_language -> Nullable<Text>,
     }
 }
 
 joinable!(contest -> user (creation_user_id));
 joinable!(contest_problems -> contest (contest_id));
 joinable!(contest_problems -> problem (problem_id));
+joinable!(package_import -> contest (contest_id));
+joinable!(package_import -> user (creation_user_id));
+joinable!(package_import_problem -> contest_problems (contest_problem_id));
+joinable!(package_import_problem -> package_import (package_import_uuid));
+joinable!(package_import_self_test -> package_import_problem (package_import_problem_id));
+joinable!(package_import_solution -> package_import_problem (package_import_problem_id));
+joinable!(package_import_test -> package_import_problem (package_import_problem_id));
 joinable!(problem -> user (creation_user_id));
+joinable!(problem_test -> problem (problem_id));
+joinable!(problem_statement -> problem (problem_id));
+joinable!(problem_testset -> problem (problem_id));
 joinable!(submission -> contest_problems (contest_problem_id));
 joinable!(submission -> user (user_id));
+joinable!(submission_file -> submission (submission_uuid));
 
 allow_tables_to_appear_in_same_query!(
     contest,
     contest_problems,
+    package_import,
+    package_import_problem,
+    package_import_self_test,
+    package_import_solution,
+    package_import_test,
     problem,
+    problem_statement,
+    problem_test,
+    problem_testset,
     submission,
+    submission_file,
     user,
 );
@@ -1,7 +1,7 @@
 use chrono::prelude::*;
 use log::info;
 
-use crate::models::user;
+use crate::models::{package_import, problem, user};
 use crate::models::user::NewUser;
 use diesel::PgConnection;
 
@@ -36,3 +36,18 @@ pub fn setup_admin(connection: &PgConnection) {
         }
     }
 }
+
+/// Nothing survives a restart, so work left halfway would look like it's
+/// still going forever.
+pub fn reset_interrupted_work(connection: &PgConnection) {
+    let problems = problem::fail_interrupted_validations(connection)
+        .expect("Couldn't reset interrupted validations");
+    if problems > 0 {
+        info!("Marked {} problems with interrupted validations as invalid", problems);
+    }
+    let imports = package_import::fail_interrupted_imports(connection, Local::now().naive_utc())
+        .expect("Couldn't reset interrupted imports");
+    if imports > 0 {
+        info!("Marked {} interrupted package imports as failed", imports);
+    }
+}
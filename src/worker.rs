@@ -0,0 +1,1145 @@
+use crate::import_contest::format_width;
+use crate::queue::job_protocol::{GetJobRequest, JobResult, job_result, job, Language, Job};
+use crate::queue::job_protocol::{JobProgress, job_progress, WorkerMessage, worker_message, ServerMessage, server_message};
+use crate::queue::job_protocol::{RegisterWorker, Heartbeat, HandshakeRequest};
+use crate::queue::job_protocol::job_queue_server::JobQueue;
+use crate::queue::{JobQueuer, PROTOCOL_VERSION, SUPPORTED_FEATURES};
+use std::path::PathBuf;
+use which::which;
+use chrono::Local;
+
+use tonic::{Request, Status};
+use async_channel::Sender;
+use futures::{Stream, StreamExt};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub mod isolate;
+pub mod language;
+pub mod process;
+pub mod sandbox;
+
+use tokio::time::{sleep, interval, Duration};
+use isolate::{IsolateSandbox, new_isolate_box};
+use sandbox::{RunStatus, RunStats, CommandTuple, CompileParams, ExecuteParams, Sandbox};
+use std::fs::read_to_string;
+use std::fs;
+use log::info;
+use language::Compile;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::Write;
+
+pub fn get_isolate_executable_path() -> PathBuf {
+    which("isolate").expect("isolate binary not installed")
+}
+
+pub fn new_isolate_sandbox(box_id: i32) -> IsolateSandbox {
+    let isolate_executable_path = get_isolate_executable_path();
+    log::info!("Found isolate at {:?}", isolate_executable_path);
+    let isolate_box = new_isolate_box(&isolate_executable_path, box_id).expect("Couldn't create an isolate box");
+    log::info!("Created an isolate box at {:?}", isolate_box.path);
+    IsolateSandbox {
+        isolate_executable_path,
+        isolate_box,
+    }
+}
+
+/// Loads the languages this machine can compile, with their precompiled
+/// headers built under PCH_CACHE_PATH.
+pub fn load_supported_languages() -> Arc<HashMap<String, language::LanguageParams>> {
+    let mut supported_languages = language::get_supported_languages();
+    let cache_path = std::env::var("PCH_CACHE_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| std::env::temp_dir().join("jughisto-pch"));
+    language::prepare_precompiled_headers(&mut supported_languages, &cache_path);
+    log::info!("Loaded {} supported languages", supported_languages.len());
+    Arc::new(supported_languages)
+}
+
+/// Uses isolate unless JUDGE_SANDBOX=process asks for plain processes.
+pub fn new_sandbox(box_id: i32) -> Arc<dyn Sandbox> {
+    match std::env::var("JUDGE_SANDBOX").as_deref() {
+        Ok("process") => {
+            let path = std::env::temp_dir().join(format!("jughisto-box-{}", box_id));
+            let sandbox = process::new_process_sandbox(path, "/data/".into())
+                .expect("Couldn't create a process sandbox");
+            log::warn!("Judging without isolation at {:?}", sandbox.path);
+            Arc::new(sandbox)
+        }
+        _ => Arc::new(new_isolate_sandbox(box_id)),
+    }
+}
+
+pub struct JobReporter {
+    uuid: String,
+    sender: Sender<WorkerMessage>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl JobReporter {
+    pub fn new(uuid: String, sender: Sender<WorkerMessage>, cancelled: Arc<AtomicBool>) -> Self {
+        JobReporter {
+            uuid,
+            sender,
+            cancelled,
+        }
+    }
+
+    fn progress(&self, stage: job_progress::Stage, test: i32, test_count: i32) {
+        // Progress is best effort, a closed stream is handled by the job loop
+        self.sender.try_send(WorkerMessage {
+            which: Some(worker_message::Which::Progress(JobProgress {
+                uuid: self.uuid.clone(),
+                stage: stage.into(),
+                test,
+                test_count,
+            })),
+        }).ok();
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    fn cancelled_result(&self) -> JobResult {
+        JobResult {
+            uuid: self.uuid.clone(),
+            code: job_result::Code::Cancelled.into(),
+            which: None,
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_cached(
+    sandbox: &dyn Sandbox,
+    supported_languages: &HashMap<String, language::LanguageParams>,
+    uuid: String,
+    language: String,
+    time_limit_ms: i32,
+    memory_limit_kib: i32,
+    request: job::RunCached,
+    reporter: &JobReporter,
+) -> JobResult {
+    info!("Starting to run");
+    let root_data = sandbox.data_path();
+
+    let language = supported_languages.get(&language);
+    if language.is_none() {
+        return JobResult {
+            uuid,
+            code: job_result::Code::InvalidLanguage.into(),
+            which: None,
+        };
+    }
+    let language = language.unwrap();
+
+    let path_with_suffix = root_data.join(&request.source_path);
+    let path_without_suffix = path_with_suffix.with_extension("");
+
+    if let Compile::Command(_, command, output) = &language.compile {
+        let output_path =
+            output.replace("{.}", path_with_suffix.to_str().unwrap())
+                .replace("{}", path_without_suffix.to_str().unwrap());
+        if !root_data.join(&output_path).exists() {
+            fs_extra::dir::copy(
+                path_with_suffix.parent().unwrap(),
+                sandbox.path(),
+                &fs_extra::dir::CopyOptions {
+                    overwrite: false,
+                    skip_exist: false,
+                    buffer_size: 64000, //64kb
+                    copy_inside: true,
+                    content_only: true,
+                    depth: 0,
+                }
+            ).unwrap();
+
+            let command = CommandTuple {
+                binary_path: command.binary_path.clone(),
+                args: command
+                    .args
+                    .iter()
+                    .map(|c|
+                        c.replace("{.}", path_with_suffix.file_name().unwrap().to_str().unwrap())
+                            .replace("{}", path_without_suffix.file_name().unwrap().to_str().unwrap()))
+                    .collect(),
+            };
+
+            info!("Compiling: {:#?}", command);
+            reporter.progress(job_progress::Stage::Compiling, 0, 0);
+
+            let compile_stats = sandbox.compile(
+                CompileParams {
+                    uuid: &uuid,
+                    // 1GiB
+                    memory_limit_kib: 1_024 * 1_024,
+                    // 25 seconds
+                    time_limit_ms: 25_000,
+                    command: &command,
+                    mounts: &language.compile_mounts,
+                },
+            ).unwrap();
+
+            if match compile_stats {
+                RunStats {
+                    exit_code: Some(c), ..
+                } => c != 0,
+                RunStats {
+                    exit_code: None, ..
+                } => true,
+            } {
+                fs_extra::dir::create(sandbox.path(), true).unwrap();
+                return JobResult {
+                    uuid,
+                    code: job_result::Code::Ok.into(),
+                    which: Some(job_result::Which::RunCached(job_result::RunCached {
+                        result: job_result::run_cached::Result::CompilationError.into(),
+                        exit_code: compile_stats.exit_code.unwrap_or(42),
+                        exit_signal: compile_stats.exit_signal,
+                        memory_kib: compile_stats.memory_kib.unwrap(),
+                        time_ms: compile_stats.time_ms.unwrap(),
+                        time_wall_ms: compile_stats.time_wall_ms.unwrap(),
+                        error_output: read_to_string(compile_stats.stderr_path).unwrap_or("".into()),
+                    }))
+                }
+            }
+
+            fs::copy(sandbox.path().join(
+                output.replace("{.}", path_with_suffix.file_name().unwrap().to_str().unwrap())
+                .replace("{}", path_without_suffix.file_name().unwrap().to_str().unwrap())), &output_path).unwrap();
+
+            fs_extra::dir::create(sandbox.path(), true).unwrap();
+        }
+    }
+
+    let inside_path_with_suffix = PathBuf::from(format!("/data-{}/", uuid)).join(&request.source_path);
+    let inside_path_without_suffix = inside_path_with_suffix.with_extension("");
+
+    let command = CommandTuple {
+        binary_path: language.run.binary_path.to_str().unwrap()
+            .replace("{.}", inside_path_with_suffix.to_str().unwrap())
+            .replace("{}", inside_path_without_suffix.to_str().unwrap()).into(),
+        args: language.run
+            .args
+            .iter()
+            .map(|c|
+            c.replace("{.}", inside_path_with_suffix.to_str().unwrap())
+                .replace("{}", inside_path_without_suffix.to_str().unwrap()))
+            .chain(request.arguments)
+            // Files under /data, as the program sees them
+            .chain(request.path_arguments.iter().map(|path|
+                PathBuf::from(format!("/data-{}/", uuid)).join(path).to_str().unwrap().into()))
+            .collect(),
+    };
+
+    if reporter.is_cancelled() {
+        return reporter.cancelled_result();
+    }
+
+    info!("Executing");
+    reporter.progress(job_progress::Stage::Running, 1, 1);
+    let run_stats = sandbox.execute(
+        &command,
+        &ExecuteParams {
+            uuid: &uuid,
+            memory_limit_kib,
+            time_limit_ms,
+            stdin_path: request.stdin_path,
+            process_limit: language.process_limit,
+        },
+    ).unwrap();
+
+    if let Some(stdout_path) = request.stdout_path {
+        fs::copy(&run_stats.stdout_path, root_data.join(stdout_path)).unwrap();
+    }
+
+    let mut error_output = read_to_string(&run_stats.stderr_path).unwrap_or("".into());
+
+    // Files the program wrote to its working directory, as multi-test generators do
+    let mut missing_output = false;
+    for output_file in request.output_files {
+        let name = PathBuf::from(&output_file.name);
+        let box_path = sandbox.path().join(name.file_name().unwrap_or_default());
+        if let Err(e) = fs::copy(&box_path, root_data.join(&output_file.path)) {
+            error_output.push_str(&format!("\nCouldn't read output file {}: {}", output_file.name, e));
+            missing_output = true;
+        }
+    }
+
+    fs_extra::dir::create(sandbox.path(), true).unwrap();
+
+    JobResult {
+        uuid,
+        code: job_result::Code::Ok.into(),
+        which: Some(job_result::Which::RunCached(job_result::RunCached {
+            result: match run_stats.status {
+                RunStatus::Ok if missing_output =>
+                    job_result::run_cached::Result::RuntimeError.into(),
+                RunStatus::Ok =>
+                    job_result::run_cached::Result::Ok.into(),
+                RunStatus::RuntimeError =>
+                    job_result::run_cached::Result::RuntimeError.into(),
+                RunStatus::TimeLimitExceeded =>
+                    job_result::run_cached::Result::TimeLimitExceeded.into(),
+                RunStatus::MemoryLimitExceeded =>
+                    job_result::run_cached::Result::MemoryLimitExceeded.into(),
+            },
+            exit_code: run_stats.exit_code.unwrap(),
+            exit_signal: run_stats.exit_signal,
+            memory_kib: run_stats.memory_kib.unwrap(),
+            time_ms: run_stats.time_ms.unwrap(),
+            time_wall_ms: run_stats.time_wall_ms.unwrap(),
+            error_output,
+        })),
+    }
+}
+
+// Contestants see the output in the page, so keep it small
+const RUN_PROGRAM_OUTPUT_LIMIT: usize = 64 * 1_024;
+
+fn read_truncated(path: &PathBuf) -> String {
+    let mut text = read_to_string(path).unwrap_or("".into());
+    if text.len() > RUN_PROGRAM_OUTPUT_LIMIT {
+        let mut end = RUN_PROGRAM_OUTPUT_LIMIT;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.push_str("\n[...]");
+    }
+    text
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_program(
+    sandbox: &dyn Sandbox,
+    supported_languages: &HashMap<String, language::LanguageParams>,
+    uuid: String,
+    language: String,
+    time_limit_ms: i32,
+    memory_limit_kib: i32,
+    request: job::RunProgram,
+    reporter: &JobReporter,
+) -> JobResult {
+    let language = supported_languages.get(&language);
+    if language.is_none() {
+        return JobResult {
+            uuid,
+            code: job_result::Code::InvalidLanguage.into(),
+            which: None,
+        };
+    }
+    let language = language.unwrap();
+
+    if let Compile::Command(transform, command, _) = &language.compile {
+        reporter.progress(job_progress::Stage::Compiling, 0, 1);
+        let mut file = File::create(sandbox.path().join(format!("x{}", language.suffix))).unwrap();
+        file.write_all(transform(request.source_text, "x".into()).as_bytes()).unwrap();
+        file.sync_data().unwrap();
+
+        let command = CommandTuple {
+            binary_path: command.binary_path.clone(),
+            args: command
+                .args
+                .iter()
+                .map(|c|
+                    c.replace("{.}", &format!("x{}", language.suffix))
+                        .replace("{}", "x"))
+                .collect(),
+        };
+
+        let compile_stats = sandbox.compile(
+            CompileParams {
+                uuid: &uuid,
+                // 1GiB
+                memory_limit_kib: 1_024 * 1_024,
+                // 25 seconds
+                time_limit_ms: 25_000,
+                command: &command,
+                mounts: &language.compile_mounts,
+            },
+        )
+        .expect("Crashed while compiling");
+
+        info!("Compile finished: {:#?}", compile_stats);
+
+        if match compile_stats {
+            RunStats {
+                exit_code: Some(c), ..
+            } => c != 0,
+            RunStats {
+                exit_code: None, ..
+            } => true,
+        } {
+            let stdout_text = read_truncated(&compile_stats.stdout_path);
+            let stderr_text = read_truncated(&compile_stats.stderr_path);
+            fs_extra::dir::create(sandbox.path(), true).unwrap();
+            return JobResult {
+                uuid,
+                code: job_result::Code::Ok.into(),
+                which: Some(job_result::Which::RunProgram(job_result::RunProgram {
+                    result: job_result::run_program::Result::CompilationError.into(),
+                    exit_code: compile_stats.exit_code.unwrap_or(42),
+                    exit_signal: compile_stats.exit_signal,
+                    memory_kib: compile_stats.memory_kib.unwrap_or(0),
+                    time_ms: compile_stats.time_ms.unwrap_or(0),
+                    time_wall_ms: compile_stats.time_wall_ms.unwrap_or(0),
+                    stdout_text,
+                    stderr_text,
+                }))
+            }
+        }
+    } else {
+        let mut file = File::create(sandbox.path().join(format!("x{}", language.suffix))).unwrap();
+        file.write_all(request.source_text.as_bytes()).unwrap();
+        file.sync_data().unwrap();
+    }
+
+    let mut file = File::create(sandbox.path().join("input")).unwrap();
+    file.write_all(request.stdin_text.as_bytes()).unwrap();
+    file.sync_data().unwrap();
+
+    let command = CommandTuple {
+        binary_path: language.run.binary_path.to_str().unwrap()
+            .replace("{.}", &format!("x{}", language.suffix))
+            .replace("{}", "x").into(),
+        args: language.run
+            .args
+            .iter()
+            .map(|c|
+            c.replace("{.}", &format!("x{}", language.suffix))
+                .replace("{}", "x"))
+            .collect(),
+    };
+
+    if reporter.is_cancelled() {
+        fs_extra::dir::create(sandbox.path(), true).unwrap();
+        return reporter.cancelled_result();
+    }
+
+    info!("Executing");
+    reporter.progress(job_progress::Stage::Running, 1, 1);
+    let run_stats = sandbox.execute(
+        &command,
+        &ExecuteParams {
+            uuid: &uuid,
+            memory_limit_kib,
+            time_limit_ms,
+            // Absolute, so it is taken from the box instead of the data directory
+            stdin_path: Some("/box/input".into()),
+            process_limit: language.process_limit,
+        },
+    ).unwrap();
+
+    let stdout_text = read_truncated(&run_stats.stdout_path);
+    let stderr_text = read_truncated(&run_stats.stderr_path);
+
+    fs_extra::dir::create(sandbox.path(), true).unwrap();
+
+    JobResult {
+        uuid,
+        code: job_result::Code::Ok.into(),
+        which: Some(job_result::Which::RunProgram(job_result::RunProgram {
+            result: match run_stats.status {
+                RunStatus::Ok =>
+                    job_result::run_program::Result::Ok.into(),
+                RunStatus::RuntimeError =>
+                    job_result::run_program::Result::RuntimeError.into(),
+                RunStatus::TimeLimitExceeded =>
+                    job_result::run_program::Result::TimeLimitExceeded.into(),
+                RunStatus::MemoryLimitExceeded =>
+                    job_result::run_program::Result::MemoryLimitExceeded.into(),
+            },
+            exit_code: run_stats.exit_code.unwrap_or(42),
+            exit_signal: run_stats.exit_signal,
+            memory_kib: run_stats.memory_kib.unwrap_or(0),
+            time_ms: run_stats.time_ms.unwrap_or(0),
+            time_wall_ms: run_stats.time_wall_ms.unwrap_or(0),
+            stdout_text,
+            stderr_text,
+        })),
+    }
+}
+
+/// Writes the submitted files and the problem's graders into the box, by
+/// their base names so nothing lands outside of it.
+fn write_source_files(
+    sandbox: &dyn Sandbox,
+    language: &language::LanguageParams,
+    request: &job::Judgement,
+) {
+    if request.source_files.is_empty() {
+        fs::write(sandbox.path().join(format!("x{}", language.suffix)), &request.source_text).unwrap();
+    }
+    for source_file in &request.source_files {
+        if let Some(name) = PathBuf::from(&source_file.name).file_name() {
+            fs::write(sandbox.path().join(name), &source_file.text).unwrap();
+        }
+    }
+    for grader_path in &request.grader_paths {
+        let grader_path = sandbox.data_path().join(grader_path);
+        if let Some(name) = grader_path.file_name() {
+            fs::copy(&grader_path, sandbox.path().join(name)).unwrap();
+        }
+    }
+}
+
+fn expand_build_command(
+    sandbox: &dyn Sandbox,
+    language: &language::LanguageParams,
+    build: &CommandTuple,
+) -> CommandTuple {
+    let mut sources: Vec<String> = fs::read_dir(sandbox.path())
+        .unwrap()
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name.ends_with(&language.suffix))
+        .collect();
+    sources.sort();
+
+    CommandTuple {
+        binary_path: build.binary_path.clone(),
+        args: build
+            .args
+            .iter()
+            .flat_map(|c| match c.as_str() {
+                "{sources}" => sources.clone(),
+                _ => vec![c.replace("{}", "x")],
+            })
+            .collect(),
+    }
+}
+
+fn compilation_error(
+    uuid: String,
+    judge_start_instant: chrono::NaiveDateTime,
+    compilation: job_result::Compilation,
+) -> JobResult {
+    let judge_end_instant = Local::now().naive_utc();
+    JobResult {
+        uuid,
+        code: job_result::Code::Ok.into(),
+        which: Some(job_result::Which::Judgement(job_result::Judgement {
+            verdict: job_result::judgement::Verdict::CompilationError.into(),
+            failed_test: 0,
+            exit_signal: None,
+            memory_kib: 0,
+            exit_code: 0,
+            time_ms: 0,
+            time_wall_ms: 0,
+            error_output: "".into(),
+            judge_start_instant: judge_start_instant.format("%Y-%m-%dT%H:%M:%S%.f").to_string(),
+            judge_end_instant: judge_end_instant.format("%Y-%m-%dT%H:%M:%S%.f").to_string(),
+            compilation: Some(compilation),
+        }))
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn judge(
+    sandbox: &dyn Sandbox,
+    supported_languages: &HashMap<String, language::LanguageParams>,
+    uuid: String,
+    language: String,
+    time_limit_ms: i32,
+    memory_limit_kib: i32,
+    request: job::Judgement,
+    reporter: &JobReporter,
+) -> JobResult {
+    let root_data = sandbox.data_path();
+
+    let language = supported_languages.get(&language);
+    if language.is_none() {
+        return JobResult {
+            uuid,
+            code: job_result::Code::InvalidLanguage.into(),
+            which: None,
+        };
+    }
+    let language = language.unwrap();
+
+    let checker_language = supported_languages.get(&request.checker_language);
+    if checker_language.is_none() {
+        return JobResult {
+            uuid,
+            code: job_result::Code::InvalidLanguage.into(),
+            which: None,
+        };
+    }
+    let checker_language = checker_language.unwrap();
+    let checker_protocol = request.checker_protocol();
+
+    let path_with_suffix = root_data.join(&request.checker_source_path);
+    let path_without_suffix = path_with_suffix.with_extension("");
+
+    if let Compile::Command(_, command, output) = &checker_language.compile {
+        let output_path =
+            output.replace("{.}", path_with_suffix.to_str().unwrap())
+                .replace("{}", path_without_suffix.to_str().unwrap());
+        if !root_data.join(&output_path).exists() {
+            fs_extra::dir::copy(
+                path_with_suffix.parent().unwrap(),
+                sandbox.path(),
+                &fs_extra::dir::CopyOptions {
+                    overwrite: false,
+                    skip_exist: false,
+                    buffer_size: 64000, //64kb
+                    copy_inside: true,
+                    content_only: true,
+                    depth: 0,
+                }
+            ).unwrap();
+
+            let command = CommandTuple {
+                binary_path: command.binary_path.clone(),
+                args: command
+                    .args
+                    .iter()
+                    .map(|c|
+                        c.replace("{.}", path_with_suffix.file_name().unwrap().to_str().unwrap())
+                            .replace("{}", path_without_suffix.file_name().unwrap().to_str().unwrap()))
+                    .collect(),
+            };
+
+            info!("Compiling: {:#?}", command);
+
+            let compile_stats = sandbox.compile(
+                CompileParams {
+                    uuid: &uuid,
+                    // 1GiB
+                    memory_limit_kib: 1_024 * 1_024,
+                    // 25 seconds
+                    time_limit_ms: 25_000,
+                    command: &command,
+                    mounts: &checker_language.compile_mounts,
+                },
+            ).unwrap();
+
+            if match compile_stats {
+                RunStats {
+                    exit_code: Some(c), ..
+                } => c != 0,
+                RunStats {
+                    exit_code: None, ..
+                } => true,
+            } {
+                // A broken checker is the problem's fault, not the contestant's
+                log::error!(
+                    "Checker {:?} failed to compile: {}",
+                    request.checker_source_path,
+                    read_to_string(compile_stats.stderr_path).unwrap_or("".into())
+                );
+                fs_extra::dir::create(sandbox.path(), true).unwrap();
+                return JobResult {
+                    uuid,
+                    code: job_result::Code::FailedToExecute.into(),
+                    which: None,
+                }
+            }
+
+            fs::copy(sandbox.path().join(
+                output.replace("{.}", path_with_suffix.file_name().unwrap().to_str().unwrap())
+                .replace("{}", path_without_suffix.file_name().unwrap().to_str().unwrap())), &output_path).unwrap();
+
+            fs_extra::dir::create(sandbox.path(), true).unwrap();
+        }
+    }
+
+    let judge_start_instant = Local::now().naive_utc();
+
+    let multi_file = !request.source_files.is_empty() || !request.grader_paths.is_empty();
+    let compile_command = if multi_file {
+        write_source_files(sandbox, language, &request);
+        match &language.build {
+            Some(build) => Some(expand_build_command(sandbox, language, build)),
+            None => {
+                fs_extra::dir::create(sandbox.path(), true).unwrap();
+                return compilation_error(uuid, judge_start_instant, job_result::Compilation {
+                    exit_code: 1,
+                    exit_signal: None,
+                    time_ms: 0,
+                    memory_kib: 0,
+                    stdout_text: "".into(),
+                    stderr_text: format!("{} doesn't support multiple files", language.name),
+                });
+            }
+        }
+    } else if let Compile::Command(transform, command, _) = &language.compile {
+        let mut file = File::create(sandbox.path().join(format!("x{}", language.suffix))).unwrap();
+        file.write_all(transform(request.source_text, "x".into()).as_bytes()).unwrap();
+        file.sync_data().unwrap();
+
+        Some(CommandTuple {
+            binary_path: command.binary_path.clone(),
+            args: command
+                .args
+                .iter()
+                .map(|c|
+                    c.replace("{.}", &format!("x{}", language.suffix))
+                        .replace("{}", "x"))
+                .collect(),
+        })
+    } else {
+        let mut file = File::create(sandbox.path().join(format!("x{}", language.suffix))).unwrap();
+        file.write_all(request.source_text.as_bytes()).unwrap();
+        file.sync_data().unwrap();
+        None
+    };
+
+    let compilation = if let Some(command) = compile_command {
+        reporter.progress(job_progress::Stage::Compiling, 0, request.test_count);
+        info!("Compiling: {:#?}", command);
+
+        let compile_stats = sandbox.compile(
+            CompileParams {
+                uuid: &uuid,
+                // 1GiB
+                memory_limit_kib: 1_024 * 1_024,
+                // 25 seconds
+                time_limit_ms: 25_000,
+                command: &command,
+                mounts: &language.compile_mounts,
+            },
+        )
+        .expect("Crashed while compiling");
+
+        info!("Compile finished: {:#?}", compile_stats);
+
+        let compilation = job_result::Compilation {
+            exit_code: compile_stats.exit_code.unwrap_or(42),
+            exit_signal: compile_stats.exit_signal,
+            time_ms: compile_stats.time_ms.unwrap_or(0),
+            memory_kib: compile_stats.memory_kib.unwrap_or(0),
+            stdout_text: read_truncated(&compile_stats.stdout_path),
+            stderr_text: read_truncated(&compile_stats.stderr_path),
+        };
+
+        if match compile_stats {
+            RunStats {
+                exit_code: Some(c), ..
+            } => c != 0,
+            RunStats {
+                exit_code: None, ..
+            } => true,
+        } {
+            fs_extra::dir::create(sandbox.path(), true).unwrap();
+            return compilation_error(uuid, judge_start_instant, compilation);
+        }
+        Some(compilation)
+    } else {
+        None
+    };
+
+    let mut last_execute_stats: Option<RunStats> = None;
+
+    let command = CommandTuple {
+        binary_path: language.run.binary_path.to_str().unwrap()
+            .replace("{.}", &format!("x{}", language.suffix))
+            .replace("{}", "x").into(),
+        args: language.run
+            .args
+            .iter()
+            .map(|c|
+            c.replace("{.}", &format!("x{}", language.suffix))
+                .replace("{}", "x"))
+            .collect(),
+    };
+
+    let mut error_output: Option<String> = None;
+    let mut failed_test: i32 = 0;
+
+    for i in 1..request.test_count + 1 {
+        if reporter.is_cancelled() {
+            fs_extra::dir::create(sandbox.path(), true).unwrap();
+            return reporter.cancelled_result();
+        }
+        reporter.progress(job_progress::Stage::Running, i, request.test_count);
+
+        let stdin_path =
+            format_width(&request.test_pattern, i.try_into().unwrap());
+        let answer_path = format!("{}.a", stdin_path);
+        info!(
+            "Starting run {}/{} with test {:?}",
+            i, request.test_count, stdin_path
+        );
+        let execute_stats = sandbox.execute(
+            &command,
+            &ExecuteParams {
+                process_limit: language.process_limit,
+                uuid: &uuid,
+                memory_limit_kib,
+                time_limit_ms,
+                stdin_path: Some(stdin_path.clone()),
+            },
+        )
+        .expect("Crashed while running");
+        info!("Run finished: {:#?}", execute_stats);
+
+        if match execute_stats {
+            RunStats {
+                exit_code: Some(c), ..
+            } => c != 0,
+            RunStats {
+                exit_code: None, ..
+            } => true,
+        } {
+            error_output = Some(read_to_string(&execute_stats.stderr_path).unwrap_or("".into()));
+            failed_test = i;
+            last_execute_stats = Some(execute_stats);
+            break;
+        }
+
+        fs::copy(&execute_stats.stdout_path, sandbox.path().join("stdin")).expect("Copy");
+
+        let data_path = |path: &str| -> String {
+            PathBuf::from(format!("/data-{}/", &uuid))
+                .join(path)
+                .to_str()
+                .expect("Should work")
+                .into()
+        };
+        let kattis = checker_protocol == job::judgement::CheckerProtocol::Kattis;
+
+        // TODO: Support non-compile based languages
+        let binary_path = PathBuf::from(data_path(&request.checker_source_path)).with_extension("");
+        let (command, checker_stdin_path) = if kattis {
+            // Kattis output validators read the output from stdin and may
+            // explain their verdict in the feedback directory
+            fs_extra::dir::create(sandbox.path().join("feedback"), true).unwrap();
+            (
+                CommandTuple {
+                    binary_path,
+                    args: vec![data_path(&stdin_path), data_path(&answer_path), "/box/feedback/".into()]
+                        .into_iter()
+                        .chain(request.checker_arguments.iter().cloned())
+                        .collect(),
+                },
+                Some("/box/stdin".into()),
+            )
+        } else {
+            (
+                CommandTuple {
+                    binary_path,
+                    args: vec![data_path(&stdin_path), "/box/stdin".into(), data_path(&answer_path)],
+                },
+                None,
+            )
+        };
+
+        info!("Executing checker: {:?}", command);
+        let checker_stats = match sandbox.execute(
+            &command,
+            &ExecuteParams {
+                uuid: &uuid,
+                memory_limit_kib,
+                time_limit_ms,
+                stdin_path: checker_stdin_path,
+                process_limit: 1,
+            },
+        ) {
+            Ok(checker_stats) => checker_stats,
+            Err(e) => {
+                log::error!("Couldn't run checker {:?}: {}", request.checker_source_path, e);
+                fs_extra::dir::create(sandbox.path(), true).unwrap();
+                return JobResult {
+                    uuid,
+                    code: job_result::Code::FailedToExecute.into(),
+                    which: None,
+                };
+            }
+        };
+        // Kattis accepts with 42 and rejects with 43, testlib accepts with 0
+        // and rejects with 1 or, for presentation errors, 2
+        let (accepted_exit_code, rejected_exit_codes): (i32, &[i32]) =
+            if kattis { (42, &[43]) } else { (0, &[1, 2]) };
+        if checker_stats.exit_code != Some(accepted_exit_code) {
+            if !matches!(checker_stats.exit_code, Some(c) if rejected_exit_codes.contains(&c)) {
+                // A checker that crashes or gives up is the problem's fault
+                log::error!(
+                    "Checker {:?} failed on test {} with exit code {:?}: {}",
+                    request.checker_source_path,
+                    i,
+                    checker_stats.exit_code,
+                    read_to_string(&checker_stats.stderr_path).unwrap_or("".into())
+                );
+                fs_extra::dir::create(sandbox.path(), true).unwrap();
+                return JobResult {
+                    uuid,
+                    code: job_result::Code::FailedToExecute.into(),
+                    which: None,
+                };
+            }
+            let feedback = if kattis {
+                read_to_string(sandbox.path().join("feedback/judgemessage.txt")).unwrap_or("".into())
+            } else {
+                "".into()
+            };
+            error_output = Some(if feedback.is_empty() {
+                read_to_string(checker_stats.stderr_path).unwrap_or("".into())
+            } else {
+                feedback
+            });
+            failed_test = i;
+            last_execute_stats = Some(execute_stats);
+            break;
+        }
+
+        last_execute_stats = Some(execute_stats);
+    }
+
+    let judge_end_instant = Local::now().naive_utc();
+
+    let last_execute_stats = last_execute_stats.unwrap();
+
+    JobResult {
+        uuid,
+        code: job_result::Code::Ok.into(),
+        which: Some(job_result::Which::Judgement(
+            job_result::Judgement {
+                verdict: match last_execute_stats.status {
+                    RunStatus::Ok => match failed_test {
+                        0 => job_result::judgement::Verdict::Accepted.into(),
+                        _ => job_result::judgement::Verdict::WrongAnswer.into(),
+                    },
+                    RunStatus::TimeLimitExceeded => job_result::judgement::Verdict::TimeLimitExceeded.into(),
+                    RunStatus::MemoryLimitExceeded => job_result::judgement::Verdict::MemoryLimitExceeded.into(),
+                    RunStatus::RuntimeError => job_result::judgement::Verdict::RuntimeError.into(),
+                },
+                failed_test,
+                exit_signal: last_execute_stats.exit_signal,
+                memory_kib: last_execute_stats.memory_kib.unwrap(),
+                exit_code: last_execute_stats.exit_code.unwrap_or(42),
+                time_ms: last_execute_stats.time_ms.unwrap(),
+                time_wall_ms: last_execute_stats.time_wall_ms.unwrap(),
+                error_output: error_output.unwrap_or("".into()),
+                judge_start_instant: judge_start_instant.format("%Y-%m-%dT%H:%M:%S%.f").to_string(),
+                judge_end_instant: judge_end_instant.format("%Y-%m-%dT%H:%M:%S%.f").to_string(),
+                compilation,
+            }
+            ))
+    }
+}
+
+pub fn get_languages(supported_languages: &HashMap<String, language::LanguageParams>) -> Vec<Language> {
+    supported_languages.iter().map(|(key, language)| Language {
+        key: key.clone(),
+        name: language.name.clone(),
+        order: language.order,
+        multi_file: language.build.is_some(),
+    }).collect()
+}
+
+fn get_job_message(supported_languages: &HashMap<String, language::LanguageParams>) -> WorkerMessage {
+    WorkerMessage {
+        which: Some(worker_message::Which::GetJob(GetJobRequest {
+            supported_languages: get_languages(supported_languages),
+        })),
+    }
+}
+
+pub fn get_host() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| read_to_string("/etc/hostname").ok().map(|h| h.trim().into()))
+        .unwrap_or("unknown".into())
+}
+
+pub struct WorkerIdentity {
+    pub id: String,
+    pub host: String,
+    pub box_count: i32,
+}
+
+pub enum JobLoopEnd {
+    Disconnected,
+    Shutdown,
+}
+
+pub fn run_job(
+    sandbox: &dyn Sandbox,
+    supported_languages: &HashMap<String, language::LanguageParams>,
+    job: Job,
+    reporter: &JobReporter,
+) -> Option<JobResult> {
+    match job.which {
+        Some(job::Which::Judgement(judgement_request)) => Some(judge(
+            sandbox,
+            supported_languages,
+            job.uuid,
+            job.language,
+            job.time_limit_ms,
+            job.memory_limit_kib,
+            judgement_request,
+            reporter,
+        )),
+        Some(job::Which::RunCached(run_request)) => Some(run_cached(
+            sandbox,
+            supported_languages,
+            job.uuid,
+            job.language,
+            job.time_limit_ms,
+            job.memory_limit_kib,
+            run_request,
+            reporter,
+        )),
+        Some(job::Which::RunProgram(run_request)) => Some(run_program(
+            sandbox,
+            supported_languages,
+            job.uuid,
+            job.language,
+            job.time_limit_ms,
+            job.memory_limit_kib,
+            run_request,
+            reporter,
+        )),
+        None => {
+            log::info!("Empty job!");
+            None
+        }
+    }
+}
+
+pub fn handshake_request(identity: &WorkerIdentity) -> HandshakeRequest {
+    HandshakeRequest {
+        protocol_version: PROTOCOL_VERSION,
+        features: SUPPORTED_FEATURES.iter().map(|feature| i32::from(*feature)).collect(),
+        worker_id: identity.id.clone(),
+    }
+}
+
+/// Runs jobs coming from `inbound` until the server shuts the worker down or
+/// goes away, whatever transport carries the messages.
+pub async fn job_loop<S>(
+    identity: &WorkerIdentity,
+    sandbox: &Arc<dyn Sandbox>,
+    supported_languages: &Arc<HashMap<String, language::LanguageParams>>,
+    worker_message_sender: Sender<WorkerMessage>,
+    mut inbound: S,
+) -> Result<JobLoopEnd, Status>
+where
+    S: Stream<Item = Result<ServerMessage, Status>> + Unpin,
+{
+    if worker_message_sender.send(WorkerMessage {
+        which: Some(worker_message::Which::Register(RegisterWorker {
+            worker_id: identity.id.clone(),
+            host: identity.host.clone(),
+            box_count: identity.box_count,
+            supported_languages: get_languages(supported_languages),
+        })),
+    }).await.is_err() {
+        return Ok(JobLoopEnd::Disconnected);
+    }
+
+    {
+        let heartbeat_sender = worker_message_sender.clone();
+        tokio::spawn(async move {
+            let mut heartbeat = interval(Duration::from_secs(5));
+            loop {
+                heartbeat.tick().await;
+                if heartbeat_sender.send(WorkerMessage {
+                    which: Some(worker_message::Which::Heartbeat(Heartbeat {})),
+                }).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    loop {
+        log::info!("Waiting for job");
+        if worker_message_sender.send(get_job_message(supported_languages)).await.is_err() {
+            return Ok(JobLoopEnd::Disconnected);
+        }
+
+        let job = loop {
+            match inbound.next().await.transpose()? {
+                Some(ServerMessage { which: Some(server_message::Which::Job(job)) }) => break job,
+                Some(ServerMessage { which: Some(server_message::Which::Shutdown(_)) }) => {
+                    return Ok(JobLoopEnd::Shutdown);
+                },
+                Some(_) => {},
+                None => return Ok(JobLoopEnd::Disconnected),
+            }
+        };
+        log::info!("Got job uuid={}", job.uuid);
+
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let reporter = JobReporter::new(job.uuid.clone(), worker_message_sender.clone(), cancelled.clone());
+        let uuid = job.uuid.clone();
+        let mut running = {
+            let sandbox = sandbox.clone();
+            let supported_languages = supported_languages.clone();
+            tokio::task::spawn_blocking(move || run_job(
+                sandbox.as_ref(),
+                &supported_languages,
+                job,
+                &reporter,
+            ))
+        };
+
+        let job_result = loop {
+            tokio::select! {
+                job_result = &mut running => break job_result.expect("Job runner panicked"),
+                message = inbound.next() => match message.transpose()? {
+                    Some(ServerMessage { which: Some(server_message::Which::Cancel(cancel)) }) => {
+                        if cancel.uuid == uuid {
+                            log::info!("Cancelling job uuid={}", uuid);
+                            cancelled.store(true, Ordering::Relaxed);
+                        }
+                    },
+                    Some(_) => {},
+                    None => return Ok(JobLoopEnd::Disconnected),
+                },
+            }
+        };
+
+        if let Some(job_result) = job_result {
+            if worker_message_sender.send(WorkerMessage {
+                which: Some(worker_message::Which::Result(job_result)),
+            }).await.is_err() {
+                return Ok(JobLoopEnd::Disconnected);
+            }
+        }
+    }
+}
+
+/// Judges inside the jughisto process, talking to the queue over channels
+/// instead of gRPC, for setups without a separate alvokanto.
+pub async fn run_local(job_queuer: JobQueuer, box_id: i32) {
+    let sandbox = new_sandbox(box_id);
+
+    let supported_languages = load_supported_languages();
+
+    let identity = WorkerIdentity {
+        id: format!("local-{}", box_id),
+        host: get_host(),
+        box_count: 1,
+    };
+
+    loop {
+        job_queuer
+            .handshake(Request::new(handshake_request(&identity)))
+            .await
+            .expect("Local worker speaks the same protocol");
+
+        let (worker_message_sender, worker_message_receiver) = async_channel::unbounded();
+        let inbound = job_queuer.serve_worker(worker_message_receiver.map(Ok::<_, Status>));
+        match job_loop(
+            &identity,
+            &sandbox,
+            &supported_languages,
+            worker_message_sender,
+            inbound,
+        ).await {
+            Err(e) => log::error!("On local job loop {}, restarting in 3 seconds", e),
+            Ok(JobLoopEnd::Shutdown) => {
+                log::info!("Local worker drained, shutting down");
+                return;
+            },
+            Ok(JobLoopEnd::Disconnected) => {},
+        }
+        sleep(Duration::from_millis(3000)).await;
+    }
+}
@@ -1,26 +1,10 @@
+use super::sandbox::{CommandError, CommandTuple, CompileParams, ExecuteParams, Mount, RunStats, RunStatus, Sandbox};
 use log::info;
-use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::str;
-use thiserror::Error;
-
-#[derive(Debug, Clone)]
-pub struct CommandTuple {
-    pub binary_path: PathBuf,
-    pub args: Vec<String>,
-}
-
-#[derive(Error, Debug)]
-pub enum CommandError {
-    #[error("couldn't get command output")]
-    CommandIo(#[source] io::Error),
-    #[error("{0}")]
-    IsolateCommandFailed(String),
-    #[error(transparent)]
-    Utf8(#[from] str::Utf8Error),
-}
 
+#[derive(Clone)]
 pub struct IsolateBox {
     pub id: i32,
     pub path: PathBuf,
@@ -67,39 +51,11 @@ pub struct RunParams<'a> {
     pub restricted: bool,
     pub process_limit: i32,
     pub command: &'a CommandTuple,
+    pub mounts: &'a [Mount],
 }
 
 const WALL_TIME: i32 = 50_000;
 
-#[derive(Debug, PartialEq)]
-pub enum RunStatus {
-    Ok,
-    TimeLimitExceeded,
-    MemoryLimitExceeded,
-    RuntimeError,
-}
-
-#[derive(Debug)]
-pub struct RunStats {
-    pub time_ms: Option<i32>,
-    pub time_wall_ms: Option<i32>,
-    pub memory_kib: Option<i32>,
-    pub exit_code: Option<i32>,
-    pub message: Option<String>,
-    pub exit_signal: Option<i32>,
-    pub status: RunStatus,
-    pub stdout_path: PathBuf,
-    pub stderr_path: PathBuf,
-}
-
-pub struct ExecuteParams<'a> {
-    pub uuid: &'a str,
-    pub memory_limit_kib: i32,
-    pub time_limit_ms: i32,
-    pub stdin_path: Option<String>,
-    pub process_limit: i32,
-}
-
 use std::str::FromStr;
 
 pub fn execute(
@@ -119,6 +75,7 @@ pub fn execute(
             memory_limit_kib: execute_params.memory_limit_kib,
             time_limit_ms: execute_params.time_limit_ms,
             command,
+            mounts: &[],
         },
     )
 }
@@ -129,7 +86,7 @@ pub fn run(
     run_params: RunParams,
 ) -> Result<RunStats, CommandError> {
     let in_data_dir = PathBuf::from(format!("/data-{}/", run_params.uuid));
-    let out_data_dir = PathBuf::from("../data").canonicalize().unwrap();
+    let out_data_dir = PathBuf::from("/data/");
     let stdin_path = run_params
         .stdin_path
         .map(|stdin_path| in_data_dir.join(stdin_path));
@@ -191,6 +148,13 @@ pub fn run(
                 "--dir=proc=proc:fs",
             ]
         })
+        .args(run_params.mounts.iter().map(|mount| {
+            format!(
+                "--dir={}={}",
+                mount.inside.to_str().unwrap().trim_start_matches('/'),
+                mount.outside.to_str().unwrap()
+            )
+        }))
         .arg(format!("--processes={}", run_params.process_limit))
         .arg(format!(
             "--dir={}={}", in_data_dir.to_str().unwrap(), out_data_dir.to_str().unwrap()
@@ -278,13 +242,6 @@ pub fn run(
     Ok(stats)
 }
 
-pub struct CompileParams<'a> {
-    pub uuid: &'a str,
-    pub memory_limit_kib: i32,
-    pub time_limit_ms: i32,
-    pub command: &'a CommandTuple,
-}
-
 pub fn compile(
     isolate_executable_path: &PathBuf,
     isolate_box: &IsolateBox,
@@ -301,6 +258,7 @@ pub fn compile(
             memory_limit_kib: compile_params.memory_limit_kib,
             time_limit_ms: compile_params.time_limit_ms,
             command: compile_params.command,
+            mounts: compile_params.mounts,
         },
     )
 }
@@ -315,3 +273,30 @@ pub fn cleanup_box(isolate_executable_path: &PathBuf, box_id: i32) -> Result<boo
         .status
         .success())
 }
+
+pub struct IsolateSandbox {
+    pub isolate_executable_path: PathBuf,
+    pub isolate_box: IsolateBox,
+}
+
+impl Sandbox for IsolateSandbox {
+    fn path(&self) -> &Path {
+        &self.isolate_box.path
+    }
+
+    fn data_path(&self) -> &Path {
+        Path::new("/data/")
+    }
+
+    fn compile(&self, compile_params: CompileParams) -> Result<RunStats, CommandError> {
+        compile(&self.isolate_executable_path, &self.isolate_box, compile_params)
+    }
+
+    fn execute(
+        &self,
+        command: &CommandTuple,
+        execute_params: &ExecuteParams,
+    ) -> Result<RunStats, CommandError> {
+        execute(&self.isolate_executable_path, &self.isolate_box, command, execute_params)
+    }
+}
@@ -1,10 +1,14 @@
-use crate::isolate::CommandTuple;
+use crate::worker::sandbox::{CommandTuple, Mount};
+use log::{info, warn};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::Instant;
 
 fn no_transform(source_text: String, _source_name: String) -> String {
-    return source_text;
+    source_text
 }
 
 #[derive(Clone)]
@@ -25,8 +29,24 @@ pub struct LanguageParams {
     pub compile: Compile,
     pub run: CommandTuple,
     pub process_limit: i32,
+    pub precompiled_header: Option<PrecompiledHeader>,
+    pub compile_mounts: Vec<Mount>,
+    /// Builds several files at once, `{sources}` being every file in the box
+    /// with the language's suffix and `{}` the output.
+    pub build: Option<CommandTuple>,
 }
 
+/// A system header compiled ahead of time with the same flags as the
+/// submissions, `{.}` being the header source and `{}` the output.
+#[derive(Clone)]
+pub struct PrecompiledHeader {
+    pub header: String,
+    pub command: CommandTuple,
+}
+
+// Where the precompiled headers show up inside the compile sandbox
+const PRECOMPILED_HEADER_DIR: &str = "/pch";
+
 use lazy_static::lazy_static;
 use regex::Captures;
 use regex::Regex;
@@ -50,6 +70,45 @@ pub fn get_supported_languages() -> HashMap<String, LanguageParams> {
         }
         let version = VERSION_REGEX.find(stdout).unwrap().as_str();
 
+        let precompiled_header = if x == "c++" {
+            Some(PrecompiledHeader {
+                header: "bits/stdc++.h".into(),
+                command: CommandTuple {
+                    binary_path: binary_path.clone(),
+                    args: vec![
+                        // Everything that changes the generated code must match the compilation
+                        "-DONLINE_JUDGE".into(),
+                        format!("-std={}", std),
+                        "-x".into(),
+                        format!("{}-header", x),
+                        "-O2".into(),
+                        "-o".into(),
+                        "{}".into(),
+                        "{.}".into(),
+                    ],
+                },
+            })
+        } else {
+            None
+        };
+
+        let build = CommandTuple {
+            binary_path: binary_path.clone(),
+            args: vec![
+                "-static".into(),
+                "-DONLINE_JUDGE".into(),
+                "-lm".into(),
+                "-s".into(),
+                format!("-std={}", std),
+                "-x".into(),
+                x.into(),
+                "-O2".into(),
+                "-o".into(),
+                "{}".into(),
+                "{sources}".into(),
+            ],
+        };
+
         LanguageParams {
             order,
             suffix: ".cpp".into(),
@@ -84,6 +143,9 @@ pub fn get_supported_languages() -> HashMap<String, LanguageParams> {
                 args: vec![]
             },
             process_limit: 1,
+            precompiled_header,
+            compile_mounts: vec![],
+            build: Some(build),
         }
     }
 
@@ -130,6 +192,9 @@ pub fn get_supported_languages() -> HashMap<String, LanguageParams> {
                 args: vec![]
             },
             process_limit: 1,
+            precompiled_header: None,
+            compile_mounts: vec![],
+            build: None,
         },
     );
     languages.insert(
@@ -176,6 +241,9 @@ pub fn get_supported_languages() -> HashMap<String, LanguageParams> {
                 ],
             },
             process_limit: 19,
+            precompiled_header: None,
+            compile_mounts: vec![],
+            build: None,
         },
     );
     languages.insert(
@@ -190,7 +258,76 @@ pub fn get_supported_languages() -> HashMap<String, LanguageParams> {
                 args: vec!["{.}".into()],
             },
             process_limit: 1,
+            precompiled_header: None,
+            compile_mounts: vec![],
+            build: None,
         },
     );
     languages
 }
+
+fn build_precompiled_header(
+    precompiled_header: &PrecompiledHeader,
+    cache_path: &Path,
+) -> io::Result<()> {
+    let output_path = cache_path.join(format!("{}.gch", precompiled_header.header));
+    if output_path.exists() {
+        return Ok(());
+    }
+    fs::create_dir_all(output_path.parent().unwrap())?;
+
+    // GCC picks the .gch up by the name of the included header, whatever its source was
+    let source_path = cache_path.join("source.h");
+    fs::write(&source_path, format!("#include <{}>\n", precompiled_header.header))?;
+
+    let start = Instant::now();
+    let output = Command::new(&precompiled_header.command.binary_path)
+        .args(precompiled_header.command.args.iter().map(|arg| {
+            arg.replace("{.}", source_path.to_str().unwrap())
+                .replace("{}", output_path.to_str().unwrap())
+        }))
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(String::from_utf8_lossy(&output.stderr).into_owned()));
+    }
+    info!(
+        "Precompiled {} in {} ms",
+        precompiled_header.header,
+        start.elapsed().as_millis()
+    );
+    Ok(())
+}
+
+/// Builds the precompiled headers under `cache_path` once, and makes the
+/// languages that have one mount it read-only while compiling.
+pub fn prepare_precompiled_headers(
+    languages: &mut HashMap<String, LanguageParams>,
+    cache_path: &Path,
+) {
+    for (key, language) in languages.iter_mut() {
+        let precompiled_header = match &language.precompiled_header {
+            Some(precompiled_header) => precompiled_header,
+            None => continue,
+        };
+        // Compiler upgrades change the name, so stale headers are never used
+        let language_cache_path = cache_path.join(format!("{}-{}", key, language.name.replace(' ', "_")));
+        match build_precompiled_header(precompiled_header, &language_cache_path) {
+            Ok(()) => {
+                let build = language.build.as_mut();
+                let compile = match &mut language.compile {
+                    Compile::Command(_, command, _) => Some(command),
+                    Compile::NoCompile => None,
+                };
+                for command in compile.into_iter().chain(build) {
+                    command.args.insert(0, "-I".into());
+                    command.args.insert(1, PRECOMPILED_HEADER_DIR.into());
+                }
+                language.compile_mounts.push(Mount {
+                    inside: PRECOMPILED_HEADER_DIR.into(),
+                    outside: language_cache_path,
+                });
+            }
+            Err(e) => warn!("Couldn't precompile headers for {}, compiling without them: {}", key, e),
+        }
+    }
+}
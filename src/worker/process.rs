@@ -0,0 +1,301 @@
+use super::sandbox::{CommandError, CommandTuple, CompileParams, ExecuteParams, Mount, RunStats, RunStatus, Sandbox};
+use log::info;
+use std::fs::{self, File};
+use std::io;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often the memory and wall time of a running program are checked
+const WATCH_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Runs programs as plain child processes of the worker, limited by rlimits
+/// and a thread watching their memory and wall time. It doesn't isolate
+/// anything, so only use it for tests and trusted code on machines without
+/// isolate. The process limit is an rlimit on the user's processes, which
+/// root isn't held to.
+pub struct ProcessSandbox {
+    pub path: PathBuf,
+    pub data_path: PathBuf,
+}
+
+pub fn new_process_sandbox(path: PathBuf, data_path: PathBuf) -> io::Result<ProcessSandbox> {
+    fs::create_dir_all(&path)?;
+    Ok(ProcessSandbox {
+        path: path.canonicalize()?,
+        data_path: data_path.canonicalize()?,
+    })
+}
+
+struct RunParams<'a> {
+    memory_limit_kib: i32,
+    time_limit_ms: i32,
+    process_limit: Option<i32>,
+    stdin_path: Option<String>,
+    uuid: &'a str,
+    command: &'a CommandTuple,
+    mounts: &'a [Mount],
+}
+
+/// Twice the time limit and a second more, so a program blocked on input or
+/// sleeping is stopped soon after its time is up.
+fn wall_time_limit_ms(time_limit_ms: i32) -> i32 {
+    time_limit_ms * 2 + 1_000
+}
+
+/// Resident memory of a process, in KiB, as long as it's running.
+fn resident_memory_kib(pid: libc::pid_t) -> Option<i32> {
+    fs::read_to_string(format!("/proc/{}/status", pid))
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Processes and threads the user already has, which RLIMIT_NPROC counts
+/// along with the program's.
+fn user_task_count() -> libc::rlim_t {
+    let uid = unsafe { libc::getuid() }.to_string();
+    let owned_by_user = |pid: &str| {
+        fs::read_to_string(format!("/proc/{}/status", pid)).is_ok_and(|status| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix("Uid:"))
+                .and_then(|uids| uids.split_whitespace().next())
+                == Some(uid.as_str())
+        })
+    };
+    fs::read_dir("/proc")
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                .filter(|pid| pid.bytes().all(|c| c.is_ascii_digit()) && owned_by_user(pid))
+                .map(|pid| fs::read_dir(format!("/proc/{}/task", pid)).map_or(1, |tasks| tasks.count()))
+                .sum::<usize>() as libc::rlim_t
+        })
+        .unwrap_or(0)
+}
+
+impl ProcessSandbox {
+    /// Translates paths as seen from inside isolate into real ones.
+    fn outside_path(&self, uuid: &str, mounts: &[Mount], path: &str) -> String {
+        let data_prefix = format!("/data-{}/", uuid);
+        for mount in mounts {
+            if let Ok(rest) = PathBuf::from(path).strip_prefix(&mount.inside) {
+                return mount.outside.join(rest).to_str().unwrap().into();
+            }
+        }
+        if let Some(rest) = path.strip_prefix(&data_prefix) {
+            self.data_path.join(rest).to_str().unwrap().into()
+        } else if let Some(rest) = path.strip_prefix("/box/") {
+            self.path.join(rest).to_str().unwrap().into()
+        } else {
+            path.into()
+        }
+    }
+
+    fn run(&self, run_params: RunParams) -> Result<RunStats, CommandError> {
+        let stdout_path = self.path.join("stdout");
+        let stderr_path = self.path.join("stderr");
+
+        let binary_path = run_params.command.binary_path.to_str().unwrap();
+        // isolate runs relative binaries from the box, as in `./x`
+        let binary_path = if !binary_path.contains('/') && self.path.join(binary_path).exists() {
+            self.path.join(binary_path)
+        } else {
+            PathBuf::from(self.outside_path(run_params.uuid, run_params.mounts, binary_path))
+        };
+
+        let stdin = match &run_params.stdin_path {
+            Some(stdin_path) => {
+                let stdin_path = if stdin_path.starts_with('/') {
+                    PathBuf::from(self.outside_path(run_params.uuid, run_params.mounts, stdin_path))
+                } else {
+                    self.data_path.join(stdin_path)
+                };
+                info!("Using stdin path {:?}", stdin_path);
+                Stdio::from(File::open(stdin_path).map_err(CommandError::CommandIo)?)
+            }
+            None => Stdio::null(),
+        };
+
+        let mut command = Command::new(binary_path);
+        command
+            .args(
+                run_params
+                    .command
+                    .args
+                    .iter()
+                    .map(|arg| self.outside_path(run_params.uuid, run_params.mounts, arg)),
+            )
+            .current_dir(&self.path)
+            .env_clear()
+            .env("PATH", "/usr/bin:/bin")
+            .stdin(stdin)
+            .stdout(File::create(&stdout_path).map_err(CommandError::CommandIo)?)
+            .stderr(File::create(&stderr_path).map_err(CommandError::CommandIo)?);
+
+        // Whole seconds, rounded up, the exact limit is checked after the run
+        let cpu_seconds = (run_params.time_limit_ms as libc::rlim_t).div_ceil(1000);
+        let memory_bytes = run_params.memory_limit_kib as libc::rlim_t * 1_024;
+        let process_count = run_params
+            .process_limit
+            .map(|process_limit| user_task_count() + process_limit as libc::rlim_t);
+        unsafe {
+            command.pre_exec(move || {
+                // Its own process group, so whatever it forks is killed with it
+                if libc::setpgid(0, 0) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                let set = |resource, soft, hard| {
+                    let limit = libc::rlimit {
+                        rlim_cur: soft,
+                        rlim_max: hard,
+                    };
+                    if libc::setrlimit(resource, &limit) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    Ok(())
+                };
+                set(libc::RLIMIT_CPU, cpu_seconds, cpu_seconds + 1)?;
+                // Memory is watched instead of limited by RLIMIT_AS, under
+                // which a big allocation fails and looks like a runtime error
+                set(libc::RLIMIT_STACK, memory_bytes, memory_bytes)?;
+                set(libc::RLIMIT_CORE, 0, 0)?;
+                if let Some(process_count) = process_count {
+                    set(libc::RLIMIT_NPROC, process_count, process_count)?;
+                }
+                Ok(())
+            });
+        }
+
+        let start = Instant::now();
+        let child = command.spawn().map_err(CommandError::CommandIo)?;
+        let pid = child.id() as libc::pid_t;
+
+        let finished = Arc::new(AtomicBool::new(false));
+        let wall_timed_out = Arc::new(AtomicBool::new(false));
+        let memory_exceeded = Arc::new(AtomicBool::new(false));
+        let watcher = {
+            let finished = finished.clone();
+            let wall_timed_out = wall_timed_out.clone();
+            let memory_exceeded = memory_exceeded.clone();
+            let memory_limit_kib = run_params.memory_limit_kib;
+            let deadline = start + Duration::from_millis(wall_time_limit_ms(run_params.time_limit_ms) as u64);
+            thread::spawn(move || {
+                let mut peak_memory_kib = 0;
+                while !finished.load(Ordering::Relaxed) {
+                    if let Some(memory_kib) = resident_memory_kib(pid) {
+                        peak_memory_kib = peak_memory_kib.max(memory_kib);
+                    }
+                    if peak_memory_kib > memory_limit_kib {
+                        memory_exceeded.store(true, Ordering::Relaxed);
+                    } else if Instant::now() > deadline {
+                        wall_timed_out.store(true, Ordering::Relaxed);
+                    } else {
+                        thread::sleep(WATCH_INTERVAL);
+                        continue;
+                    }
+                    unsafe { libc::kill(-pid, libc::SIGKILL) };
+                    break;
+                }
+                peak_memory_kib
+            })
+        };
+
+        // Reaped by hand to get the child's resource usage
+        let mut status = 0;
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        if unsafe { libc::wait4(pid, &mut status, 0, &mut usage) } < 0 {
+            return Err(CommandError::CommandIo(io::Error::last_os_error()));
+        }
+        let time_wall_ms = start.elapsed().as_millis() as i32;
+        finished.store(true, Ordering::Relaxed);
+        let watched_memory_kib = watcher.join().unwrap_or(0);
+        // Whatever it left behind goes too
+        unsafe { libc::kill(-pid, libc::SIGKILL) };
+        let status = ExitStatus::from_raw(status);
+
+        let time_ms = (usage.ru_utime.tv_sec + usage.ru_stime.tv_sec) as i32 * 1000
+            + (usage.ru_utime.tv_usec + usage.ru_stime.tv_usec) as i32 / 1000;
+        // ru_maxrss is already in KiB on Linux
+        let memory_kib = (usage.ru_maxrss as i32).max(watched_memory_kib);
+
+        let exit_signal = status.signal();
+        let mut stats = RunStats {
+            time_ms: Some(time_ms),
+            time_wall_ms: Some(time_wall_ms),
+            memory_kib: Some(memory_kib),
+            exit_code: status.code(),
+            message: None,
+            exit_signal,
+            status: RunStatus::Ok,
+            stdout_path,
+            stderr_path,
+        };
+
+        if time_ms > run_params.time_limit_ms
+            || exit_signal == Some(libc::SIGXCPU)
+            || wall_timed_out.load(Ordering::Relaxed)
+        {
+            stats.status = RunStatus::TimeLimitExceeded;
+            stats.message = Some("Time limit exceeded".into());
+        } else if memory_exceeded.load(Ordering::Relaxed) || memory_kib >= run_params.memory_limit_kib {
+            stats.status = RunStatus::MemoryLimitExceeded;
+        } else if let Some(signal) = exit_signal {
+            stats.status = RunStatus::RuntimeError;
+            stats.message = Some(format!("Caught fatal signal {}", signal));
+        } else if status.code() != Some(0) {
+            stats.status = RunStatus::RuntimeError;
+            stats.message = Some("Exited with error status".into());
+        }
+
+        Ok(stats)
+    }
+}
+
+impl Sandbox for ProcessSandbox {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn data_path(&self) -> &Path {
+        &self.data_path
+    }
+
+    fn compile(&self, compile_params: CompileParams) -> Result<RunStats, CommandError> {
+        self.run(RunParams {
+            uuid: compile_params.uuid,
+            stdin_path: None,
+            process_limit: None,
+            memory_limit_kib: compile_params.memory_limit_kib,
+            time_limit_ms: compile_params.time_limit_ms,
+            command: compile_params.command,
+            mounts: compile_params.mounts,
+        })
+    }
+
+    fn execute(
+        &self,
+        command: &CommandTuple,
+        execute_params: &ExecuteParams,
+    ) -> Result<RunStats, CommandError> {
+        self.run(RunParams {
+            uuid: execute_params.uuid,
+            stdin_path: execute_params.stdin_path.clone(),
+            process_limit: Some(execute_params.process_limit),
+            memory_limit_kib: execute_params.memory_limit_kib,
+            time_limit_ms: execute_params.time_limit_ms,
+            command,
+            mounts: &[],
+        })
+    }
+}
@@ -0,0 +1,81 @@
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str;
+use thiserror::Error;
+
+#[derive(Debug, Clone)]
+pub struct CommandTuple {
+    pub binary_path: PathBuf,
+    pub args: Vec<String>,
+}
+
+#[derive(Error, Debug)]
+pub enum CommandError {
+    #[error("couldn't get command output")]
+    CommandIo(#[source] io::Error),
+    #[error("{0}")]
+    IsolateCommandFailed(String),
+    #[error(transparent)]
+    Utf8(#[from] str::Utf8Error),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RunStatus {
+    Ok,
+    TimeLimitExceeded,
+    MemoryLimitExceeded,
+    RuntimeError,
+}
+
+#[derive(Debug)]
+pub struct RunStats {
+    pub time_ms: Option<i32>,
+    pub time_wall_ms: Option<i32>,
+    pub memory_kib: Option<i32>,
+    pub exit_code: Option<i32>,
+    pub message: Option<String>,
+    pub exit_signal: Option<i32>,
+    pub status: RunStatus,
+    pub stdout_path: PathBuf,
+    pub stderr_path: PathBuf,
+}
+
+pub struct ExecuteParams<'a> {
+    pub uuid: &'a str,
+    pub memory_limit_kib: i32,
+    pub time_limit_ms: i32,
+    pub stdin_path: Option<String>,
+    pub process_limit: i32,
+}
+
+/// A directory from the worker made visible, read-only, inside the sandbox.
+#[derive(Debug, Clone)]
+pub struct Mount {
+    pub inside: PathBuf,
+    pub outside: PathBuf,
+}
+
+pub struct CompileParams<'a> {
+    pub uuid: &'a str,
+    pub memory_limit_kib: i32,
+    pub time_limit_ms: i32,
+    pub command: &'a CommandTuple,
+    pub mounts: &'a [Mount],
+}
+
+/// Where compilations and executions happen. Inside it, the working
+/// directory is mounted at `/box` and the problem data at `/data-{uuid}`.
+pub trait Sandbox: Send + Sync {
+    fn path(&self) -> &Path;
+
+    /// Where the problem data is outside the sandbox.
+    fn data_path(&self) -> &Path;
+
+    fn compile(&self, compile_params: CompileParams) -> Result<RunStats, CommandError>;
+
+    fn execute(
+        &self,
+        command: &CommandTuple,
+        execute_params: &ExecuteParams,
+    ) -> Result<RunStats, CommandError>;
+}
@@ -0,0 +1,126 @@
+use jughisto::job_protocol::{job, job_result, Job, JobResult};
+use jughisto::worker::{language, process, run_job, JobReporter};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use job_result::judgement::Verdict;
+
+const CORPUS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/verdict-tests");
+
+/// A fresh directory under the system's temporary one.
+fn temp_dir(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("jughisto-verdicts-{}-{}", std::process::id(), name));
+    fs::remove_dir_all(&path).ok();
+    fs::create_dir_all(&path).unwrap();
+    path
+}
+
+/// An A+B problem with two tests and a testlib-style checker from the corpus.
+fn write_problem(data_path: &Path, checker: &str) {
+    fs::create_dir_all(data_path.join("tests")).unwrap();
+    for (i, (input, answer)) in [("1 2\n", "3\n"), ("-5 20\n", "15\n")].iter().enumerate() {
+        fs::write(data_path.join(format!("tests/{:02}", i + 1)), input).unwrap();
+        fs::write(data_path.join(format!("tests/{:02}.a", i + 1)), answer).unwrap();
+    }
+    fs::create_dir_all(data_path.join("checker")).unwrap();
+    fs::copy(Path::new(CORPUS).join(checker), data_path.join("checker/checker.cpp")).unwrap();
+}
+
+/// Runs a solution and a checker from the corpus with the process sandbox.
+fn run(source: &str, checker: &str, time_limit_ms: i32, memory_limit_kib: i32) -> JobResult {
+    let name = format!("{}-{}", source.trim_end_matches(".cpp"), checker.trim_end_matches(".cpp"));
+    let data_path = temp_dir(&format!("{}-data", name));
+    write_problem(&data_path, checker);
+    let box_path = temp_dir(&format!("{}-box", name));
+    let sandbox = process::new_process_sandbox(box_path.clone(), data_path.clone()).unwrap();
+    let supported_languages = language::get_supported_languages();
+
+    let (sender, _receiver) = async_channel::unbounded();
+    let reporter = JobReporter::new(name.clone(), sender, Arc::new(AtomicBool::new(false)));
+    let result = run_job(
+        &sandbox,
+        &supported_languages,
+        Job {
+            uuid: name,
+            language: "cpp.17.g++".into(),
+            memory_limit_kib,
+            time_limit_ms,
+            required_features: vec![],
+            which: Some(job::Which::Judgement(job::Judgement {
+                source_text: fs::read_to_string(Path::new(CORPUS).join(source)).unwrap(),
+                test_count: 2,
+                test_pattern: "tests/%02d".into(),
+                checker_language: "cpp.17.g++".into(),
+                checker_source_path: "checker/checker.cpp".into(),
+                ..Default::default()
+            })),
+        },
+        &reporter,
+    )
+    .unwrap();
+
+    fs::remove_dir_all(data_path).ok();
+    fs::remove_dir_all(box_path).ok();
+    result
+}
+
+/// Judges a solution from the corpus with the testlib-style checker.
+fn judge(source: &str, time_limit_ms: i32, memory_limit_kib: i32) -> job_result::Judgement {
+    let result = run(source, "checker.cpp", time_limit_ms, memory_limit_kib);
+    assert_eq!(result.code(), job_result::Code::Ok);
+    match result.which {
+        Some(job_result::Which::Judgement(judgement)) => judgement,
+        _ => panic!("Expected a judgement"),
+    }
+}
+
+#[test]
+fn accepted() {
+    let judgement = judge("ac.cpp", 1_000, 256 * 1_024);
+    assert_eq!(judgement.verdict(), Verdict::Accepted);
+    assert_eq!(judgement.failed_test, 0);
+}
+
+#[test]
+fn wrong_answer() {
+    let judgement = judge("wa.cpp", 1_000, 256 * 1_024);
+    assert_eq!(judgement.verdict(), Verdict::WrongAnswer);
+    assert_eq!(judgement.failed_test, 1);
+}
+
+#[test]
+fn time_limit_exceeded() {
+    let judgement = judge("tle.cpp", 1_000, 256 * 1_024);
+    assert_eq!(judgement.verdict(), Verdict::TimeLimitExceeded);
+    assert_eq!(judgement.failed_test, 1);
+}
+
+#[test]
+fn memory_limit_exceeded_writing() {
+    let judgement = judge("mle.cpp", 5_000, 256 * 1_024);
+    assert_eq!(judgement.verdict(), Verdict::MemoryLimitExceeded);
+    assert_eq!(judgement.failed_test, 1);
+}
+
+#[test]
+fn memory_limit_exceeded_allocating() {
+    let judgement = judge("mle2.cpp", 5_000, 256 * 1_024);
+    assert_eq!(judgement.verdict(), Verdict::MemoryLimitExceeded);
+    assert_eq!(judgement.failed_test, 1);
+}
+
+#[test]
+fn runtime_error() {
+    let judgement = judge("re.cpp", 1_000, 256 * 1_024);
+    assert_eq!(judgement.verdict(), Verdict::RuntimeError);
+    assert_eq!(judgement.exit_code, 1);
+    assert_eq!(judgement.failed_test, 1);
+}
+
+#[test]
+fn checker_failure() {
+    let result = run("ac.cpp", "failing_checker.cpp", 1_000, 256 * 1_024);
+    assert_eq!(result.code(), job_result::Code::FailedToExecute);
+}
@@ -0,0 +1,12 @@
+#include <bits/stdc++.h>
+using namespace std;
+
+// Testlib's protocol: input, output and answer, and exit 0 to accept
+int main(int argc, char* argv[]) {
+    ifstream output(argv[2]), answer(argv[3]);
+    long long got, expected;
+    if (!(output >> got) || !(answer >> expected) || got != expected) {
+        cerr << "wrong answer\n";
+        return 1;
+    }
+}
@@ -0,0 +1,8 @@
+#include <bits/stdc++.h>
+using namespace std;
+
+// Testlib's "fail" exit code, the checker itself is broken
+int main() {
+    cerr << "answer file is malformed\n";
+    return 3;
+}
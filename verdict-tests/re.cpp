@@ -0,0 +1,7 @@
+#include <bits/stdc++.h>
+using namespace std;
+
+int main() {
+    vector<int> v;
+    return v.size() + 1;
+}
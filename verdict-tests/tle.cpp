@@ -0,0 +1,9 @@
+#include <bits/stdc++.h>
+using namespace std;
+
+int main() {
+    volatile unsigned long long i = 0;
+    while (true) {
+        i++;
+    }
+}